@@ -0,0 +1,58 @@
+// Time of the static passes that run before a program: resolving and type checking.
+// Run with `cargo bench --bench check`. The programs are parsed up front.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lako_interpreted::frontend::parser::Parser;
+use lako_interpreted::frontend::resolver::Resolver;
+use lako_interpreted::frontend::scanner::Scanner;
+use lako_interpreted::frontend::stmt_ast::Stmt;
+use lako_interpreted::frontend::typecheck::TypeChecker;
+use std::hint::black_box;
+
+// Small programs of the kinds the interpreter will be benchmarked with, repeated
+// so the passes have something to chew on
+const PROGRAMS: &[(&str, &str)] = &[
+    (
+        "fib",
+        "fn fib(n: Number): Number { if (n <= 1) return n; return fib(n - 2) + fib(n - 1); }\n\
+         print fib(25);\n",
+    ),
+    (
+        "loop",
+        "var total: Number = 0;\n\
+         for (var i = 0; i < 1000000; i++) { if (i % 3 == 0) total += i; else total -= 1; }\n\
+         print total;\n",
+    ),
+    (
+        "strings",
+        "var s: String = \"\";\n\
+         for (var i = 0; i < 1000; i++) { s = s + \"ab\"; { var t = s[0:2] + \"c\"; print t; } }\n\
+         print s;\n",
+    ),
+];
+
+fn parse(source: &str) -> Vec<Stmt> {
+    let tokens = Scanner::new(source.to_string())
+        .scan_tokens()
+        .expect("Benchmark program should scan.");
+    Parser::new(tokens)
+        .parse()
+        .expect("Benchmark program should parse.")
+}
+
+fn check(c: &mut Criterion) {
+    let mut group = c.benchmark_group("check");
+    for (name, program) in PROGRAMS {
+        let stmts = parse(&program.repeat(500));
+        group.bench_with_input(BenchmarkId::new("resolve", name), &stmts, |b, stmts| {
+            b.iter(|| Resolver::new().resolve(black_box(stmts)))
+        });
+        group.bench_with_input(BenchmarkId::new("typecheck", name), &stmts, |b, stmts| {
+            b.iter(|| TypeChecker::new().check(black_box(stmts)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, check);
+criterion_main!(benches);
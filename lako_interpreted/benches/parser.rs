@@ -0,0 +1,86 @@
+// Parsing time on generated scripts and on deeply nested expressions, and the time
+// to free a parsed script. Run with `cargo bench --bench parser`. Only the parser is
+// timed, the tokens are scanned up front.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use lako_interpreted::frontend::parser::Parser;
+use lako_interpreted::frontend::scanner::Scanner;
+use lako_interpreted::frontend::token::Token;
+use std::hint::black_box;
+
+// A script of roughly `size` bytes using most statements and expressions
+fn generate_source(size: usize) -> String {
+    let chunk = r#"class Point {
+    init(x, y) { this.x = x; this.y = y; }
+    add(other) { return Point(this.x + other.x, this.y + other.y); }
+}
+fn fib(n: Number): Number {
+    if (n <= 1) return n;
+    return fib(n - 2) + fib(n - 1);
+}
+var xs = [1, 2, 3];
+var m = {"a": xs[0], "b": xs[1:]};
+for (x in xs) { print x > 1 ? fib(x) : -x, m["a"]; }
+"#;
+    chunk.repeat(size / chunk.len() + 1)
+}
+
+// 1 + (1 + (1 + ... )) nested `depth` times
+fn nested_source(depth: usize) -> String {
+    format!("print {}1{};", "1 + (".repeat(depth), ")".repeat(depth))
+}
+
+fn tokens(source: &str) -> Vec<Token> {
+    Scanner::new(source.to_string())
+        .scan_tokens()
+        .expect("Generated source should scan.")
+}
+
+fn parse(tokens: &[Token]) {
+    Parser::new(black_box(tokens.to_vec()))
+        .parse()
+        .expect("Generated source should parse.");
+}
+
+fn parse_scripts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+    for kb in [256, 512, 1024] {
+        let source = generate_source(kb * 1024);
+        let tokens = tokens(&source);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{}KB", kb)),
+            &tokens,
+            |b, tokens| b.iter(|| parse(tokens)),
+        );
+    }
+    group.finish();
+}
+
+fn parse_nested(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_nested");
+    for depth in [16, 64, 250] {
+        let tokens = tokens(&nested_source(depth));
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &tokens, |b, tokens| {
+            b.iter(|| parse(tokens))
+        });
+    }
+    group.finish();
+}
+
+// Every node is boxed, so freeing the tree is one free per node
+fn drop_tree(c: &mut Criterion) {
+    let stmts = Parser::new(tokens(&generate_source(1024 * 1024)))
+        .parse()
+        .expect("Generated source should parse.");
+    let mut group = c.benchmark_group("drop_tree");
+    group.sample_size(10);
+    group.bench_function("1024KB", |b| {
+        b.iter_batched(|| stmts.clone(), drop, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse_scripts, parse_nested, drop_tree);
+criterion_main!(benches);
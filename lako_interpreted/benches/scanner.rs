@@ -0,0 +1,45 @@
+// Lexing throughput on generated scripts of growing size.
+// Run with `cargo bench --bench scanner`: time per byte should stay flat as the
+// input doubles, i.e. scanning is linear in the size of the source.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lako_interpreted::frontend::scanner::Scanner;
+use std::hint::black_box;
+
+// A script of roughly `size` bytes mixing every kind of token, including
+// multi-byte characters in strings and comments.
+fn generate_source(size: usize) -> String {
+    let chunk = r#"fn fib(n) {
+    if (n <= 1) return n; // base case ✓
+    return fib(n - 2) + fib(n - 1);
+}
+var greeting = "héllo wörld";
+for (var i = 0; i < 10; i = i + 1) { print fib(i) * 3.25 % 7; }
+"#;
+    chunk.repeat(size / chunk.len() + 1)
+}
+
+fn scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan_tokens");
+    group.sample_size(10);
+    for mb in [1, 2, 4] {
+        let source = generate_source(mb * 1024 * 1024);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{}MB", mb)),
+            &source,
+            |b, source| {
+                b.iter(|| {
+                    let mut scanner = Scanner::new(black_box(source.clone()));
+                    scanner
+                        .scan_tokens()
+                        .expect("Generated source should scan.")
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scan);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+// Parses whatever scans. Parsing must end with statements or errors.
+#![no_main]
+
+use lako_interpreted::frontend::parser::Parser;
+use lako_interpreted::frontend::scanner::Scanner;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data).into_owned();
+    if let Ok(tokens) = Scanner::new(source).scan_tokens() {
+        let _ = Parser::new(tokens).parse();
+    }
+});
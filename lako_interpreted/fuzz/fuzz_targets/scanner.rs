@@ -0,0 +1,15 @@
+// Scans arbitrary text, with and without trivia. Scanning must end with tokens or
+// errors, and with trivia the tokens must add up to the source.
+#![no_main]
+
+use lako_interpreted::frontend::scanner::Scanner;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data).into_owned();
+    let _ = Scanner::new(source.clone()).scan_tokens();
+    if let Ok(tokens) = Scanner::with_trivia(source.clone()).scan_tokens() {
+        let text: String = tokens.iter().map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(text, source);
+    }
+});
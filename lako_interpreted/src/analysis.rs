@@ -0,0 +1,432 @@
+// What editor tools need to know about one source file, worked out in one go by the
+// frontend passes: its diagnostics, the declaration every name resolves to, the type
+// of each variable and parameter, and an outline of its declarations. The lako-lsp
+// binary answers requests from it; the protocol itself stays out of here.
+
+use crate::config::Config;
+use crate::frontend::diagnostic::Diagnostic;
+use crate::frontend::expr_ast::Expr;
+use crate::frontend::formatter::Formatter;
+use crate::frontend::reporter::{MemoryReporter, Reporter};
+use crate::frontend::resolver::{Reference, Resolver};
+use crate::frontend::scanner::Scanner;
+use crate::frontend::stmt_ast::{Param, Stmt};
+use crate::frontend::token::{Span, Token};
+use crate::frontend::typecheck::{TypeChecker, Types};
+use crate::parse_with;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Variable,
+    Function,
+    Method,
+    Field,
+    Class,
+}
+
+// A declaration in the outline of a file. Classes hold their fields and methods, and
+// functions the functions and classes declared inside them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // the whole declaration, and just its name
+    pub span: Span,
+    pub name_span: Span,
+    // how it is declared, e.g. `fn add(a: Number, b)`
+    pub detail: String,
+    pub children: Vec<Symbol>,
+}
+
+// Markdown to show for the name under the cursor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hover {
+    pub span: Span,
+    pub text: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Analysis {
+    source: String,
+    // errors and warnings at the configured lint levels, in source order
+    pub diagnostics: Vec<Diagnostic>,
+    references: Vec<Reference>,
+    types: Types,
+    symbols: Vec<Symbol>,
+}
+
+impl Analysis {
+    // Runs the scanner, parser, resolver and type checker over the source. When it
+    // doesn't parse there are only the errors to go by.
+    pub fn new(source: &str, config: &Config) -> Analysis {
+        let mut reporter = MemoryReporter::new();
+        let mut analysis = Analysis {
+            source: source.to_string(),
+            ..Analysis::default()
+        };
+        if let Some(stmts) = parse_with(source, config, &mut reporter) {
+            if let Ok((warnings, references)) = Resolver::new().resolve_references(&stmts) {
+                reporter.report_all(warnings);
+                analysis.references = references;
+            }
+            if let Ok((errors, types)) = TypeChecker::new().check_types(&stmts) {
+                reporter.report_all(errors);
+                analysis.types = types;
+            }
+            analysis.symbols = outline(&stmts, false);
+        }
+        analysis.diagnostics = reporter
+            .diagnostics
+            .into_iter()
+            .filter_map(|d| config.lints.apply(d))
+            .collect();
+        analysis.diagnostics.sort_by_key(|d| d.span.start);
+        analysis
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    // Where the name at the byte offset is declared
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        self.references
+            .iter()
+            .find(|r| contains(&r.span, offset))
+            .map(|r| r.declaration)
+    }
+
+    // The declaration of the name at the byte offset, whether it is a use or the
+    // declaration itself: the type of a variable or parameter, the signature of a
+    // function and the superclass and init method of a class
+    pub fn hover(&self, offset: usize) -> Option<Hover> {
+        let (span, declaration) = match self.definition(offset) {
+            Some(declaration) => {
+                let reference = self.references.iter().find(|r| contains(&r.span, offset));
+                (reference?.span, declaration)
+            }
+            None => {
+                let declared = self
+                    .types
+                    .iter()
+                    .map(|(span, _)| *span)
+                    .chain(flatten(&self.symbols).map(|s| s.name_span))
+                    .find(|span| contains(span, offset))?;
+                (declared, declared)
+            }
+        };
+        if let Some((_, ty)) = self.types.iter().find(|(s, _)| *s == declaration) {
+            let name = self.source.get(declaration.start..declaration.end)?;
+            let text = code(&format!("{}: {}", name, ty));
+            return Some(Hover { span, text });
+        }
+        let symbol = flatten(&self.symbols).find(|s| s.name_span == declaration)?;
+        let mut detail = symbol.detail.clone();
+        let init = symbol
+            .children
+            .iter()
+            .find(|m| m.kind == SymbolKind::Method && m.name == "init");
+        if let Some(init) = init {
+            detail = format!("{}\n    {}", detail, init.detail);
+        }
+        Some(Hover {
+            span,
+            text: code(&detail),
+        })
+    }
+}
+
+// The source as `lako fmt` would write it, None if it doesn't parse
+pub fn format(source: &str, config: &Config) -> Option<String> {
+    let stmts = parse_with(source, config, &mut MemoryReporter::new())?;
+    let tokens = Scanner::with_trivia(source.to_string())
+        .scan_tokens()
+        .ok()?;
+    Formatter::new(4, 80)
+        .with_trivia(&tokens)
+        .format(&stmts)
+        .ok()
+}
+
+fn contains(span: &Span, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
+
+fn code(text: &str) -> String {
+    format!("```lako\n{}\n```", text)
+}
+
+// Every symbol, parents before their children
+fn flatten(symbols: &[Symbol]) -> Box<dyn Iterator<Item = &Symbol> + '_> {
+    Box::new(
+        symbols
+            .iter()
+            .flat_map(|s| std::iter::once(s).chain(flatten(&s.children))),
+    )
+}
+
+// The declarations in a statement list. Variables are only listed at the top level,
+// functions and classes wherever they are.
+fn outline<'a>(stmts: impl IntoIterator<Item = &'a Stmt>, nested: bool) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    for stmt in stmts {
+        let before = symbols.len();
+        match stmt.declaration() {
+            Stmt::Var { name, ty, .. } if !nested => {
+                symbols.push(variable(name, ty, SymbolKind::Variable))
+            }
+            Stmt::Function {
+                name,
+                params,
+                ret,
+                body,
+                close,
+                ..
+            } => symbols.push(function(
+                name,
+                params,
+                ret,
+                body,
+                *close,
+                SymbolKind::Function,
+            )),
+            Stmt::Class {
+                name,
+                sclass,
+                fields,
+                methods,
+                close,
+            } => {
+                let superclass = match sclass {
+                    Some(Expr::Variable { name }) => format!(" < {}", name.lexeme),
+                    _ => String::new(),
+                };
+                let mut members: Vec<Symbol> = fields
+                    .iter()
+                    .chain(methods)
+                    .filter_map(|member| match member {
+                        Stmt::Var { name, ty, .. } => Some(variable(name, ty, SymbolKind::Field)),
+                        Stmt::Function {
+                            name,
+                            params,
+                            ret,
+                            body,
+                            close,
+                            static_,
+                        } => {
+                            let mut method =
+                                function(name, params, ret, body, *close, SymbolKind::Method);
+                            if *static_ {
+                                method.detail.insert_str(0, "static ");
+                            }
+                            Some(method)
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                members.sort_by_key(|member| member.span.start);
+                symbols.push(Symbol {
+                    name: name.lexeme.to_string(),
+                    kind: SymbolKind::Class,
+                    span: Span {
+                        end: close.end,
+                        ..name.span
+                    },
+                    name_span: name.span,
+                    detail: format!("class {}{}", name.lexeme, superclass),
+                    children: members,
+                });
+            }
+            _ => symbols.extend(outline(nested_stmts(stmt), true)),
+        }
+        if let Stmt::Export { .. } = stmt {
+            for symbol in &mut symbols[before..] {
+                symbol.detail.insert_str(0, "export ");
+            }
+        }
+    }
+    symbols
+}
+
+fn variable(name: &Token, ty: &Option<Token>, kind: SymbolKind) -> Symbol {
+    Symbol {
+        name: name.lexeme.to_string(),
+        kind,
+        span: name.span,
+        name_span: name.span,
+        detail: format!("var {}{}", name.lexeme, annotation(ty)),
+        children: Vec::new(),
+    }
+}
+
+fn function(
+    name: &Token,
+    params: &Option<Vec<Param>>,
+    ret: &Option<Token>,
+    body: &[Stmt],
+    close: Span,
+    kind: SymbolKind,
+) -> Symbol {
+    // getters read as `get name: Ret`, without a parameter list
+    let (keyword, params) = match params {
+        Some(params) => {
+            let params: Vec<String> = params
+                .iter()
+                .map(|p| format!("{}{}", p.name.lexeme, annotation(&p.ty)))
+                .collect();
+            let keyword = if kind == SymbolKind::Function {
+                "fn "
+            } else {
+                ""
+            };
+            (keyword, format!("({})", params.join(", ")))
+        }
+        None => ("get ", String::new()),
+    };
+    Symbol {
+        name: name.lexeme.to_string(),
+        kind,
+        span: Span {
+            end: close.end,
+            ..name.span
+        },
+        name_span: name.span,
+        detail: format!("{}{}{}{}", keyword, name.lexeme, params, annotation(ret)),
+        children: outline(body, true),
+    }
+}
+
+// ": Type", or nothing without an annotation
+fn annotation(ty: &Option<Token>) -> String {
+    ty.as_ref()
+        .map_or(String::new(), |ty| format!(": {}", ty.lexeme))
+}
+
+// The statements directly inside a compound statement
+fn nested_stmts(stmt: &Stmt) -> Vec<&Stmt> {
+    match stmt {
+        Stmt::Block { stmts, .. } => stmts.iter().collect(),
+        Stmt::If { then_, else_, .. } => std::iter::once(&**then_).chain(else_.as_ref()).collect(),
+        Stmt::While { body, .. }
+        | Stmt::ForIn { body, .. }
+        | Stmt::For { body, .. }
+        | Stmt::Labeled { body, .. } => vec![&**body],
+        Stmt::Match { arms, else_, .. } => arms
+            .iter()
+            .map(|arm| &arm.body)
+            .chain(else_.as_ref())
+            .collect(),
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+            ..
+        } => std::iter::once(&**body)
+            .chain(catch.as_ref().as_ref().map(|(_, body)| body))
+            .chain(finally.as_ref())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "var count = 0;\n\
+                          fn add(a: Number, b): Number {\n\
+                          \x20   fn helper() {}\n\
+                          \x20   return a + b;\n\
+                          }\n\
+                          class Point < Base {\n\
+                          \x20   init(x, y) {}\n\
+                          \x20   var z: Number = 0;\n\
+                          }\n\
+                          export class Base {}\n\
+                          print add(count, 1) + Point(1, 2);\n";
+
+    fn at(text: &str) -> usize {
+        SOURCE.rfind(text).unwrap()
+    }
+
+    #[test]
+    fn outline() {
+        let analysis = Analysis::new(SOURCE, &Config::default());
+        let outline: Vec<(SymbolKind, &str, Vec<&str>)> = analysis
+            .symbols()
+            .iter()
+            .map(|s| {
+                let children = s.children.iter().map(|c| c.detail.as_str()).collect();
+                (s.kind, s.detail.as_str(), children)
+            })
+            .collect();
+        assert_eq!(
+            outline,
+            vec![
+                (SymbolKind::Variable, "var count", vec![]),
+                (
+                    SymbolKind::Function,
+                    "fn add(a: Number, b): Number",
+                    vec!["fn helper()"]
+                ),
+                (
+                    SymbolKind::Class,
+                    "class Point < Base",
+                    vec!["init(x, y)", "var z: Number"]
+                ),
+                (SymbolKind::Class, "export class Base", vec![]),
+            ]
+        );
+        let add = &analysis.symbols()[1];
+        assert_eq!(
+            &SOURCE[add.span.start..add.span.end],
+            "add(a: Number, b): Number {\n    fn helper() {}\n    return a + b;\n}"
+        );
+    }
+
+    #[test]
+    fn definitions_and_hover() {
+        let analysis = Analysis::new(SOURCE, &Config::default());
+        let declared = |name: &str| {
+            let start = SOURCE.find(name).unwrap();
+            let len = name.split(' ').next().unwrap().len();
+            Some((start, start + len))
+        };
+        let definition = |offset| analysis.definition(offset).map(|s| (s.start, s.end));
+        assert_eq!(definition(at("add(")), declared("add"));
+        assert_eq!(definition(at("count,") + 2), declared("count"));
+        assert_eq!(definition(at("< Base") + 2), declared("Base {}"));
+        assert_eq!(definition(at("print")), None);
+
+        let hover = |offset| analysis.hover(offset).map(|h| h.text);
+        let code = |text: &str| Some(format!("```lako\n{}\n```", text));
+        assert_eq!(hover(at("count,")), code("count: Number"));
+        assert_eq!(hover(at("a + b")), code("a: Number"));
+        assert_eq!(hover(at("b):")), code("b: Any"));
+        assert_eq!(hover(at("add(count")), code("fn add(a: Number, b): Number"));
+        assert_eq!(
+            hover(at("Point(1")),
+            code("class Point < Base\n    init(x, y)")
+        );
+        assert_eq!(hover(at("print")), None);
+
+        // a field named init is no initializer
+        let analysis = Analysis::new("class P { var init = 1; }\nprint P();", &Config::default());
+        assert_eq!(analysis.hover(32).map(|h| h.text), code("class P"));
+    }
+
+    #[test]
+    fn diagnostics_and_formatting() {
+        let config = Config::default();
+        let analysis = Analysis::new("{ var a; }\nvar n: Number = \"1\";", &config);
+        let codes: Vec<&str> = analysis.diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["W0201", "E0301"]);
+
+        let analysis = Analysis::new("print ;", &config);
+        assert_eq!(analysis.diagnostics[0].message, "Expect expression.");
+        assert!(analysis.symbols().is_empty());
+
+        assert_eq!(format("print  1+2;", &config).unwrap(), "print 1 + 2;\n");
+        assert_eq!(format("print ;", &config), None);
+    }
+}
@@ -0,0 +1,580 @@
+// lako-lsp: a language server for editors, speaking LSP over stdin and stdout. It
+// publishes the diagnostics of every open file as it changes and answers
+// go-to-definition, document symbols, hover, formatting and semantic token requests
+// from the frontend passes (see lako_interpreted::analysis and highlight). Documents
+// are synced whole and analyzed once per version, when they change.
+//
+// The config file and LAKO_FLAGS apply as for lako, so --strict and the lint
+// levels hold in the editor too.
+
+use lako_interpreted::analysis::{self, Analysis, Symbol, SymbolKind};
+use lako_interpreted::config::Config;
+use lako_interpreted::frontend::diagnostic::{Diagnostic, Severity};
+use lako_interpreted::frontend::stack;
+use lako_interpreted::frontend::token::Span;
+use lako_interpreted::{highlight, TokenCategory};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    DocumentSymbolRequest, Formatting, GotoDefinition, HoverRequest, Request as _,
+    SemanticTokensFullRequest,
+};
+use lsp_types::{
+    DiagnosticRelatedInformation, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentFormattingParams,
+    DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range,
+    SemanticToken, SemanticTokenType, SemanticTokens, SemanticTokensFullOptions,
+    SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Uri,
+};
+use std::collections::HashMap;
+use std::process;
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(
+            SemanticTokensOptions {
+                legend: SemanticTokensLegend {
+                    token_types: LEGEND.iter().map(|(_, t)| t.clone()).collect(),
+                    token_modifiers: Vec::new(),
+                },
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..SemanticTokensOptions::default()
+            }
+            .into(),
+        ),
+        ..ServerCapabilities::default()
+    }
+}
+
+// The semantic token types, a token's type is its index here
+const LEGEND: &[(TokenCategory, SemanticTokenType)] = &[
+    (TokenCategory::Keyword, SemanticTokenType::KEYWORD),
+    (TokenCategory::String, SemanticTokenType::STRING),
+    (TokenCategory::Number, SemanticTokenType::NUMBER),
+    (TokenCategory::Comment, SemanticTokenType::COMMENT),
+    (TokenCategory::Operator, SemanticTokenType::OPERATOR),
+    (TokenCategory::Variable, SemanticTokenType::VARIABLE),
+    (TokenCategory::Parameter, SemanticTokenType::PARAMETER),
+    (TokenCategory::Function, SemanticTokenType::FUNCTION),
+    (TokenCategory::Class, SemanticTokenType::CLASS),
+    (TokenCategory::Property, SemanticTokenType::PROPERTY),
+    (TokenCategory::Method, SemanticTokenType::METHOD),
+];
+
+// An open document at its latest version, with what requests about it need
+struct Document {
+    text: String,
+    // the byte offset each line starts at
+    lines: Vec<usize>,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn new(text: String, config: &Config) -> Document {
+        let lines = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let analysis = Analysis::new(&text, config);
+        Document {
+            text,
+            lines,
+            analysis,
+        }
+    }
+
+    fn range(&self, span: &Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+
+    // LSP positions count lines from 0 and characters in UTF-16 code units
+    fn position(&self, offset: usize) -> Position {
+        let offset = if self.text.is_char_boundary(offset) {
+            offset
+        } else {
+            self.text.len()
+        };
+        let line = self.lines.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.lines[line]..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    // The byte offset of a position, clamped to the end of its line and of the source
+    fn offset(&self, position: Position) -> usize {
+        let line_start = match self.lines.get(position.line as usize) {
+            Some(&start) => start,
+            None => return self.text.len(),
+        };
+        let mut units = 0;
+        for (i, c) in self.text[line_start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return line_start + i;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+}
+
+// The open documents by uri
+struct Server {
+    config: Config,
+    documents: HashMap<String, Document>,
+}
+
+impl Server {
+    fn new(config: Config) -> Server {
+        Server {
+            config,
+            documents: HashMap::new(),
+        }
+    }
+
+    // The messages to send back for a request or notification from the client
+    fn handle(&mut self, message: Message) -> Vec<Message> {
+        match message {
+            Message::Request(request) => vec![Message::Response(self.request(request))],
+            Message::Notification(notification) => self.notification(notification),
+            Message::Response(_) => Vec::new(),
+        }
+    }
+
+    fn request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => request
+                .extract::<GotoDefinitionParams>(GotoDefinition::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.definition(params))),
+            DocumentSymbolRequest::METHOD => request
+                .extract::<DocumentSymbolParams>(DocumentSymbolRequest::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.symbols(params))),
+            HoverRequest::METHOD => request
+                .extract::<HoverParams>(HoverRequest::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.hover(params))),
+            Formatting::METHOD => request
+                .extract::<DocumentFormattingParams>(Formatting::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.format(params))),
+            SemanticTokensFullRequest::METHOD => request
+                .extract::<SemanticTokensParams>(SemanticTokensFullRequest::METHOD)
+                .map(|(_, params)| serde_json::to_value(self.semantic_tokens(params))),
+            method => {
+                let message = format!("Unknown method '{}'.", method);
+                return Response::new_err(id, ErrorCode::MethodNotFound as i32, message);
+            }
+        };
+        match result {
+            Ok(Ok(result)) => Response {
+                id,
+                result: Some(result),
+                error: None,
+            },
+            Ok(Err(e)) => Response::new_err(id, ErrorCode::InternalError as i32, e.to_string()),
+            Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    // Keeps the documents in sync and publishes their diagnostics
+    fn notification(&mut self, notification: Notification) -> Vec<Message> {
+        let (documents, config) = (&mut self.documents, &self.config);
+        let uri = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => notification
+                .extract::<DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD)
+                .ok()
+                .map(|params| {
+                    let document = params.text_document;
+                    let opened = Document::new(document.text, config);
+                    documents.insert(document.uri.to_string(), opened);
+                    document.uri
+                }),
+            // with full sync the last change is the whole new text
+            DidChangeTextDocument::METHOD => notification
+                .extract::<DidChangeTextDocumentParams>(DidChangeTextDocument::METHOD)
+                .ok()
+                .and_then(|mut params| {
+                    let change = params.content_changes.pop()?;
+                    let changed = Document::new(change.text, config);
+                    documents.insert(params.text_document.uri.to_string(), changed);
+                    Some(params.text_document.uri)
+                }),
+            DidCloseTextDocument::METHOD => notification
+                .extract::<DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD)
+                .ok()
+                .map(|params| {
+                    documents.remove(params.text_document.uri.as_str());
+                    params.text_document.uri
+                }),
+            _ => None,
+        };
+        uri.map_or_else(Vec::new, |uri| vec![self.publish_diagnostics(uri)])
+    }
+
+    // The diagnostics of a document, none once it is closed
+    fn publish_diagnostics(&self, uri: Uri) -> Message {
+        let diagnostics = match self.documents.get(uri.as_str()) {
+            Some(document) => document
+                .analysis
+                .diagnostics
+                .iter()
+                .map(|d| diagnostic(&uri, document, d))
+                .collect(),
+            None => Vec::new(),
+        };
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        ))
+    }
+
+    // An open document and the byte offset of a position in it
+    fn at(&self, uri: &Uri, position: Position) -> Option<(&Document, usize)> {
+        let document = self.documents.get(uri.as_str())?;
+        Some((document, document.offset(position)))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let at = params.text_document_position_params;
+        let (document, offset) = self.at(&at.text_document.uri, at.position)?;
+        let declaration = document.analysis.definition(offset)?;
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            at.text_document.uri,
+            document.range(&declaration),
+        )))
+    }
+
+    fn symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let document = self.documents.get(params.text_document.uri.as_str())?;
+        let symbols = document
+            .analysis
+            .symbols()
+            .iter()
+            .map(|s| document_symbol(document, s))
+            .collect();
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let at = params.text_document_position_params;
+        let (document, offset) = self.at(&at.text_document.uri, at.position)?;
+        let hover = document.analysis.hover(offset)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: hover.text,
+            }),
+            range: Some(document.range(&hover.span)),
+        })
+    }
+
+    // One edit replacing the whole document, none if it is formatted or doesn't parse
+    fn format(&self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        let document = self.documents.get(params.text_document.uri.as_str())?;
+        let formatted = analysis::format(&document.text, &self.config)?;
+        if formatted == document.text {
+            return Some(Vec::new());
+        }
+        let end = document.position(document.text.len());
+        let whole = Range::new(Position::new(0, 0), end);
+        Some(vec![TextEdit::new(whole, formatted)])
+    }
+
+    fn semantic_tokens(&self, params: SemanticTokensParams) -> Option<SemanticTokens> {
+        let document = self.documents.get(params.text_document.uri.as_str())?;
+        Some(SemanticTokens {
+            result_id: None,
+            data: semantic_tokens(document),
+        })
+    }
+}
+
+// The highlighted tokens, each relative to the one before. Editors want a token per
+// line, so strings and comments that span lines are split.
+fn semantic_tokens(document: &Document) -> Vec<SemanticToken> {
+    let source = document.text.as_str();
+    let mut data = Vec::new();
+    let mut last = Position::new(0, 0);
+    for (span, category) in highlight(source) {
+        let token_type = LEGEND.iter().position(|(c, _)| *c == category).unwrap_or(0) as u32;
+        let mut start = span.start;
+        for line in source[span.start..span.end].split('\n') {
+            let length = line.encode_utf16().count() as u32;
+            let at = document.position(start);
+            start += line.len() + 1;
+            if length == 0 {
+                continue;
+            }
+            let delta_start = if at.line == last.line {
+                at.character - last.character
+            } else {
+                at.character
+            };
+            data.push(SemanticToken {
+                delta_line: at.line - last.line,
+                delta_start,
+                length,
+                token_type,
+                token_modifiers_bitset: 0,
+            });
+            last = at;
+        }
+    }
+    data
+}
+
+fn diagnostic(uri: &Uri, document: &Document, d: &Diagnostic) -> lsp_types::Diagnostic {
+    let severity = match d.severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Note => DiagnosticSeverity::INFORMATION,
+    };
+    let related = d.help.as_ref().map(|help| {
+        vec![DiagnosticRelatedInformation {
+            location: Location::new(uri.clone(), document.range(&help.span)),
+            message: help.message.clone(),
+        }]
+    });
+    lsp_types::Diagnostic {
+        range: document.range(&d.span),
+        severity: Some(severity),
+        code: Some(NumberOrString::String(d.code.to_string())),
+        source: Some("lako".to_string()),
+        message: d.message.clone(),
+        related_information: related,
+        ..lsp_types::Diagnostic::default()
+    }
+}
+
+#[allow(deprecated)] // DocumentSymbol::deprecated has to be set
+fn document_symbol(document: &Document, symbol: &Symbol) -> DocumentSymbol {
+    let kind = match symbol.kind {
+        SymbolKind::Variable => lsp_types::SymbolKind::VARIABLE,
+        SymbolKind::Function => lsp_types::SymbolKind::FUNCTION,
+        SymbolKind::Method => lsp_types::SymbolKind::METHOD,
+        SymbolKind::Field => lsp_types::SymbolKind::FIELD,
+        SymbolKind::Class => lsp_types::SymbolKind::CLASS,
+    };
+    DocumentSymbol {
+        name: symbol.name.clone(),
+        detail: Some(symbol.detail.clone()),
+        kind,
+        tags: None,
+        deprecated: None,
+        range: document.range(&symbol.span),
+        selection_range: document.range(&symbol.name_span),
+        children: Some(
+            symbol
+                .children
+                .iter()
+                .map(|s| document_symbol(document, s))
+                .collect(),
+        ),
+    }
+}
+
+// deep programs need more stack than the main thread has, see frontend::stack
+fn main() {
+    stack::run(serve)
+}
+
+fn serve() {
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("lako-lsp: {}", e);
+        Config::default()
+    });
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = serde_json::to_value(capabilities()).expect("capabilities are JSON");
+    if let Err(e) = connection.initialize(capabilities) {
+        eprintln!("lako-lsp: {}", e);
+        process::exit(1);
+    }
+
+    let mut server = Server::new(config);
+    for message in &connection.receiver {
+        if let Message::Request(request) = &message {
+            match connection.handle_shutdown(request) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(e) => {
+                    eprintln!("lako-lsp: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        for reply in server.handle(message) {
+            if connection.sender.send(reply).is_err() {
+                break;
+            }
+        }
+    }
+    drop(connection);
+    if let Err(e) = io_threads.join() {
+        eprintln!("lako-lsp: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn uri() -> Uri {
+        "file:///test.lak".parse().unwrap()
+    }
+
+    fn open(server: &mut Server, text: &str) -> Value {
+        let params = json!({
+            "textDocument": {"uri": "file:///test.lak", "languageId": "lako", "version": 1, "text": text}
+        });
+        let notification = Notification::new(DidOpenTextDocument::METHOD.to_string(), params);
+        match server.handle(Message::Notification(notification)).pop() {
+            Some(Message::Notification(published)) => published.params,
+            other => panic!("expected diagnostics, got {:?}", other),
+        }
+    }
+
+    fn request(server: &mut Server, method: &str, params: Value) -> Value {
+        let request = Request::new(1.into(), method.to_string(), params);
+        match server.handle(Message::Request(request)).pop() {
+            Some(Message::Response(response)) => response.result.unwrap_or(Value::Null),
+            other => panic!("expected a response, got {:?}", other),
+        }
+    }
+
+    fn at(line: u32, character: u32) -> Value {
+        json!({"textDocument": {"uri": "file:///test.lak"}, "position": {"line": line, "character": character}})
+    }
+
+    #[test]
+    fn positions_count_utf16() {
+        let source = "print \"é😀\";\nprint a;";
+        let document = Document::new(source.to_string(), &Config::default());
+        assert_eq!(document.position(0), Position::new(0, 0));
+        // é is one UTF-16 unit, 😀 two
+        assert_eq!(document.position(13), Position::new(0, 10));
+        assert_eq!(document.position(16), Position::new(1, 0));
+        assert_eq!(document.position(source.len()), Position::new(1, 8));
+        assert_eq!(document.offset(Position::new(0, 10)), 13);
+        assert_eq!(document.offset(Position::new(1, 6)), 22);
+        assert_eq!(document.offset(Position::new(0, 99)), 15);
+        assert_eq!(document.offset(Position::new(9, 0)), source.len());
+    }
+
+    #[test]
+    fn splits_tokens_by_line() {
+        // the string is one token per line, é is one UTF-16 unit
+        let document = Document::new("var s = \"a\n  é\";\n".to_string(), &Config::default());
+        let data = semantic_tokens(&document);
+        let tokens: Vec<(u32, u32, u32, u32)> = data
+            .iter()
+            .map(|t| (t.delta_line, t.delta_start, t.length, t.token_type))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (0, 0, 3, 0),
+                (0, 4, 1, 5),
+                (0, 2, 1, 4),
+                (0, 2, 2, 1),
+                (1, 0, 4, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn publishes_diagnostics() {
+        let mut server = Server::new(Config::default());
+        let published = open(&mut server, "fn f(a, b) {}\nf(1);");
+        assert_eq!(
+            published["diagnostics"],
+            json!([{
+                "range": {"start": {"line": 1, "character": 0}, "end": {"line": 1, "character": 1}},
+                "severity": 2,
+                "code": "W0206",
+                "source": "lako",
+                "message": "'f' expects 2 arguments, got 1.",
+                "relatedInformation": [{
+                    "location": {
+                        "uri": "file:///test.lak",
+                        "range": {"start": {"line": 0, "character": 3}, "end": {"line": 0, "character": 4}}
+                    },
+                    "message": "It is declared here."
+                }]
+            }])
+        );
+
+        let close = Notification::new(
+            DidCloseTextDocument::METHOD.to_string(),
+            json!({"textDocument": {"uri": "file:///test.lak"}}),
+        );
+        match server.handle(Message::Notification(close)).pop() {
+            Some(Message::Notification(published)) => {
+                assert_eq!(published.params["diagnostics"], json!([]))
+            }
+            other => panic!("expected diagnostics, got {:?}", other),
+        }
+        assert!(!server.documents.contains_key(uri().as_str()));
+    }
+
+    #[test]
+    fn answers_requests() {
+        let mut server = Server::new(Config::default());
+        open(
+            &mut server,
+            "fn add(a, b) {\n  return a + b;\n}\nprint add(1, 2);",
+        );
+
+        let definition = request(&mut server, GotoDefinition::METHOD, at(3, 7));
+        assert_eq!(
+            definition["range"],
+            json!({"start": {"line": 0, "character": 3}, "end": {"line": 0, "character": 6}})
+        );
+
+        let hover = request(&mut server, HoverRequest::METHOD, at(1, 9));
+        assert_eq!(hover["contents"]["value"], "```lako\na: Any\n```");
+
+        let symbols = request(&mut server, DocumentSymbolRequest::METHOD, at(0, 0));
+        assert_eq!(symbols[0]["name"], "add");
+        assert_eq!(symbols[0]["detail"], "fn add(a, b)");
+
+        let params = json!({"textDocument": {"uri": "file:///test.lak"}, "options": {"tabSize": 4, "insertSpaces": true}});
+        let edits = request(&mut server, Formatting::METHOD, params);
+        assert_eq!(
+            edits[0]["newText"],
+            "fn add(a, b) {\n    return a + b;\n}\n\nprint add(1, 2);\n"
+        );
+
+        let params = json!({"textDocument": {"uri": "file:///test.lak"}});
+        let tokens = request(&mut server, SemanticTokensFullRequest::METHOD, params);
+        let data: Vec<u64> = tokens["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .take(15)
+            .map(|n| n.as_u64().unwrap())
+            .collect();
+        // the keyword fn, the function add three characters on, the parameter a
+        assert_eq!(data, vec![0, 0, 2, 0, 0, 0, 3, 3, 7, 0, 0, 4, 1, 6, 0]);
+
+        let request = Request::new(2.into(), "lako/unknown".to_string(), json!({}));
+        match server.handle(Message::Request(request)).pop() {
+            Some(Message::Response(response)) => {
+                assert_eq!(
+                    response.error.unwrap().code,
+                    ErrorCode::MethodNotFound as i32
+                )
+            }
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+}
@@ -1,66 +1,646 @@
+use lako_interpreted::analysis::Analysis;
+use lako_interpreted::config::{Config, ErrorFormat};
+use lako_interpreted::frontend::ast_json::AstJson;
+use lako_interpreted::frontend::diagnostic::Severity;
+use lako_interpreted::frontend::error::Error;
 use lako_interpreted::frontend::expr_ast::AstPrinter;
-use lako_interpreted::frontend::parser::Parser;
-use lako_interpreted::frontend::scanner::Scanner;
-use std::{
-    env, fs,
-    io::{self, Write},
-    process,
+use lako_interpreted::frontend::formatter::{self, Formatter};
+use lako_interpreted::frontend::grammar;
+use lako_interpreted::frontend::optimizer;
+use lako_interpreted::frontend::reporter::{
+    JsonReporter, LintReporter, MemoryReporter, Reporter, StderrReporter,
 };
+use lako_interpreted::frontend::resolver::Resolver;
+use lako_interpreted::frontend::scanner::Scanner;
+use lako_interpreted::frontend::stack;
+use lako_interpreted::frontend::stmt_ast::{module_path, Stmt};
+use lako_interpreted::frontend::typecheck::TypeChecker;
+use lako_interpreted::repl::{self, ParseCache};
+use lako_interpreted::script_test::Expected;
+use lako_interpreted::{parse_cst_with, parse_with};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::{env, fs, mem, process};
 
-fn run_file(path: &str) {
-    let input = fs::read_to_string(path);
-    match input {
-        Ok(bytes) => run(bytes),
-        Err(e) => {
-            eprintln!("Failed to read file: {:?}", e);
-            process::exit(5); // IO error
+// Exit codes from sysexits.h. Diagnostics always go to stderr, program output to stdout.
+// Runtime errors will exit with 70 (EX_SOFTWARE) once programs run.
+const EX_USAGE: i32 = 64; // bad command line, flags or config
+const EX_DATAERR: i32 = 65; // the source has scan, parse or type errors
+const EX_NOINPUT: i32 = 66; // the source file can't be read
+const EX_CANTCREAT: i32 = 73; // a formatted file can't be written
+
+// The path - reads the program from stdin. A file that can't be read is reported in
+// the configured error format.
+fn read_file(path: &str, config: &Config) -> String {
+    let source = if path == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        fs::read_to_string(path)
+    };
+    source.unwrap_or_else(|e| {
+        let message = format!("cannot read {}: {}", file_name(path), e);
+        let error = Error::Io(io::Error::new(e.kind(), message));
+        match config.error_format {
+            ErrorFormat::Human => eprintln!("lako: {}", error),
+            ErrorFormat::Json => JsonReporter::new(file_name(path)).report_unlocated(&error),
+        }
+        process::exit(EX_NOINPUT);
+    })
+}
+
+// The name diagnostics give the file at path
+fn file_name(path: &str) -> &str {
+    if path == "-" {
+        "<stdin>"
+    } else {
+        path
+    }
+}
+
+fn run_file(path: &str, config: &Config, check: bool) {
+    let source = read_file(path, config);
+    if run(source, file_name(path), &[], config, check, None).is_none() {
+        process::exit(EX_DATAERR);
+    }
+}
+
+// lako run [--check] file: --check type checks the program before it runs
+fn run_command(args: &[String], config: &Config) {
+    match args {
+        [flag, path] if flag == "--check" => run_file(path, config, true),
+        [path] if !path.starts_with("--") => run_file(path, config, false),
+        _ => usage(),
+    }
+}
+
+// lako check file...: reports the errors and warnings of each file without running
+// it, and exits with 65 if any had errors
+fn check_files(args: &[String], config: &Config) {
+    if args.is_empty() || args.iter().any(|arg| arg.starts_with("--")) {
+        usage();
+    }
+    let mut failed = false;
+    for path in args {
+        let source = read_file(path, config);
+        let mut reporter = reporter(&source, file_name(path), config);
+        if let Some(statements) = parse_with(&source, config, reporter.as_mut()) {
+            let modules = modules(&statements, path, config);
+            if resolve(&statements, &[], &modules, reporter.as_mut()) {
+                typecheck(&statements, reporter.as_mut());
+            }
+        }
+        failed |= reporter.had_error();
+    }
+    if failed {
+        process::exit(EX_DATAERR);
+    }
+}
+
+// lako tokens [--trivia] file: one token per line with its position
+fn tokens_file(args: &[String], config: &Config) {
+    let (trivia, path) = match args {
+        [flag, path] if flag == "--trivia" => (true, path),
+        [path] if !path.starts_with("--") => (false, path),
+        _ => usage(),
+    };
+    let source = read_file(path, config);
+    let mut scanner = if trivia {
+        Scanner::with_trivia(source.clone())
+    } else {
+        Scanner::new(source.clone())
+    };
+    match scanner.scan_tokens() {
+        Ok(tokens) => {
+            for token in tokens {
+                println!("{}:{} {}", token.span.line, token.span.col, token);
+            }
+        }
+        Err(errors) => {
+            reporter(&source, file_name(path), config).report_errors(&errors);
+            process::exit(EX_DATAERR);
+        }
+    }
+}
+
+// lako ast [--ast-format=sexpr|json|cst] file
+// cst prints the lossless tree, comments and whitespace included
+fn ast_file(args: &[String], config: &Config) {
+    let mut path = None;
+    let mut format = "sexpr";
+    for arg in args {
+        match arg.strip_prefix("--ast-format=") {
+            Some(f @ ("sexpr" | "json" | "cst")) => format = f,
+            Some(f) => {
+                eprintln!(
+                    "Unsupported AST format '{}'. Expected 'sexpr', 'json' or 'cst'.",
+                    f
+                );
+                process::exit(EX_USAGE);
+            }
+            None if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            None => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+    let source = read_file(path, config);
+    let mut reporter = reporter(&source, file_name(path), config);
+    if format == "cst" {
+        match parse_cst_with(&source, config, reporter.as_mut()) {
+            Some((_, root)) => print!("{}", root),
+            None => process::exit(EX_DATAERR),
+        }
+        return;
+    }
+    let statements = match parse_with(&source, config, reporter.as_mut()) {
+        Some(statements) => optimized(statements, config),
+        None => process::exit(EX_DATAERR),
+    };
+    if format == "json" {
+        match AstJson.program(&statements) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("Error {:?}", e),
+        }
+    } else {
+        print_statements(&statements);
+    }
+}
+
+// lako fmt [--check] [--indent=N] [--width=N] file...
+// Rewrites the files in place, or with --check prints a diff for each file that
+// isn't formatted and exits with 1. The file - formats stdin to stdout.
+fn fmt_files(args: &[String], config: &Config) {
+    let mut check = false;
+    let (mut indent, mut width) = (4, 80);
+    let mut paths = Vec::new();
+    for arg in args {
+        if arg == "--check" {
+            check = true;
+        } else if let Some(n) = arg.strip_prefix("--indent=") {
+            indent = fmt_option(arg, n);
+        } else if let Some(n) = arg.strip_prefix("--width=") {
+            width = fmt_option(arg, n);
+        } else if arg.starts_with("--") {
+            usage();
+        } else {
+            paths.push(arg);
+        }
+    }
+    if paths.is_empty() {
+        usage();
+    }
+
+    // a file that fails is reported and the rest are still formatted
+    let (mut unformatted, mut invalid, mut unwritten) = (false, false, false);
+    for path in paths {
+        let source = read_file(path, config);
+        let mut reporter = reporter(&source, file_name(path), config);
+        let statements = match parse_with(&source, config, reporter.as_mut()) {
+            Some(statements) => statements,
+            None => {
+                invalid = true;
+                continue;
+            }
+        };
+        // it scanned fine in parse
+        let tokens = Scanner::with_trivia(source.clone())
+            .scan_tokens()
+            .unwrap_or_default();
+        let formatted = match Formatter::new(indent, width)
+            .with_trivia(&tokens)
+            .format(&statements)
+        {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprintln!("Error {:?}", e);
+                invalid = true;
+                continue;
+            }
+        };
+
+        if check {
+            if formatted != source {
+                unformatted = true;
+                print!("{}", formatter::diff(path, &source, &formatted));
+            }
+        } else if path == "-" {
+            print!("{}", formatted);
+        } else if formatted != source {
+            if let Err(e) = fs::write(path, formatted) {
+                eprintln!("lako: cannot write {}: {}", path, e);
+                unwritten = true;
+            }
+        }
+    }
+    if unwritten {
+        process::exit(EX_CANTCREAT);
+    } else if invalid {
+        process::exit(EX_DATAERR);
+    } else if unformatted {
+        process::exit(1);
+    }
+}
+
+// lako test path...: runs every script, and every .lako or .lak file in a directory,
+// and checks its output against the expectations in its comments. Exits with 1 if
+// any script failed.
+fn test_scripts(args: &[String], config: &Config) {
+    if args.is_empty() || args.iter().any(|arg| arg.starts_with("--")) {
+        usage();
+    }
+    let mut scripts = Vec::new();
+    for arg in args {
+        collect_scripts(Path::new(arg), &mut scripts);
+    }
+    let lako = env::current_exe().unwrap_or_else(|e| {
+        eprintln!("lako: cannot find the lako executable: {}", e);
+        process::exit(EX_NOINPUT);
+    });
+
+    let mut failed = 0;
+    for script in &scripts {
+        let expected = Expected::parse(&read_file(&script.to_string_lossy(), config));
+        // the scripts' own pragmas only, whatever the config file or LAKO_FLAGS say
+        let output = process::Command::new(&lako)
+            .args([
+                "--color=never",
+                "--error-format=human",
+                "--no-strict",
+                "--no-opt",
+                "run",
+            ])
+            .arg(script)
+            .env_remove("LAKO_FLAGS")
+            .output()
+            .unwrap_or_else(|e| {
+                eprintln!("lako: cannot run {}: {}", script.display(), e);
+                process::exit(EX_NOINPUT);
+            });
+        let failures = expected.check(
+            &String::from_utf8_lossy(&output.stdout),
+            &String::from_utf8_lossy(&output.stderr),
+            output.status.code().unwrap_or(-1),
+        );
+        if !failures.is_empty() {
+            failed += 1;
+            println!("FAIL {}", script.display());
+            for failure in failures {
+                println!("     {}", failure);
+            }
+        }
+    }
+    println!("{} passed, {} failed", scripts.len() - failed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+// The path if it's a file, else the scripts under it in name order
+fn collect_scripts(path: &Path, scripts: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        scripts.push(path.to_path_buf());
+        return;
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)
+        .and_then(|dir| dir.map(|entry| entry.map(|e| e.path())).collect())
+        .unwrap_or_else(|e| {
+            eprintln!("lako: cannot read {}: {}", path.display(), e);
+            process::exit(EX_NOINPUT);
+        });
+    entries.sort();
+    for entry in entries {
+        let script = matches!(
+            entry.extension().and_then(|e| e.to_str()),
+            Some("lako") | Some("lak")
+        );
+        if entry.is_dir() || script {
+            collect_scripts(&entry, scripts);
         }
     }
 }
 
-fn run_repl() {
+fn fmt_option(arg: &str, value: &str) -> usize {
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("lako: {} expects a number", arg);
+        usage()
+    })
+}
+
+// ~/.lako_history keeps the REPL history between sessions
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".lako_history"))
+}
+
+fn run_repl(config: &Config) {
+    let mut editor: Editor<repl::Helper, DefaultHistory> =
+        Editor::new().expect("Failed to start the line editor!");
+    // input is colored as it is typed, like the diagnostics
+    editor.set_helper(Some(repl::Helper {
+        color: config.color.enabled(),
+        globals: Vec::new(),
+    }));
+    let history = history_path();
+    if let Some(path) = &history {
+        // there is no history the first time
+        let _ = editor.load_history(path);
+    }
+
+    // inputs that ran without errors, in order - written out by :save
+    let mut session: Vec<String> = Vec::new();
+    // their statements, so later inputs can call what they declared
+    let mut prelude: Vec<Stmt> = Vec::new();
+    // the files :load parsed, see ParseCache
+    let mut cache = ParseCache::new();
+    // lines read so far while brackets or a string are still open
+    let mut input = String::new();
     loop {
-        print!("> ");
-        io::stdout().flush().expect("Failed to flush stdout!");
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read from stdin!");
+        let prompt = if input.is_empty() { "> " } else { ". " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+            }
+            // Ctrl-c drops the current input, Ctrl-d leaves
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("Failed to read input: {}", e);
+                break;
+            }
+        }
+        if repl::needs_more_input(&input) {
+            continue;
+        }
 
-        run(input);
+        let input = mem::take(&mut input);
+        if input.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(input.trim_end());
+        if let Some(command) = input.trim().strip_prefix(':') {
+            meta_command(command, &mut session, &mut prelude, &mut cache, config);
+        } else if let Some(statements) = run(input.clone(), "<repl>", &prelude, config, false, None)
+        {
+            session.push(input);
+            prelude.extend(statements);
+        }
+        // errors were already reported - keep the session going
+        if let Some(helper) = editor.helper_mut() {
+            helper.globals = repl::globals(&prelude);
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("Failed to save history to {}: {}", path.display(), e);
+        }
     }
 }
 
-fn run(source: String) {
-    let mut scanner = Scanner::new(source);
-    let tokens = scanner.scan_tokens();
+// REPL commands start with ':' and are never part of the session
+fn meta_command(
+    command: &str,
+    session: &mut Vec<String>,
+    prelude: &mut Vec<Stmt>,
+    cache: &mut ParseCache,
+    config: &Config,
+) {
+    let mut parts = command.splitn(2, char::is_whitespace);
+    match (parts.next(), parts.next().map(str::trim)) {
+        (Some("help"), _) => {
+            for (command, help) in repl::COMMANDS {
+                println!(":{:<13}{}", command, help);
+            }
+        }
+        // the last declaration of each name, as the outline shows it
+        (Some("vars"), _) => {
+            let analysis = Analysis::new(&session.concat(), config);
+            let symbols = analysis.symbols();
+            for (i, symbol) in symbols.iter().enumerate() {
+                if !symbols[i + 1..].iter().any(|s| s.name == symbol.name) {
+                    println!("{}", symbol.detail);
+                }
+            }
+        }
+        (Some("clear"), _) => {
+            session.clear();
+            prelude.clear();
+        }
+        (Some("load"), Some(path)) if !path.is_empty() => match fs::read_to_string(path) {
+            Ok(source) => {
+                if let Some(statements) =
+                    run(source.clone(), path, prelude, config, false, Some(cache))
+                {
+                    session.push(source);
+                    prelude.extend(statements);
+                }
+            }
+            Err(e) => eprintln!("Failed to read {}: {}", path, e),
+        },
+        (Some("load"), _) => eprintln!("Usage: :load <file>"),
+        (Some("save"), Some(path)) if !path.is_empty() => match fs::write(path, session.concat()) {
+            Ok(()) => println!("Saved {} inputs to {}", session.len(), path),
+            Err(e) => eprintln!("Failed to write {}: {}", path, e),
+        },
+        (Some("save"), _) => eprintln!("Usage: :save <file>"),
+        _ => eprintln!("Unknown command ':{}', :help lists them.", command),
+    }
+}
 
-    // temp x debug - scanner works correctly
-    println!("{:?}", tokens);
+// Prints the diagnostics for source to stderr in the configured format, at the
+// configured lint levels. file names the source in JSON diagnostics.
+fn reporter<'a>(source: &'a str, file: &'a str, config: &Config) -> Box<dyn Reporter + 'a> {
+    let sink: Box<dyn Reporter> = match config.error_format {
+        ErrorFormat::Human => Box::new(StderrReporter::new(source, config.color.enabled())),
+        ErrorFormat::Json => Box::new(JsonReporter::new(file)),
+    };
+    Box::new(LintReporter::new(config.lints.clone(), sink))
+}
+
+// The statements after the optimizer passes when --opt is on
+fn optimized(statements: Vec<Stmt>, config: &Config) -> Vec<Stmt> {
+    if !config.opt {
+        return statements;
+    }
+    optimizer::optimize(&statements).unwrap_or_else(|e| {
+        eprintln!("Error {:?}", e);
+        statements
+    })
+}
+
+fn print_statements(statements: &[Stmt]) {
+    let mut printer = AstPrinter;
+    for stmt in statements {
+        match printer.print_stmt(stmt) {
+            Ok(res) => println!("{}", res),
+            Err(e) => eprintln!("Error {:?}", e),
+        };
+    }
+}
 
-    // let mut parser = Parser::new(tokens.to_vec());
-    // let expr = match parser.parse() {
-    //     Ok(res) => res,
-    //     Err(e) => {
-    //         eprintln!("Parsing error: {:?}", e);
-    //         process::exit(127); // command not found or invalid command
-    //     }
-    // };
-    // let mut printer = AstPrinter;
-    // match printer.print(expr) {
-    //     Ok(res) => println!("{}", res),
-    //     Err(e) => eprintln!("Error {:?}", e),
-    // };
+// The modules the statements import, parsed from their paths relative to the file
+// importing them. Those that can't be read or don't parse are left out, and what is
+// imported from them isn't checked.
+fn modules(statements: &[Stmt], file: &str, config: &Config) -> Vec<(String, Vec<Stmt>)> {
+    let dir = Path::new(file).parent().unwrap_or(Path::new(""));
+    statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Import { path, .. } => Some(module_path(path)),
+            _ => None,
+        })
+        .filter_map(|path| {
+            let source = fs::read_to_string(dir.join(path)).ok()?;
+            let statements = parse_with(&source, config, &mut MemoryReporter::new())?;
+            Some((path.to_string(), statements))
+        })
+        .collect()
 }
 
+// Reports the resolver warnings and errors, checking imports against the modules.
+// False if there were errors
+fn resolve(
+    statements: &[Stmt],
+    prelude: &[Stmt],
+    modules: &[(String, Vec<Stmt>)],
+    reporter: &mut dyn Reporter,
+) -> bool {
+    let resolver = modules.iter().fold(
+        Resolver::with_prelude(prelude),
+        |resolver, (path, stmts)| resolver.with_module(path, stmts),
+    );
+    match resolver.resolve(statements) {
+        Ok(diagnostics) => {
+            let ok = diagnostics.iter().all(|d| d.severity != Severity::Error);
+            reporter.report_all(diagnostics);
+            ok
+        }
+        Err(e) => {
+            reporter.report_errors(&[e]);
+            false
+        }
+    }
+}
+
+// Reports the type errors. False if there were any
+fn typecheck(statements: &[Stmt], reporter: &mut dyn Reporter) -> bool {
+    match TypeChecker::new().check(statements) {
+        Ok(errors) => {
+            let ok = errors.is_empty();
+            reporter.report_all(errors);
+            ok
+        }
+        Err(e) => {
+            reporter.report_errors(&[e]);
+            false
+        }
+    }
+}
+
+// The statements that ran, None if the source had errors, denied warnings included.
+// The prelude holds the statements of earlier REPL inputs. With check, type errors are
+// errors too. A cache, when given, saves parsing a source that ran before.
+fn run(
+    source: String,
+    file: &str,
+    prelude: &[Stmt],
+    config: &Config,
+    check: bool,
+    cache: Option<&mut ParseCache>,
+) -> Option<Vec<Stmt>> {
+    let mut reporter = reporter(&source, file, config);
+    let parsed = match cache {
+        Some(cache) => cache.parse(&source, config, reporter.as_mut()),
+        None => parse_with(&source, config, reporter.as_mut()),
+    };
+    match parsed {
+        Some(statements) => {
+            let modules = modules(&statements, file, config);
+            if !resolve(&statements, prelude, &modules, reporter.as_mut())
+                || (check && !typecheck(&statements, reporter.as_mut()))
+                || reporter.had_error()
+            {
+                return None;
+            }
+            let statements = optimized(statements, config);
+            // For now print the parsed program back
+            print_statements(&statements);
+            Some(statements)
+        }
+        None => None,
+    }
+}
+
+// --dump-grammar[=json] prints the tokens, keywords and operator precedence for tools
+fn dump_grammar(flag: &str) {
+    match flag.trim_start_matches("--dump-grammar") {
+        "" | "=json" => println!("{}", grammar::to_json()),
+        format => {
+            eprintln!(
+                "Unsupported grammar format '{}'. Expected 'json'.",
+                format.trim_start_matches('=')
+            );
+            process::exit(EX_USAGE);
+        }
+    }
+}
+
+const COMMANDS: &[&str] = &["repl", "run", "check", "tokens", "ast", "fmt", "test"];
+
+fn usage() -> ! {
+    eprintln!("Usage: lako [options] [repl]");
+    eprintln!("       lako [options] [run] [--check] <file | ->");
+    eprintln!("       lako [options] check <file | ->...");
+    eprintln!("       lako [options] tokens [--trivia] <file>");
+    eprintln!("       lako [options] ast [--ast-format=sexpr|json|cst] <file>");
+    eprintln!("       lako [options] fmt [--check] [--indent=N] [--width=N] <file | ->...");
+    eprintln!("       lako test <file | dir>...");
+    eprintln!("       lako --dump-grammar=json");
+    eprintln!();
+    eprintln!("Options: --strict, --no-strict, --opt, --no-opt, --color=auto|always|never,");
+    eprintln!("         --error-format=human|json, -A|-W|-D <lint | all> (allow, warn, deny)");
+    process::exit(EX_USAGE);
+}
+
+// deep programs need more stack than the main thread has, see frontend::stack
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    match args.len() {
-        1 => run_repl(),
-        2 => run_file(&args[1]),
-        _ => {
-            eprintln!("Usage: lako [file]");
-            process::exit(64); // arguments error
+    stack::run(lako)
+}
+
+fn lako() {
+    // config file and LAKO_FLAGS defaults, then the command line on top
+    let mut config = Config::load().unwrap_or_else(|e| {
+        eprintln!("lako: {}", e);
+        process::exit(EX_USAGE);
+    });
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = config.apply_flags(&args).unwrap_or_else(|e| {
+        eprintln!("lako: {}", e);
+        usage()
+    });
+
+    match args.as_slice() {
+        // a program piped in with no arguments runs like lako -
+        [] if !io::stdin().is_terminal() => run_file("-", &config, false),
+        [] => run_repl(&config),
+        [command] if command == "repl" => run_repl(&config),
+        [flag] if flag.starts_with("--dump-grammar") => dump_grammar(flag),
+        [command, rest @ ..] if command == "run" => run_command(rest, &config),
+        [command, rest @ ..] if command == "check" => check_files(rest, &config),
+        [command, rest @ ..] if command == "tokens" => tokens_file(rest, &config),
+        [command, rest @ ..] if command == "ast" => ast_file(rest, &config),
+        [command, rest @ ..] if command == "fmt" => fmt_files(rest, &config),
+        [command, rest @ ..] if command == "test" => test_scripts(rest, &config),
+        // lako file.lak is short for lako run file.lak
+        [path] if !path.starts_with("--") && !COMMANDS.contains(&path.as_str()) => {
+            run_file(path, &config, false)
         }
+        _ => usage(),
     }
 }
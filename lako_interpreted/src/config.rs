@@ -0,0 +1,315 @@
+// Default options for the lako command, so they don't have to be typed every time.
+// Later sources override earlier ones:
+//
+// 1. the config file: $XDG_CONFIG_HOME/lako/config.toml or ~/.config/lako/config.toml
+// 2. the LAKO_FLAGS environment variable, e.g. LAKO_FLAGS="--strict --color=never"
+// 3. the flags given on the command line
+//
+// The config file is a small subset of TOML, one `key = value` per line:
+//
+// color = "never"   # "auto", "always" or "never"
+// strict = true     # as if every file started with //! strict
+// opt = true        # run the optimizer passes, as with --opt
+// error_format = "json"  # "human" or "json", as with --error-format
+
+use crate::frontend::diagnostic::{self, Level, Lints};
+use std::path::PathBuf;
+use std::{env, fs, io};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn parse(s: &str) -> Option<ColorChoice> {
+        match s {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    // Whether diagnostics printed to stderr are colored
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorChoice::Auto => diagnostic::color_enabled(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// How diagnostics are printed: rendered with the source line for people, or as one
+// JSON object per line for editors and CI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+impl ErrorFormat {
+    fn parse(s: &str) -> Option<ErrorFormat> {
+        match s {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub color: ColorChoice,
+    pub error_format: ErrorFormat,
+    // the level of each warning, from -A, -W and -D
+    pub lints: Lints,
+    // the strict pragma is on for every file
+    pub strict: bool,
+    // run the optimizer passes before running a program
+    pub opt: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            color: ColorChoice::Auto,
+            error_format: ErrorFormat::Human,
+            lints: Lints::default(),
+            strict: false,
+            opt: false,
+        }
+    }
+}
+
+impl Config {
+    // The config file, then LAKO_FLAGS. Command line flags go on top with apply_flags.
+    pub fn load() -> Result<Config, String> {
+        let mut config = Config::default();
+
+        if let Some(path) = config_path() {
+            match fs::read_to_string(&path) {
+                Ok(text) => config
+                    .apply_file(&text)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            }
+        }
+
+        if let Ok(flags) = env::var("LAKO_FLAGS") {
+            let flags: Vec<String> = flags.split_whitespace().map(String::from).collect();
+            let rest = config
+                .apply_flags(&flags)
+                .map_err(|e| format!("LAKO_FLAGS: {}", e))?;
+            if let Some(arg) = rest.first() {
+                return Err(format!("LAKO_FLAGS: unknown flag '{}'", arg));
+            }
+        }
+        Ok(config)
+    }
+
+    // Reads `key = value` lines. Blank lines and # comments are skipped.
+    pub fn apply_file(&mut self, text: &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", i + 1))?;
+            let value = strip_comment(value.trim());
+            match key.trim() {
+                "color" => {
+                    self.color = value
+                        .strip_prefix('"')
+                        .and_then(|v| v.strip_suffix('"'))
+                        .and_then(ColorChoice::parse)
+                        .ok_or_else(|| {
+                            format!(
+                                "line {}: color must be \"auto\", \"always\" or \"never\"",
+                                i + 1
+                            )
+                        })?
+                }
+                "error_format" => {
+                    self.error_format = value
+                        .strip_prefix('"')
+                        .and_then(|v| v.strip_suffix('"'))
+                        .and_then(ErrorFormat::parse)
+                        .ok_or_else(|| {
+                            format!("line {}: error_format must be \"human\" or \"json\"", i + 1)
+                        })?
+                }
+                "strict" => self.strict = boolean("strict", value, i + 1)?,
+                "opt" => self.opt = boolean("opt", value, i + 1)?,
+                key => return Err(format!("line {}: unknown key '{}'", i + 1, key)),
+            }
+        }
+        Ok(())
+    }
+
+    // Applies the flags it knows and returns the other arguments, in order. Lint flags
+    // apply left to right, so `-A all -W unused` only keeps the unused warnings.
+    pub fn apply_flags(&mut self, args: &[String]) -> Result<Vec<String>, String> {
+        let mut rest = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-A" | "--allow" | "-W" | "--warn" | "-D" | "--deny" => {
+                    let lint = args
+                        .next()
+                        .ok_or_else(|| format!("{} expects a lint name", arg))?;
+                    let level = match arg.as_str() {
+                        "-A" | "--allow" => Level::Allow,
+                        "-W" | "--warn" => Level::Warn,
+                        _ => Level::Deny,
+                    };
+                    self.lints.set(lint, level)?
+                }
+                "--strict" => self.strict = true,
+                "--no-strict" => self.strict = false,
+                "--opt" => self.opt = true,
+                "--no-opt" => self.opt = false,
+                _ => {
+                    if let Some(choice) = arg.strip_prefix("--color=") {
+                        self.color = ColorChoice::parse(choice).ok_or_else(|| {
+                            format!("unknown color '{}', expected auto, always or never", choice)
+                        })?
+                    } else if let Some(format) = arg.strip_prefix("--error-format=") {
+                        self.error_format = ErrorFormat::parse(format).ok_or_else(|| {
+                            format!("unknown error format '{}', expected human or json", format)
+                        })?
+                    } else {
+                        rest.push(arg.clone())
+                    }
+                }
+            }
+        }
+        Ok(rest)
+    }
+}
+
+fn boolean(key: &str, value: &str, line: usize) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("line {}: {} must be true or false", line, key)),
+    }
+}
+
+// A value up to a trailing # comment. Quoted values are kept whole.
+fn strip_comment(value: &str) -> &str {
+    let end = if let Some(quoted) = value.strip_prefix('"') {
+        quoted.find('"').map_or(value.len(), |i| i + 2)
+    } else {
+        value.find('#').unwrap_or(value.len())
+    };
+    value[..end].trim()
+}
+
+fn config_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("lako").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn config_file() {
+        let mut config = Config::default();
+        let text =
+            "# defaults\n\ncolor = \"never\"  # no escapes in logs\nstrict=true\nopt = true\n\
+             error_format = \"json\"\n";
+        config.apply_file(text).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                color: ColorChoice::Never,
+                error_format: ErrorFormat::Json,
+                lints: Lints::default(),
+                strict: true,
+                opt: true,
+            }
+        );
+    }
+
+    #[test]
+    fn config_file_errors() {
+        let mut config = Config::default();
+        assert_eq!(
+            config
+                .apply_file("strict = true\ncolor = never")
+                .unwrap_err(),
+            "line 2: color must be \"auto\", \"always\" or \"never\""
+        );
+        assert_eq!(
+            config.apply_file("error_format = \"xml\"").unwrap_err(),
+            "line 1: error_format must be \"human\" or \"json\""
+        );
+        assert_eq!(
+            config.apply_file("strict = yes").unwrap_err(),
+            "line 1: strict must be true or false"
+        );
+        assert_eq!(
+            config.apply_file("prelude = false").unwrap_err(),
+            "line 1: unknown key 'prelude'"
+        );
+        assert_eq!(
+            config.apply_file("[lako]").unwrap_err(),
+            "line 1: expected `key = value`"
+        );
+    }
+
+    #[test]
+    fn flags_override_earlier_settings() {
+        let mut config = Config::default();
+        config
+            .apply_file("strict = true\ncolor = \"always\"\nopt = true\nerror_format = \"json\"")
+            .unwrap();
+        let rest = config
+            .apply_flags(&args(
+                "--no-strict test.lak --color=auto --no-opt --error-format=human",
+            ))
+            .unwrap();
+        assert_eq!(rest, args("test.lak"));
+        assert_eq!(config, Config::default());
+
+        assert!(config.apply_flags(&args("--color=rainbow")).is_err());
+        assert!(config.apply_flags(&args("--error-format=xml")).is_err());
+    }
+
+    #[test]
+    fn lint_flags() {
+        let mut config = Config::default();
+        let rest = config
+            .apply_flags(&args("-A all run --warn unused -D wrong-arity test.lak"))
+            .unwrap();
+        assert_eq!(rest, args("run test.lak"));
+
+        let mut lints = Lints::default();
+        lints.set("all", Level::Allow).unwrap();
+        lints.set("unused", Level::Warn).unwrap();
+        lints.set("wrong-arity", Level::Deny).unwrap();
+        assert_eq!(config.lints, lints);
+
+        assert_eq!(
+            config.apply_flags(&args("--deny")).unwrap_err(),
+            "--deny expects a lint name"
+        );
+        assert!(config.apply_flags(&args("-W everything")).is_err());
+    }
+}
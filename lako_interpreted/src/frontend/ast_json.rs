@@ -0,0 +1,646 @@
+// JSON form of the syntax tree for editor plugins, test harnesses and other tools
+// that would rather not link the crate, printed by `lako ast --ast-format=json`.
+//
+// A program is an array of statements. Every node is an object with a "type" naming
+// its Expr or Stmt variant, then its fields in a fixed order. Names and operators are
+// strings, missing optional parts are null. Nodes built around a token (a name, an
+// operator or a keyword) carry that token's "span": {"start", "end", "line", "col"},
+// with byte offsets and a 1-based line and character column.
+//
+// {"type":"Var","span":{..},"name":"a","init":{"type":"Literal","value":1}}
+//
+// Fields are only ever added, so consumers should ignore the ones they don't know.
+
+use crate::frontend::error::Error;
+use crate::frontend::expr_ast::{self, Expr, LiteralValue};
+use crate::frontend::grammar::json_string;
+use crate::frontend::stmt_ast::{self, Arm, Param, Stmt};
+use crate::frontend::token::{Span, Token};
+
+pub struct AstJson;
+
+impl AstJson {
+    // The whole program as a JSON array
+    pub fn program(&mut self, stmts: &[Stmt]) -> Result<String, Error> {
+        self.stmts(stmts)
+    }
+
+    pub fn expr(&mut self, expr: &Expr) -> Result<String, Error> {
+        expr.accept(self)
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<String, Error> {
+        let items = stmts
+            .iter()
+            .map(|s| s.accept(self))
+            .collect::<Result<Vec<String>, Error>>()?;
+        Ok(format!("[{}]", items.join(",")))
+    }
+
+    fn opt_expr(&mut self, expr: &Option<Expr>) -> Result<String, Error> {
+        match expr {
+            Some(expr) => expr.accept(self),
+            None => Ok("null".to_string()),
+        }
+    }
+}
+
+// {"start":..,"end":..,"line":..,"col":..}, shared with the JSON diagnostics
+pub(crate) fn span(span: &Span) -> String {
+    format!(
+        "{{\"start\":{},\"end\":{},\"line\":{},\"col\":{}}}",
+        span.start, span.end, span.line, span.col
+    )
+}
+
+// {"type":kind,"span":..,fields...}
+fn node(kind: &str, token: Option<&Token>, fields: &[(&str, String)]) -> String {
+    let mut r = format!("{{\"type\":{}", json_string(kind));
+    if let Some(token) = token {
+        r.push_str(&format!(",\"span\":{}", span(&token.span)));
+    }
+    for (name, value) in fields {
+        r.push_str(&format!(",{}:{}", json_string(name), value));
+    }
+    r.push('}');
+    r
+}
+
+fn name(token: &Token) -> String {
+    json_string(&token.lexeme)
+}
+
+fn opt_name(token: &Option<Token>) -> String {
+    token.as_ref().map_or("null".to_string(), name)
+}
+
+// The parameter names and, in a separate array, their types or null
+fn signature(params: &[Param]) -> (String, String) {
+    let names: Vec<String> = params.iter().map(|p| name(&p.name)).collect();
+    let types: Vec<String> = params.iter().map(|p| opt_name(&p.ty)).collect();
+    (
+        format!("[{}]", names.join(",")),
+        format!("[{}]", types.join(",")),
+    )
+}
+
+impl expr_ast::Visitor<String> for AstJson {
+    fn visit_assign_expr(
+        &mut self,
+        name_: &Token,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("name", name(name_)),
+            ("op", name(op)),
+            ("value", val.accept(self)?),
+        ];
+        Ok(node("Assign", Some(name_), &fields))
+    }
+
+    fn visit_binary_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<String, Error> {
+        let fields = [
+            ("op", name(op)),
+            ("left", lhs.accept(self)?),
+            ("right", rhs.accept(self)?),
+        ];
+        Ok(node("Binary", Some(op), &fields))
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        arg: &[Expr],
+    ) -> Result<String, Error> {
+        let args = arg
+            .iter()
+            .map(|a| a.accept(self))
+            .collect::<Result<Vec<String>, Error>>()?;
+        let fields = [
+            ("callee", callee.accept(self)?),
+            ("args", format!("[{}]", args.join(","))),
+        ];
+        Ok(node("Call", Some(paren), &fields))
+    }
+
+    fn visit_conditional_expr(
+        &mut self,
+        cond: &Expr,
+        then_: &Expr,
+        else_: &Expr,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("cond", cond.accept(self)?),
+            ("then", then_.accept(self)?),
+            ("else", else_.accept(self)?),
+        ];
+        Ok(node("Conditional", None, &fields))
+    }
+
+    fn visit_get_expr(&mut self, obj: &Expr, name_: &Token) -> Result<String, Error> {
+        let fields = [("object", obj.accept(self)?), ("name", name(name_))];
+        Ok(node("Get", Some(name_), &fields))
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<String, Error> {
+        Ok(node("Grouping", None, &[("expr", expr.accept(self)?)]))
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        obj: &Expr,
+        bracket: &Token,
+        index: &Expr,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("object", obj.accept(self)?),
+            ("index", index.accept(self)?),
+        ];
+        Ok(node("Index", Some(bracket), &fields))
+    }
+
+    fn visit_index_set_expr(
+        &mut self,
+        obj: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("object", obj.accept(self)?),
+            ("index", index.accept(self)?),
+            ("op", name(op)),
+            ("value", val.accept(self)?),
+        ];
+        Ok(node("IndexSet", Some(bracket), &fields))
+    }
+
+    fn visit_lambda_expr(
+        &mut self,
+        keywd: &Token,
+        params: &[Param],
+        ret: &Option<Token>,
+        body: &[Stmt],
+        _close: Span,
+    ) -> Result<String, Error> {
+        let (params, types) = signature(params);
+        let fields = [
+            ("params", params),
+            ("body", self.stmts(body)?),
+            ("annotations", types),
+            ("returns", opt_name(ret)),
+        ];
+        Ok(node("Lambda", Some(keywd), &fields))
+    }
+
+    fn visit_list_expr(&mut self, bracket: &Token, elems: &[Expr]) -> Result<String, Error> {
+        let elems = elems
+            .iter()
+            .map(|e| e.accept(self))
+            .collect::<Result<Vec<String>, Error>>()?;
+        let fields = [("elements", format!("[{}]", elems.join(",")))];
+        Ok(node("List", Some(bracket), &fields))
+    }
+
+    fn visit_literal_expr(&self, _token: &Token, val: &LiteralValue) -> Result<String, Error> {
+        let value = match val {
+            LiteralValue::Number(n) if n.is_finite() => n.to_string(),
+            // JSON has no infinity, e.g. for 1e999
+            LiteralValue::Number(_) | LiteralValue::Nil => "null".to_string(),
+            LiteralValue::String(s) => json_string(s),
+            LiteralValue::Boolean(b) => b.to_string(),
+        };
+        Ok(node("Literal", None, &[("value", value)]))
+    }
+
+    fn visit_logical_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<String, Error> {
+        let fields = [
+            ("op", name(op)),
+            ("left", lhs.accept(self)?),
+            ("right", rhs.accept(self)?),
+        ];
+        Ok(node("Logical", Some(op), &fields))
+    }
+
+    fn visit_map_expr(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> Result<String, Error> {
+        let mut items = Vec::new();
+        for (key, val) in entries {
+            let fields = [("key", key.accept(self)?), ("value", val.accept(self)?)];
+            items.push(node("Entry", None, &fields));
+        }
+        let fields = [("entries", format!("[{}]", items.join(",")))];
+        Ok(node("Map", Some(brace), &fields))
+    }
+
+    fn visit_set_expr(
+        &mut self,
+        obj: &Expr,
+        name_: &Token,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("object", obj.accept(self)?),
+            ("name", name(name_)),
+            ("op", name(op)),
+            ("value", val.accept(self)?),
+        ];
+        Ok(node("Set", Some(name_), &fields))
+    }
+
+    fn visit_slice_expr(
+        &mut self,
+        obj: &Expr,
+        bracket: &Token,
+        from: &Option<Expr>,
+        to: &Option<Expr>,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("object", obj.accept(self)?),
+            ("from", self.opt_expr(from)?),
+            ("to", self.opt_expr(to)?),
+        ];
+        Ok(node("Slice", Some(bracket), &fields))
+    }
+
+    fn visit_super_expr(&mut self, keywd: &Token, method: &Token) -> Result<String, Error> {
+        Ok(node("Super", Some(keywd), &[("method", name(method))]))
+    }
+
+    fn visit_this_expr(&mut self, keywd: &Token) -> Result<String, Error> {
+        Ok(node("This", Some(keywd), &[]))
+    }
+
+    fn visit_unary_expr(&mut self, op: &Token, rhs: &Expr) -> Result<String, Error> {
+        let fields = [("op", name(op)), ("operand", rhs.accept(self)?)];
+        Ok(node("Unary", Some(op), &fields))
+    }
+
+    fn visit_update_expr(
+        &mut self,
+        op: &Token,
+        target: &Expr,
+        prefix: bool,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("op", name(op)),
+            ("target", target.accept(self)?),
+            ("prefix", prefix.to_string()),
+        ];
+        Ok(node("Update", Some(op), &fields))
+    }
+
+    fn visit_variable_expr(&mut self, name_: &Token) -> Result<String, Error> {
+        Ok(node("Variable", Some(name_), &[("name", name(name_))]))
+    }
+}
+
+impl stmt_ast::Visitor<String> for AstJson {
+    fn visit_assert_stmt(
+        &mut self,
+        keywd: &Token,
+        cond: &Expr,
+        message: &Option<Expr>,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("cond", cond.accept(self)?),
+            ("message", self.opt_expr(message)?),
+        ];
+        Ok(node("Assert", Some(keywd), &fields))
+    }
+
+    fn visit_block_stmt(&mut self, stmts: &[Stmt], _close: Span) -> Result<String, Error> {
+        Ok(node("Block", None, &[("body", self.stmts(stmts)?)]))
+    }
+
+    fn visit_break_stmt(&mut self, keywd: &Token, label: &Option<Token>) -> Result<String, Error> {
+        Ok(node("Break", Some(keywd), &[("label", opt_name(label))]))
+    }
+
+    fn visit_class_stmt(
+        &mut self,
+        name_: &Token,
+        sclass: &Option<Expr>,
+        fields_: &[Stmt],
+        methods: &[Stmt],
+        _close: Span,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("name", name(name_)),
+            ("superclass", self.opt_expr(sclass)?),
+            ("fields", self.stmts(fields_)?),
+            ("methods", self.stmts(methods)?),
+        ];
+        Ok(node("Class", Some(name_), &fields))
+    }
+
+    fn visit_continue_stmt(
+        &mut self,
+        keywd: &Token,
+        label: &Option<Token>,
+    ) -> Result<String, Error> {
+        Ok(node("Continue", Some(keywd), &[("label", opt_name(label))]))
+    }
+
+    fn visit_export_stmt(&mut self, keywd: &Token, decl: &Stmt) -> Result<String, Error> {
+        Ok(node(
+            "Export",
+            Some(keywd),
+            &[("declaration", decl.accept(self)?)],
+        ))
+    }
+
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<String, Error> {
+        Ok(node("Expression", None, &[("expr", expr.accept(self)?)]))
+    }
+
+    fn visit_for_stmt(
+        &mut self,
+        init: &Option<Stmt>,
+        cond: &Option<Expr>,
+        incr: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<String, Error> {
+        let init = match init {
+            Some(stmt) => stmt.accept(self)?,
+            None => "null".to_string(),
+        };
+        let fields = [
+            ("init", init),
+            ("cond", self.opt_expr(cond)?),
+            ("incr", self.opt_expr(incr)?),
+            ("body", body.accept(self)?),
+        ];
+        Ok(node("For", None, &fields))
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        name_: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("name", name(name_)),
+            ("iterable", iterable.accept(self)?),
+            ("body", body.accept(self)?),
+        ];
+        Ok(node("ForIn", Some(name_), &fields))
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        name_: &Token,
+        params: &Option<Vec<Param>>,
+        ret: &Option<Token>,
+        body: &[Stmt],
+        _close: Span,
+        static_: bool,
+    ) -> Result<String, Error> {
+        // a getter has no parameter list
+        let (params, types) = match params {
+            Some(params) => signature(params),
+            None => ("null".to_string(), "null".to_string()),
+        };
+        let fields = [
+            ("name", name(name_)),
+            ("static", static_.to_string()),
+            ("params", params),
+            ("body", self.stmts(body)?),
+            ("annotations", types),
+            ("returns", opt_name(ret)),
+        ];
+        Ok(node("Function", Some(name_), &fields))
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        else_: &Option<Stmt>,
+        then_: &Stmt,
+    ) -> Result<String, Error> {
+        let else_ = match else_ {
+            Some(stmt) => stmt.accept(self)?,
+            None => "null".to_string(),
+        };
+        let fields = [
+            ("binding", opt_name(binding)),
+            ("cond", cond.accept(self)?),
+            ("then", then_.accept(self)?),
+            ("else", else_),
+        ];
+        Ok(node("If", None, &fields))
+    }
+
+    fn visit_import_stmt(
+        &mut self,
+        keywd: &Token,
+        path: &Token,
+        alias: &Option<Token>,
+        names: &[Token],
+    ) -> Result<String, Error> {
+        let names: Vec<String> = names.iter().map(name).collect();
+        let fields = [
+            ("path", json_string(stmt_ast::module_path(path))),
+            ("alias", opt_name(alias)),
+            ("names", format!("[{}]", names.join(","))),
+        ];
+        Ok(node("Import", Some(keywd), &fields))
+    }
+
+    fn visit_labeled_stmt(&mut self, label: &Token, body: &Stmt) -> Result<String, Error> {
+        let fields = [("label", name(label)), ("body", body.accept(self)?)];
+        Ok(node("Labeled", Some(label), &fields))
+    }
+
+    fn visit_match_stmt(
+        &mut self,
+        keywd: &Token,
+        subject: &Expr,
+        arms: &[Arm],
+        else_: &Option<Stmt>,
+        _close: Span,
+    ) -> Result<String, Error> {
+        let mut items = Vec::new();
+        for arm in arms {
+            let patterns = arm
+                .patterns
+                .iter()
+                .map(|p| p.accept(self))
+                .collect::<Result<Vec<String>, Error>>()?;
+            let fields = [
+                ("patterns", format!("[{}]", patterns.join(","))),
+                ("guard", self.opt_expr(&arm.guard)?),
+                ("body", arm.body.accept(self)?),
+            ];
+            items.push(node("Arm", None, &fields));
+        }
+        let else_ = match else_ {
+            Some(stmt) => stmt.accept(self)?,
+            None => "null".to_string(),
+        };
+        let fields = [
+            ("subject", subject.accept(self)?),
+            ("arms", format!("[{}]", items.join(","))),
+            ("else", else_),
+        ];
+        Ok(node("Match", Some(keywd), &fields))
+    }
+
+    // "rest" holds the values after the first one
+    fn visit_print_stmt(&mut self, expr: &Expr, rest: &[Expr]) -> Result<String, Error> {
+        let rest = rest
+            .iter()
+            .map(|e| e.accept(self))
+            .collect::<Result<Vec<String>, Error>>()?;
+        let fields = [
+            ("expr", expr.accept(self)?),
+            ("rest", format!("[{}]", rest.join(","))),
+        ];
+        Ok(node("Print", None, &fields))
+    }
+
+    fn visit_return_stmt(&mut self, keywd: &Token, val: &Option<Expr>) -> Result<String, Error> {
+        Ok(node(
+            "Return",
+            Some(keywd),
+            &[("value", self.opt_expr(val)?)],
+        ))
+    }
+
+    fn visit_throw_stmt(&mut self, keywd: &Token, val: &Expr) -> Result<String, Error> {
+        Ok(node("Throw", Some(keywd), &[("value", val.accept(self)?)]))
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        keywd: &Token,
+        body: &Stmt,
+        catch: &Option<(Token, Stmt)>,
+        finally: &Option<Stmt>,
+    ) -> Result<String, Error> {
+        let catch = match catch {
+            Some((name_, body)) => {
+                let fields = [("name", name(name_)), ("body", body.accept(self)?)];
+                node("Catch", Some(name_), &fields)
+            }
+            None => "null".to_string(),
+        };
+        let finally = match finally {
+            Some(stmt) => stmt.accept(self)?,
+            None => "null".to_string(),
+        };
+        let fields = [
+            ("body", body.accept(self)?),
+            ("catch", catch),
+            ("finally", finally),
+        ];
+        Ok(node("Try", Some(keywd), &fields))
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        name_: &Token,
+        ty: &Option<Token>,
+        init: &Option<Expr>,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("name", name(name_)),
+            ("init", self.opt_expr(init)?),
+            ("annotation", opt_name(ty)),
+        ];
+        Ok(node("Var", Some(name_), &fields))
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        body: &Stmt,
+    ) -> Result<String, Error> {
+        let fields = [
+            ("binding", opt_name(binding)),
+            ("cond", cond.accept(self)?),
+            ("body", body.accept(self)?),
+        ];
+        Ok(node("While", None, &fields))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::parser::Parser;
+    use crate::frontend::scanner::Scanner;
+
+    fn to_json(source: &str) -> String {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        AstJson.program(&stmts).unwrap()
+    }
+
+    #[test]
+    fn statements_and_spans() {
+        assert_eq!(
+            to_json("var a = 1;\nprint -a;"),
+            concat!(
+                r#"[{"type":"Var","span":{"start":4,"end":5,"line":1,"col":5},"name":"a","#,
+                r#""init":{"type":"Literal","value":1},"annotation":null},"#,
+                r#"{"type":"Print","expr":{"type":"Unary","#,
+                r#""span":{"start":17,"end":18,"line":2,"col":7},"op":"-","#,
+                r#""operand":{"type":"Variable","#,
+                r#""span":{"start":18,"end":19,"line":2,"col":8},"name":"a"}},"rest":[]}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn type_annotations() {
+        let json = to_json("var n: Number = 1;\nfn f(a: Number, b): String { return b; }");
+        assert!(json.contains(r#""annotation":"Number"}"#));
+        assert!(json.contains(r#""annotations":["Number",null],"returns":"String"}"#));
+    }
+
+    #[test]
+    fn imports() {
+        let json = to_json("import \"utils.lako\" as u; from \"m\" import a, b;");
+        assert!(json.contains(r#""path":"utils.lako","alias":"u","names":[]}"#));
+        assert!(json.contains(r#""path":"m","alias":null,"names":["a","b"]}"#));
+    }
+
+    #[test]
+    fn exports() {
+        let json = to_json("export var a = 1;");
+        assert!(json.starts_with(r#"[{"type":"Export","span":{"start":0,"end":6"#));
+        assert!(json.contains(r#""declaration":{"type":"Var","#));
+    }
+
+    #[test]
+    fn class_members() {
+        let json = to_json("class P { var x = 0; m() {} }");
+        assert!(json.contains(r#""superclass":null,"fields":[{"type":"Var","#));
+        assert!(json.contains(r#""methods":[{"type":"Function","#));
+        assert!(json.contains(r#""name":"m","static":false,"params":[]"#));
+
+        let json = to_json("class M { static square(n) {} }");
+        assert!(json.contains(r#""name":"square","static":true,"params":["n"]"#));
+
+        let json = to_json("class C { area { return 1; } }");
+        assert!(json.contains(r#""name":"area","static":false,"params":null,"body""#));
+        assert!(json.contains(r#""annotations":null,"returns":null"#));
+    }
+
+    #[test]
+    fn optional_parts_are_null_and_strings_escaped() {
+        let json = to_json("fn f(x) { return; }\nif (x) print \"a\\b\n\t\";");
+        assert!(json.contains(r#""params":["x"],"body":[{"type":"Return""#));
+        assert!(json.contains(r#""value":null}"#));
+        assert!(json.contains(r#""else":null}"#));
+        assert!(json.contains(r#""annotations":[null],"returns":null}"#));
+        assert!(json.contains(r#"{"type":"Literal","value":"a\\b\n\t"}"#));
+    }
+}
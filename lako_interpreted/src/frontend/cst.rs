@@ -0,0 +1,337 @@
+// Lossless concrete syntax tree, built by Parser::parse_cst next to the AST.
+//
+// Every token of the source is a leaf, comments and whitespace included when the
+// tokens came from Scanner::with_trivia, so the text of the tree is the source byte
+// for byte. Inner nodes are named after the Stmt and Expr variant they parsed into.
+// Trivia sits in the innermost node that spans it: leading comments of a statement
+// belong to the enclosing block, the ones after the last statement to the Program.
+//
+// The formatter, refactoring tools and a language server can walk it to find the
+// exact tokens behind any AST node.
+
+use crate::frontend::expr_ast::Expr;
+use crate::frontend::stmt_ast::Stmt;
+use crate::frontend::token::{Span, Token};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Program,
+    // statements
+    Assert,
+    Block,
+    Break,
+    Class,
+    Continue,
+    Export,
+    Expression,
+    For,
+    ForIn,
+    Function,
+    If,
+    Import,
+    Labeled,
+    Match,
+    Print,
+    Return,
+    Throw,
+    Try,
+    Var,
+    While,
+    // expressions
+    Assign,
+    Binary,
+    Call,
+    Conditional,
+    Get,
+    Grouping,
+    Index,
+    IndexSet,
+    Lambda,
+    List,
+    Literal,
+    Logical,
+    Map,
+    Set,
+    Slice,
+    Super,
+    This,
+    Unary,
+    Update,
+    Variable,
+}
+
+impl NodeKind {
+    pub fn of_stmt(stmt: &Stmt) -> NodeKind {
+        match stmt {
+            Stmt::Assert { .. } => NodeKind::Assert,
+            Stmt::Block { .. } => NodeKind::Block,
+            Stmt::Break { .. } => NodeKind::Break,
+            Stmt::Class { .. } => NodeKind::Class,
+            Stmt::Continue { .. } => NodeKind::Continue,
+            Stmt::Export { .. } => NodeKind::Export,
+            Stmt::Expression { .. } => NodeKind::Expression,
+            Stmt::For { .. } => NodeKind::For,
+            Stmt::ForIn { .. } => NodeKind::ForIn,
+            Stmt::Function { .. } => NodeKind::Function,
+            Stmt::If { .. } => NodeKind::If,
+            Stmt::Import { .. } => NodeKind::Import,
+            Stmt::Labeled { .. } => NodeKind::Labeled,
+            Stmt::Match { .. } => NodeKind::Match,
+            Stmt::Print { .. } => NodeKind::Print,
+            Stmt::Return { .. } => NodeKind::Return,
+            Stmt::Throw { .. } => NodeKind::Throw,
+            Stmt::Try { .. } => NodeKind::Try,
+            Stmt::Var { .. } => NodeKind::Var,
+            Stmt::While { .. } => NodeKind::While,
+        }
+    }
+
+    pub fn of_expr(expr: &Expr) -> NodeKind {
+        match expr {
+            Expr::Assign { .. } => NodeKind::Assign,
+            Expr::Binary { .. } => NodeKind::Binary,
+            Expr::Call { .. } => NodeKind::Call,
+            Expr::Conditional { .. } => NodeKind::Conditional,
+            Expr::Get { .. } => NodeKind::Get,
+            Expr::Grouping { .. } => NodeKind::Grouping,
+            Expr::Index { .. } => NodeKind::Index,
+            Expr::IndexSet { .. } => NodeKind::IndexSet,
+            Expr::Lambda { .. } => NodeKind::Lambda,
+            Expr::List { .. } => NodeKind::List,
+            Expr::Literal { .. } => NodeKind::Literal,
+            Expr::Logical { .. } => NodeKind::Logical,
+            Expr::Map { .. } => NodeKind::Map,
+            Expr::Set { .. } => NodeKind::Set,
+            Expr::Slice { .. } => NodeKind::Slice,
+            Expr::Super { .. } => NodeKind::Super,
+            Expr::This { .. } => NodeKind::This,
+            Expr::Unary { .. } => NodeKind::Unary,
+            Expr::Update { .. } => NodeKind::Update,
+            Expr::Variable { .. } => NodeKind::Variable,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Element {
+    Node(Node),
+    Token(Token),
+}
+
+impl Element {
+    pub fn span(&self) -> Span {
+        match self {
+            Element::Node(node) => node.span,
+            Element::Token(token) => token.span,
+        }
+    }
+}
+
+// span runs from the first token to the end of the last one
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,
+    pub children: Vec<Element>,
+}
+
+impl Node {
+    fn new(kind: NodeKind) -> Node {
+        Node {
+            kind,
+            span: Span::default(),
+            children: Vec::new(),
+        }
+    }
+
+    // The source text the node was parsed from
+    pub fn text(&self) -> String {
+        let mut r = String::new();
+        self.push_text(&mut r);
+        r
+    }
+
+    fn push_text(&self, r: &mut String) {
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.push_text(r),
+                Element::Token(token) => r.push_str(&token.lexeme),
+            }
+        }
+    }
+
+    // The tokens under the node, in source order
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut r = Vec::new();
+        self.push_tokens(&mut r);
+        r
+    }
+
+    fn push_tokens<'a>(&'a self, r: &mut Vec<&'a Token>) {
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.push_tokens(r),
+                Element::Token(token) => r.push(token),
+            }
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, level: usize) -> fmt::Result {
+        let pad = "  ".repeat(level);
+        writeln!(
+            f,
+            "{}{:?}@{}..{}",
+            pad, self.kind, self.span.start, self.span.end
+        )?;
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.write(f, level + 1)?,
+                Element::Token(token) => writeln!(f, "{}  {}", pad, token)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// One line per node (Kind@start..end) and token, indented by depth
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+// Builds the tree from the parsed tokens (Eof last), the trivia in front of each of
+// them and the nodes the parser recorded as token index ranges start..end.
+pub(crate) fn build(
+    tokens: &[Token],
+    trivia: &[(usize, Token)],
+    nodes: Vec<(NodeKind, usize, usize)>,
+) -> Node {
+    // outer nodes first; the parser records a node after its children, so on the
+    // same range the later one is the outer one
+    let mut indexed: Vec<_> = nodes.into_iter().enumerate().collect();
+    indexed.sort_by(|(i, a), (j, b)| (a.1, b.2, j).cmp(&(b.1, a.2, i)));
+    let mut nodes: Vec<_> = indexed.into_iter().map(|(_, node)| node).collect();
+    // a statement is recorded by both declaration and statement
+    nodes.dedup();
+
+    let mut stack = vec![(Node::new(NodeKind::Program), usize::MAX)];
+    let mut next_node = nodes.iter().peekable();
+    let mut next_trivia = trivia.iter().peekable();
+    for (i, token) in tokens.iter().enumerate() {
+        while stack.len() > 1 && stack[stack.len() - 1].1 <= i {
+            close(&mut stack);
+        }
+        while let Some((_, trivia)) = next_trivia.next_if(|(at, _)| *at == i) {
+            push(&mut stack, Element::Token(trivia.clone()));
+        }
+        if i + 1 == tokens.len() {
+            // Eof has no text
+            break;
+        }
+        while let Some((kind, _, end)) = next_node.next_if(|(_, start, _)| *start == i) {
+            stack.push((Node::new(*kind), *end));
+        }
+        push(&mut stack, Element::Token(token.clone()));
+    }
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+
+    let (mut root, _) = stack.pop().unwrap_or((Node::new(NodeKind::Program), 0));
+    root.span = spanning(&root.children);
+    root
+}
+
+fn push(stack: &mut [(Node, usize)], element: Element) {
+    if let Some((node, _)) = stack.last_mut() {
+        node.children.push(element);
+    }
+}
+
+fn close(stack: &mut Vec<(Node, usize)>) {
+    if let Some((mut node, _)) = stack.pop() {
+        node.span = spanning(&node.children);
+        push(stack, Element::Node(node));
+    }
+}
+
+fn spanning(children: &[Element]) -> Span {
+    match (children.first(), children.last()) {
+        (Some(first), Some(last)) => {
+            let first = first.span();
+            Span::new(first.start, last.span().end, first.line, first.col)
+        }
+        _ => Span::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::dialect::Dialect;
+    use crate::frontend::parser::Parser;
+    use crate::frontend::scanner::Scanner;
+
+    fn cst(source: &str) -> Node {
+        let tokens = Scanner::with_trivia(source.to_string())
+            .scan_tokens()
+            .unwrap();
+        let dialect = Dialect::from_source(source).unwrap();
+        let (_, root) = Parser::with_dialect(tokens, dialect).parse_cst().unwrap();
+        root
+    }
+
+    #[test]
+    fn tree_of_nodes_and_tokens() {
+        assert_eq!(
+            cst("print -a; // x\n").to_string(),
+            concat!(
+                "Program@0..15\n",
+                "  Print@0..9\n",
+                "    Print \"print\"\n",
+                "    Whitespace \" \"\n",
+                "    Unary@6..8\n",
+                "      Minus \"-\"\n",
+                "      Variable@7..8\n",
+                "        Identifier \"a\"\n",
+                "    Semicolon \";\"\n",
+                "  Whitespace \" \"\n",
+                "  Comment \"// x\"\n",
+                "  Whitespace \"\\n\"\n",
+            )
+        );
+    }
+
+    #[test]
+    fn text_is_the_source() {
+        let source = concat!(
+            "#!/usr/bin/env lako\n",
+            "// a class\n",
+            "class B < A {\n",
+            "    init(x) { this.x = x; } // kept\n",
+            "}\n\n",
+            "for (var i = 0; i < 3; i++) print B(i).x * -(2 + i) ? \"y\" : nil;\n",
+            "var s = <<~END\n  text\n  END\n;\n",
+            "{ s += \"!\"; --s; }  \n",
+        );
+        let root = cst(source);
+        assert_eq!(root.text(), source);
+
+        // every node's span matches its text
+        fn check(node: &Node, source: &str) {
+            assert_eq!(&source[node.span.start..node.span.end], node.text());
+            for child in &node.children {
+                if let Element::Node(node) = child {
+                    check(node, source);
+                }
+            }
+        }
+        for child in &root.children {
+            if let Element::Node(node) = child {
+                check(node, source);
+            }
+        }
+    }
+}
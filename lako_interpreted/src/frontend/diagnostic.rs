@@ -0,0 +1,459 @@
+// Diagnostics: errors, warnings (and later notes) with a severity, a stable error code and the
+// span they point at. A Diagnostic renders itself against the source it came from, showing the
+// offending line with the span underlined:
+//
+// error[E0021]: Unterminated string.
+//  --> line 1, col 7
+//   |
+// 1 | print "abc;
+//   |       ^^^^^
+//
+// A diagnostic may carry a help message pointing at another span, rendered the same way
+// under it.
+//
+// For editors and CI, `lako --error-format=json` prints each diagnostic as one line of
+// JSON instead (see Diagnostic::to_json).
+//
+// Warnings are lints: each has a name and a level, warn by default, that -A (allow),
+// -W (warn) and -D (deny) change. Allowed lints are dropped and denied ones become
+// errors.
+
+use crate::frontend::ast_json;
+use crate::frontend::error::Error;
+use crate::frontend::grammar::json_string;
+use crate::frontend::token::Span;
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, IsTerminal};
+
+// Error codes. Keep them stable - users search for them.
+// E00xx: scanning
+pub const UNEXPECTED_CHARACTER: &str = "E0011";
+pub const UNTERMINATED_STRING: &str = "E0021";
+pub const UNTERMINATED_HEREDOC: &str = "E0022";
+pub const UNKNOWN_PRAGMA: &str = "E0031";
+pub const MALFORMED_NUMBER: &str = "E0041";
+// E01xx: parsing
+pub const SYNTAX_ERROR: &str = "E0100";
+// W02xx: resolver warnings
+pub const UNUSED_VARIABLE: &str = "W0201";
+pub const UNREACHABLE_CODE: &str = "W0202";
+pub const DUPLICATE_PATTERN: &str = "W0203";
+pub const NIL_DEREFERENCE: &str = "W0204";
+pub const UNKNOWN_FUNCTION: &str = "W0205";
+pub const WRONG_ARITY: &str = "W0206";
+// E021x: resolver errors
+pub const UNDEFINED_LABEL: &str = "E0211";
+pub const JUMP_OUTSIDE_LOOP: &str = "E0212";
+pub const DUPLICATE_IMPORT: &str = "E0213";
+pub const IMPORT_CONFLICT: &str = "E0214";
+pub const NOT_EXPORTED: &str = "E0215";
+// E03xx: type errors, from the checker
+pub const TYPE_MISMATCH: &str = "E0301";
+pub const BAD_OPERAND: &str = "E0302";
+pub const NOT_CALLABLE: &str = "E0303";
+pub const UNKNOWN_TYPE: &str = "E0304";
+pub const INHERITANCE_CYCLE: &str = "E0305";
+
+// The warnings by lint name, for -A, -W and -D
+pub const LINTS: &[(&str, &str)] = &[
+    ("unused", UNUSED_VARIABLE),
+    ("unreachable", UNREACHABLE_CODE),
+    ("duplicate-pattern", DUPLICATE_PATTERN),
+    ("nil-dereference", NIL_DEREFERENCE),
+    ("unknown-function", UNKNOWN_FUNCTION),
+    ("wrong-arity", WRONG_ARITY),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn label(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+
+    // ANSI color: red, yellow, cyan
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+            Severity::Note => "\x1b[1;36m",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+// The level of each lint, by code. Lints not set are at Warn.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Lints {
+    levels: HashMap<&'static str, Level>,
+}
+
+impl Lints {
+    // Sets a lint, given by name or code, or every lint with "all"
+    pub fn set(&mut self, lint: &str, level: Level) -> Result<(), String> {
+        let codes: Vec<&'static str> = match lint {
+            "all" => LINTS.iter().map(|(_, code)| *code).collect(),
+            _ => LINTS
+                .iter()
+                .find(|(name, code)| *name == lint || *code == lint)
+                .map(|(_, code)| vec![*code])
+                .ok_or_else(|| {
+                    let names: Vec<&str> = LINTS.iter().map(|(name, _)| *name).collect();
+                    format!(
+                        "unknown lint '{}', expected {} or all",
+                        lint,
+                        names.join(", ")
+                    )
+                })?,
+        };
+        for code in codes {
+            self.levels.insert(code, level);
+        }
+        Ok(())
+    }
+
+    // The diagnostic at its lint's level, None if it is allowed. Errors and notes are
+    // left as they are.
+    pub fn apply(&self, diagnostic: Diagnostic) -> Option<Diagnostic> {
+        if diagnostic.severity != Severity::Warning {
+            return Some(diagnostic);
+        }
+        match self.levels.get(diagnostic.code) {
+            Some(Level::Allow) => None,
+            Some(Level::Deny) => Some(Diagnostic {
+                severity: Severity::Error,
+                ..diagnostic
+            }),
+            Some(Level::Warn) | None => Some(diagnostic),
+        }
+    }
+}
+
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+const HELP: &str = "\x1b[1;32m"; // green
+const RESET: &str = "\x1b[0m";
+
+// A suggestion attached to a diagnostic, e.g. where a missing ';' goes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Help {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Help {
+    pub fn new(message: &str, span: Span) -> Help {
+        Help {
+            message: message.to_string(),
+            span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub help: Option<Help>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: &str, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.to_string(),
+            span,
+            help: None,
+        }
+    }
+
+    pub fn warning(code: &'static str, message: &str, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, message, span)
+        }
+    }
+
+    // Diagnostic for a frontend error. Io errors have no location so they have none.
+    pub fn from_error(e: &Error) -> Option<Diagnostic> {
+        match e {
+            Error::Scan {
+                span,
+                code,
+                message,
+            } => Some(Diagnostic::error(code, message, *span)),
+            Error::Parse {
+                token,
+                message,
+                help,
+            } => Some(Diagnostic {
+                help: help.as_deref().cloned(),
+                ..Diagnostic::error(SYNTAX_ERROR, message, token.span)
+            }),
+            Error::Io(_) | Error::Runtime { .. } => None,
+        }
+    }
+
+    // Renders the diagnostic with the source line it points at, optionally with ANSI colors.
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| paint(color, style, text);
+
+        let mut r = String::new();
+        r.push_str(&paint(
+            self.severity.color(),
+            &format!("{}[{}]", self.severity.label(), self.code),
+        ));
+        r.push_str(&paint(BOLD, &format!(": {}", self.message)));
+        r.push('\n');
+        r.push_str(&snippet(source, &self.span, self.severity.color(), color));
+        if let Some(help) = &self.help {
+            r.push('\n');
+            r.push_str(&paint(HELP, "help"));
+            r.push_str(&paint(BOLD, &format!(": {}", help.message)));
+            r.push('\n');
+            r.push_str(&snippet(source, &help.span, HELP, color));
+        }
+        r
+    }
+
+    // One JSON object on one line, with file naming the source:
+    // {"file":"test.lak","line":2,"col":7,"span":{"start":17,"end":22,"line":2,"col":7},
+    //  "code":"E0021","severity":"error","message":"Unterminated string.","help":null}
+    // help is null or {"message":..,"span":..}. Fields are only ever added.
+    pub fn to_json(&self, file: &str) -> String {
+        let help = match &self.help {
+            Some(help) => format!(
+                "{{\"message\":{},\"span\":{}}}",
+                json_string(&help.message),
+                ast_json::span(&help.span)
+            ),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"file\":{},\"line\":{},\"col\":{},\"span\":{},\"code\":{},\"severity\":{},\"message\":{},\"help\":{}}}",
+            json_string(file),
+            self.span.line,
+            self.span.col,
+            ast_json::span(&self.span),
+            json_string(self.code),
+            json_string(self.severity.label()),
+            json_string(&self.message),
+            help
+        )
+    }
+}
+
+fn paint(color: bool, style: &str, text: &str) -> String {
+    if color {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+// The location, source line and carets under the span:
+//  --> line 2, col 7
+//   |
+// 2 | print "abc;
+//   |       ^^^^^
+fn snippet(source: &str, span: &Span, caret_style: &str, color: bool) -> String {
+    let paint = |style: &str, text: &str| paint(color, style, text);
+
+    let line_no = span.line.to_string();
+    let gutter = " ".repeat(line_no.len());
+    let text = source
+        .lines()
+        .nth((span.line - 1).max(0) as usize)
+        .unwrap_or("");
+
+    // Keep tabs in the padding so the carets line up with the source line
+    let col = span.col.max(1) - 1;
+    let padding: String = text
+        .chars()
+        .take(col)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    // Underline the span, stopping at the end of the line, but always show one caret
+    let span_chars = source
+        .get(span.start..span.end)
+        .map_or(0, |t| t.chars().count());
+    let width = span_chars
+        .min(text.chars().count().saturating_sub(col))
+        .max(1);
+
+    let mut r = String::new();
+    r.push_str(&format!(
+        "{}{} line {}, col {}\n",
+        gutter,
+        paint(BLUE, "-->"),
+        span.line,
+        span.col
+    ));
+    r.push_str(&format!("{} {}\n", gutter, paint(BLUE, "|")));
+    r.push_str(&format!(
+        "{} {} {}\n",
+        paint(BLUE, &line_no),
+        paint(BLUE, "|"),
+        text
+    ));
+    r.push_str(&format!(
+        "{} {} {}{}",
+        gutter,
+        paint(BLUE, "|"),
+        padding,
+        paint(caret_style, &"^".repeat(width))
+    ));
+    r
+}
+
+// Colors are used when stderr is a terminal, unless NO_COLOR is set (https://no-color.org)
+pub fn color_enabled() -> bool {
+    env::var_os("NO_COLOR").is_none() && io::stderr().is_terminal()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_underlines_span() {
+        let source = "var a = 1;\nprint \"abc;\n";
+        let diag = Diagnostic::error(
+            UNTERMINATED_STRING,
+            "Unterminated string.",
+            Span::new(17, 22, 2, 7),
+        );
+        let expected = [
+            "error[E0021]: Unterminated string.",
+            " --> line 2, col 7",
+            "  |",
+            "2 | print \"abc;",
+            "  |       ^^^^^",
+        ];
+        assert_eq!(diag.render(source, false), expected.join("\n"));
+    }
+
+    #[test]
+    fn render_keeps_tabs_and_shows_one_caret_at_end() {
+        let source = "\tprint a";
+        let diag = Diagnostic::error(
+            SYNTAX_ERROR,
+            "Expect ';' after value.",
+            Span::new(8, 8, 1, 9),
+        );
+        let expected = [
+            "error[E0100]: Expect ';' after value.",
+            " --> line 1, col 9",
+            "  |",
+            "1 | \tprint a",
+            "  | \t       ^",
+        ];
+        assert_eq!(diag.render(source, false), expected.join("\n"));
+    }
+
+    #[test]
+    fn render_help() {
+        let source = "print a\nprint b;\n";
+        let diag = Diagnostic {
+            help: Some(Help::new(
+                "add ';' after this expression",
+                Span::new(6, 7, 1, 7),
+            )),
+            ..Diagnostic::error(
+                SYNTAX_ERROR,
+                "Expect ';' after value.",
+                Span::new(8, 13, 2, 1),
+            )
+        };
+        let expected = [
+            "error[E0100]: Expect ';' after value.",
+            " --> line 2, col 1",
+            "  |",
+            "2 | print b;",
+            "  | ^^^^^",
+            "help: add ';' after this expression",
+            " --> line 1, col 7",
+            "  |",
+            "1 | print a",
+            "  |       ^",
+        ];
+        assert_eq!(diag.render(source, false), expected.join("\n"));
+    }
+
+    #[test]
+    fn to_json() {
+        let diag = Diagnostic {
+            help: Some(Help::new("add ';'", Span::new(6, 7, 1, 7))),
+            ..Diagnostic::error(
+                SYNTAX_ERROR,
+                "Expect ';' after \"value\".",
+                Span::new(8, 13, 2, 1),
+            )
+        };
+        assert_eq!(
+            diag.to_json("dir/test.lak"),
+            "{\"file\":\"dir/test.lak\",\"line\":2,\"col\":1,\
+             \"span\":{\"start\":8,\"end\":13,\"line\":2,\"col\":1},\
+             \"code\":\"E0100\",\"severity\":\"error\",\
+             \"message\":\"Expect ';' after \\\"value\\\".\",\
+             \"help\":{\"message\":\"add ';'\",\
+             \"span\":{\"start\":6,\"end\":7,\"line\":1,\"col\":7}}}"
+        );
+    }
+
+    #[test]
+    fn lint_levels() {
+        let unused = Diagnostic::warning(UNUSED_VARIABLE, "Unused.", Span::new(0, 1, 1, 1));
+        let syntax = Diagnostic::error(SYNTAX_ERROR, "Bad.", Span::new(0, 1, 1, 1));
+        let mut lints = Lints::default();
+        assert_eq!(lints.apply(unused.clone()), Some(unused.clone()));
+
+        lints.set("unused", Level::Deny).unwrap();
+        let denied = lints.apply(unused.clone()).unwrap();
+        assert_eq!(denied.severity, Severity::Error);
+
+        lints.set("all", Level::Allow).unwrap();
+        assert_eq!(lints.apply(unused.clone()), None);
+        assert_eq!(lints.apply(syntax.clone()), Some(syntax));
+
+        lints.set("W0201", Level::Warn).unwrap();
+        assert_eq!(lints.apply(unused.clone()), Some(unused));
+
+        assert_eq!(
+            lints.set("unusd", Level::Deny).unwrap_err(),
+            "unknown lint 'unusd', expected unused, unreachable, duplicate-pattern, \
+             nil-dereference, unknown-function, wrong-arity or all"
+        );
+    }
+
+    #[test]
+    fn render_with_color() {
+        let diag = Diagnostic::error(
+            UNEXPECTED_CHARACTER,
+            "Unexpected character.",
+            Span::new(0, 1, 1, 1),
+        );
+        let rendered = diag.render("@", true);
+        assert!(rendered.starts_with("\x1b[1;31merror[E0011]\x1b[0m"));
+        assert!(rendered.ends_with("\x1b[1;31m^\x1b[0m"));
+    }
+}
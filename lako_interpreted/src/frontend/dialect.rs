@@ -0,0 +1,122 @@
+// Per-file dialect options, set with pragma comments at the very top of a file:
+//
+// //! strict
+// //! optional-semicolons
+//
+// Several pragmas can share a line separated by commas or spaces. Pragmas are read
+// before scanning; the Scanner itself just sees them as comments. They only affect
+// the file they appear in, so code bases can migrate file by file.
+
+use crate::frontend::diagnostic;
+use crate::frontend::error::Error;
+use crate::frontend::token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Dialect {
+    // `var` declarations must have an initializer
+    pub strict: bool,
+    // a line break, '}' or the end of the file may end a statement instead of ';'
+    pub optional_semicolons: bool,
+}
+
+impl Dialect {
+    // Reads the leading `//!` lines of a file. Blank lines may be mixed in;
+    // the first other line ends the pragma block. A #! line may come first.
+    pub fn from_source(source: &str) -> Result<Dialect, Vec<Error>> {
+        let mut dialect = Dialect::default();
+        let mut errors = Vec::new();
+
+        let mut offset = 0;
+        for (i, line) in source.split('\n').enumerate() {
+            let line_offset = offset;
+            offset += line.len() + 1;
+
+            let trimmed = line.trim_start();
+            if trimmed.trim().is_empty() || (i == 0 && line.starts_with("#!")) {
+                continue;
+            }
+            let pragmas = match trimmed.strip_prefix("//!") {
+                Some(pragmas) => pragmas,
+                None => break,
+            };
+
+            // Offset of the pragma text within the line, to point at unknown names
+            let mut pos = line.len() - pragmas.len();
+            for name in pragmas.split(|c: char| c == ',' || c.is_whitespace()) {
+                let start = pos;
+                pos += name.len() + 1;
+                match name {
+                    "" => {}
+                    "strict" => dialect.strict = true,
+                    "optional-semicolons" => dialect.optional_semicolons = true,
+                    _ => errors.push(Error::Scan {
+                        span: Span::new(
+                            line_offset + start,
+                            line_offset + start + name.len(),
+                            i as i32 + 1,
+                            start + 1,
+                        ),
+                        code: diagnostic::UNKNOWN_PRAGMA,
+                        message: format!("Unknown pragma '{}'.", name),
+                    }),
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(dialect)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_pragmas() {
+        let dialect = Dialect::from_source("// just a comment\nvar a = 1;").unwrap();
+        assert_eq!(dialect, Dialect::default());
+    }
+
+    #[test]
+    fn leading_pragmas() {
+        let source = "\n//! strict\n  //! optional-semicolons\nvar a = 1;";
+        let dialect = Dialect::from_source(source).unwrap();
+        assert!(dialect.strict);
+        assert!(dialect.optional_semicolons);
+
+        let dialect = Dialect::from_source("//! strict, optional-semicolons").unwrap();
+        assert!(dialect.strict);
+        assert!(dialect.optional_semicolons);
+    }
+
+    #[test]
+    fn pragmas_after_shebang() {
+        let dialect = Dialect::from_source("#!/usr/bin/env lako\n//! strict\n").unwrap();
+        assert!(dialect.strict);
+    }
+
+    #[test]
+    fn pragmas_after_code_are_ignored() {
+        let dialect = Dialect::from_source("var a = 1;\n//! strict\n").unwrap();
+        assert!(!dialect.strict);
+    }
+
+    #[test]
+    fn unknown_pragma() {
+        let errors =
+            Dialect::from_source("//! strict\n//! optional-semicolons lenient").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 2, col 25] Error: Unknown pragma 'lenient'."
+        );
+        match &errors[0] {
+            Error::Scan { span, .. } => assert_eq!((span.start, span.end), (35, 42)),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+}
@@ -1,35 +1,49 @@
-use crate::frontend::token::{Token, TokenType};
+use crate::frontend::diagnostic::Help;
+use crate::frontend::token::{Span, Token, TokenType};
 use std::io;
 use std::{convert, fmt};
 
-pub fn error(line: i32, message: &str) {
-    report(line, "", message);
-}
-
-pub fn report(line: i32, where_: &str, message: &str) {
-    eprintln!("[line {}] Error{}: {}", line, where_, message);
-}
-
-pub fn parser_error(token: &Token, message: &str) {
-    if token.t_type == TokenType::Eof {
-        report(token.line, " at end", message);
-    } else {
-        report(token.line, &format!(" at '{}'", token.lexeme), message);
-    }
+// [line 3, col 7] Error at 'x': message
+// where_ is empty for scanner errors as they have no token yet
+fn format_report(span: &Span, where_: &str, message: &str) -> String {
+    format!(
+        "[line {}, col {}] Error{}: {}",
+        span.line, span.col, where_, message
+    )
 }
 
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
-    Parse,
-    Runtime { token: Token, message: String },
+    Scan {
+        span: Span,
+        code: &'static str,
+        message: String,
+    },
+    Parse {
+        token: Token,
+        message: String,
+        help: Option<Box<Help>>,
+    },
+    Runtime {
+        token: Token,
+        message: String,
+    },
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::Io(underlying) => write!(f, "IoError {}", underlying),
-            Error::Parse => write!(f, "ParseError"),
+            Error::Io(underlying) => write!(f, "{}", underlying),
+            Error::Scan { span, message, .. } => write!(f, "{}", format_report(span, "", message)),
+            Error::Parse { token, message, .. } => {
+                let where_ = if token.t_type == TokenType::Eof {
+                    " at end".to_string()
+                } else {
+                    format!(" at '{}'", token.lexeme)
+                };
+                write!(f, "{}", format_report(&token.span, &where_, message))
+            }
             Error::Runtime { message, .. } => write!(f, "RuntimeError {}", message),
         }
     }
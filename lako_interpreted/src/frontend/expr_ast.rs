@@ -16,20 +16,25 @@
 
 // The AstPrinter struct is an implementation of the Visitor trait that prints the AST in a human-readable format. It
 // implements the visit_*_expr methods for each type of expression, and uses a parenthesize helper method to wrap
-// expressions in parentheses to ensure correct precedence of operators in the printed output.
+// expressions in parentheses to ensure correct precedence of operators in the printed output. It also implements
+// the statement Visitor from stmt_ast so whole programs can be printed the same way.
 
 // The fmt::Display trait is implemented for both Expr and LiteralValue to provide custom formatting for these types when
 // they are printed using the format! macro or other formatting functions. The fmt::Display trait allows the Expr and
 // LiteralValue types to be formatted as strings, which is useful for debugging and other purposes.
 
 use crate::frontend::error::Error;
-use crate::frontend::token::Token;
+use crate::frontend::interner::Symbol;
+use crate::frontend::stmt_ast::{self, Arm, Param, Stmt};
+use crate::frontend::token::{Span, Token, TokenType};
 use std::fmt;
 
-#[derive(Debug, Clone)]
+// Equal trees have equal tokens, so their spans must match too
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Assign {
         name: Token,
+        op: Token, // '=' or a compound operator such as '+='
         val: Box<Expr>,
     },
     Binary {
@@ -42,6 +47,11 @@ pub enum Expr {
         paren: Token,
         arg: Vec<Expr>,
     },
+    Conditional {
+        cond: Box<Expr>,
+        then_: Box<Expr>,
+        else_: Box<Expr>,
+    },
     Get {
         obj: Box<Expr>,
         name: Token,
@@ -49,9 +59,42 @@ pub enum Expr {
     Grouping {
         expr: Box<Expr>,
     },
+    // a[i]: bracket is the '[', where index errors point
+    Index {
+        obj: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    // a[i] = v, or a compound assignment such as a[i] += v
+    IndexSet {
+        obj: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        op: Token,
+        val: Box<Expr>,
+    },
+    // fn (params): ret { body }: keywd is the 'fn'
+    Lambda {
+        keywd: Token,
+        params: Vec<Param>,
+        ret: Option<Token>,
+        body: Vec<Stmt>,
+        close: Span,
+    },
+    // [a, b, c]: bracket is the '['
+    List {
+        bracket: Token,
+        elems: Vec<Expr>,
+    },
     Literal {
+        token: Token,
         val: LiteralValue,
     },
+    // {k: v, ...}: brace is the '{'
+    Map {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+    },
     Logical {
         lhs: Box<Expr>,
         op: Token,
@@ -60,8 +103,16 @@ pub enum Expr {
     Set {
         obj: Box<Expr>,
         name: Token,
+        op: Token,
         val: Box<Expr>,
     },
+    // s[from:to], either bound may be left out
+    Slice {
+        obj: Box<Expr>,
+        bracket: Token,
+        from: Box<Option<Expr>>,
+        to: Box<Option<Expr>>,
+    },
     Super {
         keywd: Token,
         method: Token,
@@ -73,15 +124,21 @@ pub enum Expr {
         op: Token,
         rhs: Box<Expr>,
     },
+    // ++x, x--: target is a Variable or a Get
+    Update {
+        op: Token,
+        target: Box<Expr>,
+        prefix: bool,
+    },
     Variable {
         name: Token,
     },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum LiteralValue {
     Number(f64),
-    String(String),
+    String(Symbol),
     Boolean(bool),
     Nil,
 }
@@ -107,39 +164,155 @@ impl fmt::Display for LiteralValue {
 // Implement Visitor Pattern:
 // A visitor encapsulates an algorithm that operates over a heterogeneous collection of objects.
 pub trait Visitor<T> {
-    fn visit_assign_expr(&mut self, name: &Token, val: &Expr) -> Result<T, Error>;
+    fn visit_assign_expr(&mut self, name: &Token, op: &Token, val: &Expr) -> Result<T, Error>;
     fn visit_binary_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<T, Error>;
     fn visit_call_expr(&mut self, callee: &Expr, paren: &Token, arg: &[Expr]) -> Result<T, Error>;
+    fn visit_conditional_expr(
+        &mut self,
+        cond: &Expr,
+        then_: &Expr,
+        else_: &Expr,
+    ) -> Result<T, Error>;
     fn visit_get_expr(&mut self, obj: &Expr, name: &Token) -> Result<T, Error>;
     fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-    fn visit_literal_expr(&self, val: &LiteralValue) -> Result<T, Error>;
+    fn visit_index_expr(&mut self, obj: &Expr, bracket: &Token, index: &Expr) -> Result<T, Error>;
+    fn visit_index_set_expr(
+        &mut self,
+        obj: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<T, Error>;
+    fn visit_lambda_expr(
+        &mut self,
+        keywd: &Token,
+        params: &[Param],
+        ret: &Option<Token>,
+        body: &[Stmt],
+        close: Span,
+    ) -> Result<T, Error>;
+    fn visit_list_expr(&mut self, bracket: &Token, elems: &[Expr]) -> Result<T, Error>;
+    fn visit_literal_expr(&self, token: &Token, val: &LiteralValue) -> Result<T, Error>;
     fn visit_logical_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<T, Error>;
-    fn visit_set_expr(&mut self, obj: &Expr, name: &Token, val: &Expr) -> Result<T, Error>;
+    fn visit_map_expr(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> Result<T, Error>;
+    fn visit_set_expr(
+        &mut self,
+        obj: &Expr,
+        name: &Token,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<T, Error>;
+    fn visit_slice_expr(
+        &mut self,
+        obj: &Expr,
+        bracket: &Token,
+        from: &Option<Expr>,
+        to: &Option<Expr>,
+    ) -> Result<T, Error>;
     fn visit_super_expr(&mut self, keywd: &Token, method: &Token) -> Result<T, Error>;
     fn visit_this_expr(&mut self, keywd: &Token) -> Result<T, Error>;
     fn visit_unary_expr(&mut self, op: &Token, rhs: &Expr) -> Result<T, Error>;
+    fn visit_update_expr(&mut self, op: &Token, target: &Expr, prefix: bool) -> Result<T, Error>;
     fn visit_variable_expr(&mut self, name: &Token) -> Result<T, Error>;
 }
 
 impl Expr {
     pub fn accept<T>(&self, v: &mut dyn Visitor<T>) -> Result<T, Error> {
         match self {
-            Expr::Assign { name, val } => v.visit_assign_expr(name, val),
+            Expr::Assign { name, op, val } => v.visit_assign_expr(name, op, val),
             Expr::Binary { lhs, op, rhs } => v.visit_binary_expr(lhs, op, rhs),
             Expr::Call { callee, paren, arg } => v.visit_call_expr(callee, paren, arg),
+            Expr::Conditional { cond, then_, else_ } => {
+                v.visit_conditional_expr(cond, then_, else_)
+            }
             Expr::Get { obj, name } => v.visit_get_expr(obj, name),
             Expr::Grouping { expr } => v.visit_grouping_expr(expr),
-            Expr::Literal { val } => v.visit_literal_expr(val),
+            Expr::Index {
+                obj,
+                bracket,
+                index,
+            } => v.visit_index_expr(obj, bracket, index),
+            Expr::IndexSet {
+                obj,
+                bracket,
+                index,
+                op,
+                val,
+            } => v.visit_index_set_expr(obj, bracket, index, op, val),
+            Expr::Lambda {
+                keywd,
+                params,
+                ret,
+                body,
+                close,
+            } => v.visit_lambda_expr(keywd, params, ret, body, *close),
+            Expr::List { bracket, elems } => v.visit_list_expr(bracket, elems),
+            Expr::Literal { token, val } => v.visit_literal_expr(token, val),
             Expr::Logical { lhs, op, rhs } => v.visit_logical_expr(lhs, op, rhs),
-            Expr::Set { obj, name, val } => v.visit_set_expr(obj, name, val),
+            Expr::Map { brace, entries } => v.visit_map_expr(brace, entries),
+            Expr::Set { obj, name, op, val } => v.visit_set_expr(obj, name, op, val),
+            Expr::Slice {
+                obj,
+                bracket,
+                from,
+                to,
+            } => v.visit_slice_expr(obj, bracket, from, to),
             Expr::Super { keywd, method } => v.visit_super_expr(keywd, method),
             Expr::This { keywd } => v.visit_this_expr(keywd),
             Expr::Unary { op, rhs } => v.visit_unary_expr(op, rhs),
+            Expr::Update { op, target, prefix } => v.visit_update_expr(op, target, *prefix),
             Expr::Variable { name } => v.visit_variable_expr(name),
         }
     }
+
+    // Where the expression's first token is
+    pub fn start(&self) -> Span {
+        match self {
+            Expr::Assign { name, .. } | Expr::Variable { name } => name.span,
+            Expr::Binary { lhs, .. } | Expr::Logical { lhs, .. } => lhs.start(),
+            Expr::Call { callee, .. } => callee.start(),
+            Expr::Conditional { cond, .. } => cond.start(),
+            Expr::Get { obj, .. } | Expr::Set { obj, .. } => obj.start(),
+            Expr::Grouping { expr } => expr.start(),
+            Expr::Index { obj, .. } | Expr::IndexSet { obj, .. } | Expr::Slice { obj, .. } => {
+                obj.start()
+            }
+            Expr::List { bracket, .. } => bracket.span,
+            Expr::Map { brace, .. } => brace.span,
+            Expr::Literal { token, .. } => token.span,
+            Expr::Lambda { keywd, .. } | Expr::Super { keywd, .. } | Expr::This { keywd } => {
+                keywd.span
+            }
+            Expr::Unary { op, .. } => op.span,
+            Expr::Update {
+                op, target, prefix, ..
+            } => {
+                if *prefix {
+                    op.span
+                } else {
+                    target.start()
+                }
+            }
+        }
+    }
+}
+
+// The binary operator a compound assignment or an increment applies, or None for '='.
+pub fn binary_operator(op: &Token) -> Option<Token> {
+    let (t_type, lexeme) = match op.t_type {
+        TokenType::PlusEqual | TokenType::PlusPlus => (TokenType::Plus, "+"),
+        TokenType::MinusEqual | TokenType::MinusMinus => (TokenType::Minus, "-"),
+        TokenType::StarEqual => (TokenType::Star, "*"),
+        TokenType::SlashEqual => (TokenType::Slash, "/"),
+        TokenType::PercentEqual => (TokenType::Percent, "%"),
+        _ => return None,
+    };
+    Some(Token::new(t_type, lexeme, op.span))
 }
 
+// Prints the desugared meaning of the sugar the tree keeps for the formatter:
+// compound assignments, ++/-- and for loops.
 pub struct AstPrinter;
 
 impl AstPrinter {
@@ -147,6 +320,23 @@ impl AstPrinter {
         expr.accept(self)
     }
 
+    pub fn print_stmt(&mut self, stmt: &Stmt) -> Result<String, Error> {
+        stmt.accept(self)
+    }
+
+    // Like parenthesize but for a list of statements, e.g. a block or function body
+    fn parenthesize_stmts(&mut self, name: String, stmts: &[Stmt]) -> Result<String, Error> {
+        let mut r = String::new();
+        r.push('(');
+        r.push_str(&name);
+        for s in stmts {
+            r.push(' ');
+            r.push_str(&s.accept(self)?);
+        }
+        r.push(')');
+        Ok(r)
+    }
+
     fn parenthesize(&mut self, name: String, exprs: Vec<&Expr>) -> Result<String, Error> {
         let mut r = String::new();
         r.push('(');
@@ -158,35 +348,140 @@ impl AstPrinter {
         r.push(')');
         Ok(r)
     }
+
+    // The condition of an if or while: cond, or (var x cond) with a binding
+    fn condition(&mut self, binding: &Option<Token>, cond: &Expr) -> Result<String, Error> {
+        match binding {
+            Some(name) => self.parenthesize(format!("var {}", name.lexeme), vec![cond]),
+            None => cond.accept(self),
+        }
+    }
 }
 
 impl Visitor<String> for AstPrinter {
     fn visit_binary_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<String, Error> {
-        self.parenthesize(op.lexeme.clone(), vec![lhs, rhs])
+        self.parenthesize(op.lexeme.to_string(), vec![lhs, rhs])
+    }
+
+    fn visit_conditional_expr(
+        &mut self,
+        cond: &Expr,
+        then_: &Expr,
+        else_: &Expr,
+    ) -> Result<String, Error> {
+        self.parenthesize("?:".to_string(), vec![cond, then_, else_])
     }
 
     fn visit_get_expr(&mut self, obj: &Expr, name: &Token) -> Result<String, Error> {
-        self.parenthesize(name.lexeme.clone(), vec![obj])
+        self.parenthesize(name.lexeme.to_string(), vec![obj])
     }
 
     fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<String, Error> {
         self.parenthesize("group".to_string(), vec![expr])
     }
 
-    fn visit_literal_expr(&self, val: &LiteralValue) -> Result<String, Error> {
+    fn visit_index_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+    ) -> Result<String, Error> {
+        self.parenthesize("index".to_string(), vec![obj, index])
+    }
+
+    // a[i] += b  ->  (index= a i (+ (index a i) b))
+    fn visit_index_set_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<String, Error> {
+        let mut val = val.accept(self)?;
+        if let Some(bin_op) = binary_operator(op) {
+            let get = self.parenthesize("index".to_string(), vec![obj, index])?;
+            val = format!("({} {} {})", bin_op.lexeme, get, val);
+        }
+        let obj = obj.accept(self)?;
+        Ok(format!("(index= {} {} {})", obj, index.accept(self)?, val))
+    }
+
+    fn visit_lambda_expr(
+        &mut self,
+        _keywd: &Token,
+        params: &[Param],
+        ret: &Option<Token>,
+        body: &[Stmt],
+        _close: Span,
+    ) -> Result<String, Error> {
+        let head = format!("fn ({}){}", typed_params(params), typed("", ret));
+        self.parenthesize_stmts(head, body)
+    }
+
+    fn visit_list_expr(&mut self, _bracket: &Token, elems: &[Expr]) -> Result<String, Error> {
+        self.parenthesize("list".to_string(), elems.iter().collect())
+    }
+
+    fn visit_literal_expr(&self, _token: &Token, val: &LiteralValue) -> Result<String, Error> {
         Ok(val.to_string())
     }
 
     fn visit_logical_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<String, Error> {
-        self.parenthesize(op.lexeme.clone(), vec![lhs, rhs])
+        self.parenthesize(op.lexeme.to_string(), vec![lhs, rhs])
+    }
+
+    // {"a": 1}  ->  (map (a 1))
+    fn visit_map_expr(
+        &mut self,
+        _brace: &Token,
+        entries: &[(Expr, Expr)],
+    ) -> Result<String, Error> {
+        let mut r = "(map".to_string();
+        for (key, val) in entries {
+            r.push_str(&format!(" ({} {})", key.accept(self)?, val.accept(self)?));
+        }
+        r.push(')');
+        Ok(r)
     }
 
-    fn visit_set_expr(&mut self, obj: &Expr, name: &Token, val: &Expr) -> Result<String, Error> {
-        self.parenthesize(name.lexeme.clone(), vec![obj, val])
+    // o.f += b  ->  (f o (+ (f o) b))
+    fn visit_set_expr(
+        &mut self,
+        obj: &Expr,
+        name: &Token,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<String, Error> {
+        let mut val = val.accept(self)?;
+        if let Some(bin_op) = binary_operator(op) {
+            let get = self.parenthesize(name.lexeme.to_string(), vec![obj])?;
+            val = format!("({} {} {})", bin_op.lexeme, get, val);
+        }
+        Ok(format!("({} {} {})", name.lexeme, obj.accept(self)?, val))
     }
 
-    fn visit_super_expr(&mut self, _keywd: &Token, _method: &Token) -> Result<String, Error> {
-        Ok("super".to_string())
+    // s[1:]  ->  (slice s 1 _)
+    fn visit_slice_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        from: &Option<Expr>,
+        to: &Option<Expr>,
+    ) -> Result<String, Error> {
+        let mut r = format!("(slice {}", obj.accept(self)?);
+        for bound in [from, to] {
+            match bound {
+                Some(bound) => r.push_str(&format!(" {}", bound.accept(self)?)),
+                None => r.push_str(" _"),
+            }
+        }
+        r.push(')');
+        Ok(r)
+    }
+
+    fn visit_super_expr(&mut self, _keywd: &Token, method: &Token) -> Result<String, Error> {
+        Ok(format!("(super {})", method.lexeme))
     }
 
     fn visit_this_expr(&mut self, _keywd: &Token) -> Result<String, Error> {
@@ -194,44 +489,355 @@ impl Visitor<String> for AstPrinter {
     }
 
     fn visit_unary_expr(&mut self, op: &Token, rhs: &Expr) -> Result<String, Error> {
-        self.parenthesize(op.lexeme.clone(), vec![rhs])
+        self.parenthesize(op.lexeme.to_string(), vec![rhs])
+    }
+
+    // ++a  ->  (a (+ a 1))  and  a++  ->  (- (a (+ a 1)) 1)
+    fn visit_update_expr(
+        &mut self,
+        op: &Token,
+        target: &Expr,
+        prefix: bool,
+    ) -> Result<String, Error> {
+        let bin_op = binary_operator(op).expect("++ and -- have a binary operator");
+        let incremented = format!("({} {} 1)", bin_op.lexeme, target.accept(self)?);
+        let assigned = match target {
+            Expr::Get { obj, name } => {
+                format!("({} {} {})", name.lexeme, obj.accept(self)?, incremented)
+            }
+            Expr::Index { obj, index, .. } => {
+                let obj = obj.accept(self)?;
+                format!("(index= {} {} {})", obj, index.accept(self)?, incremented)
+            }
+            _ => format!("({} {})", target.accept(self)?, incremented),
+        };
+        if prefix {
+            return Ok(assigned);
+        }
+        let undo = if bin_op.t_type == TokenType::Plus {
+            "-"
+        } else {
+            "+"
+        };
+        Ok(format!("({} {} 1)", undo, assigned))
     }
 
     fn visit_variable_expr(&mut self, name: &Token) -> Result<String, Error> {
-        Ok(name.lexeme.clone())
+        Ok(name.lexeme.to_string())
     }
 
-    fn visit_assign_expr(&mut self, name: &Token, value: &Expr) -> Result<String, Error> {
-        self.parenthesize(name.lexeme.clone(), vec![value])
+    // a += b  ->  (a (+ a b))
+    fn visit_assign_expr(&mut self, name: &Token, op: &Token, val: &Expr) -> Result<String, Error> {
+        let mut val = val.accept(self)?;
+        if let Some(bin_op) = binary_operator(op) {
+            val = format!("({} {} {})", bin_op.lexeme, name.lexeme, val);
+        }
+        Ok(format!("({} {})", name.lexeme, val))
     }
 
     fn visit_call_expr(
         &mut self,
-        _callee: &Expr,
+        callee: &Expr,
         _paren: &Token,
-        _arg: &[Expr],
+        arg: &[Expr],
+    ) -> Result<String, Error> {
+        let mut exprs = vec![callee];
+        exprs.extend(arg);
+        self.parenthesize("call".to_string(), exprs)
+    }
+}
+
+// name:Type, or just the name without an annotation
+fn typed(name: &str, ty: &Option<Token>) -> String {
+    match ty {
+        Some(ty) => format!("{}:{}", name, ty.lexeme),
+        None => name.to_string(),
+    }
+}
+
+// (break) or (break label)
+fn jump(keywd: &str, label: &Option<Token>) -> String {
+    match label {
+        Some(label) => format!("({} {})", keywd, label.lexeme),
+        None => format!("({})", keywd),
+    }
+}
+
+fn typed_params(params: &[Param]) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(|p| typed(&p.name.lexeme, &p.ty))
+        .collect();
+    params.join(" ")
+}
+
+impl stmt_ast::Visitor<String> for AstPrinter {
+    fn visit_assert_stmt(
+        &mut self,
+        _keywd: &Token,
+        cond: &Expr,
+        message: &Option<Expr>,
+    ) -> Result<String, Error> {
+        let mut exprs = vec![cond];
+        exprs.extend(message.iter());
+        self.parenthesize("assert".to_string(), exprs)
+    }
+
+    fn visit_block_stmt(&mut self, stmts: &[Stmt], _close: Span) -> Result<String, Error> {
+        self.parenthesize_stmts("block".to_string(), stmts)
+    }
+
+    fn visit_break_stmt(&mut self, _keywd: &Token, label: &Option<Token>) -> Result<String, Error> {
+        Ok(jump("break", label))
+    }
+
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        sclass: &Option<Expr>,
+        fields: &[Stmt],
+        methods: &[Stmt],
+        _close: Span,
+    ) -> Result<String, Error> {
+        let mut head = format!("class {}", name.lexeme);
+        if let Some(sclass) = sclass {
+            head.push_str(&format!(" < {}", sclass.accept(self)?));
+        }
+        for field in fields {
+            head.push(' ');
+            head.push_str(&field.accept(self)?);
+        }
+        self.parenthesize_stmts(head, methods)
+    }
+
+    fn visit_continue_stmt(
+        &mut self,
+        _keywd: &Token,
+        label: &Option<Token>,
+    ) -> Result<String, Error> {
+        Ok(jump("continue", label))
+    }
+
+    fn visit_export_stmt(&mut self, _keywd: &Token, decl: &Stmt) -> Result<String, Error> {
+        Ok(format!("(export {})", decl.accept(self)?))
+    }
+
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<String, Error> {
+        self.parenthesize(";".to_string(), vec![expr])
+    }
+
+    // A while loop inside a block:
+    // for (init; cond; incr) body  ->  (block init (while cond (block body (; incr))))
+    fn visit_for_stmt(
+        &mut self,
+        init: &Option<Stmt>,
+        cond: &Option<Expr>,
+        incr: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<String, Error> {
+        let mut r = body.accept(self)?;
+        if let Some(incr) = incr {
+            r = format!("(block {} (; {}))", r, incr.accept(self)?);
+        }
+        let cond = match cond {
+            Some(cond) => cond.accept(self)?,
+            None => "true".to_string(),
+        };
+        r = format!("(while {} {})", cond, r);
+        if let Some(init) = init {
+            r = format!("(block {} {})", init.accept(self)?, r);
+        }
+        Ok(r)
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        name: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+    ) -> Result<String, Error> {
+        let iterable = iterable.accept(self)?;
+        Ok(format!(
+            "(for-in {} {} {})",
+            name.lexeme,
+            iterable,
+            body.accept(self)?
+        ))
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        name: &Token,
+        params: &Option<Vec<Param>>,
+        ret: &Option<Token>,
+        body: &[Stmt],
+        _close: Span,
+        static_: bool,
+    ) -> Result<String, Error> {
+        let static_ = if static_ { "static " } else { "" };
+        let head = match params {
+            Some(params) => format!(
+                "{}fn {} ({}){}",
+                static_,
+                name.lexeme,
+                typed_params(params),
+                typed("", ret)
+            ),
+            None => format!("{}get {}{}", static_, name.lexeme, typed("", ret)),
+        };
+        self.parenthesize_stmts(head, body)
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        else_: &Option<Stmt>,
+        then_: &Stmt,
+    ) -> Result<String, Error> {
+        let cond = self.condition(binding, cond)?;
+        let mut r = format!("(if {} {}", cond, then_.accept(self)?);
+        if let Some(else_) = else_ {
+            r.push(' ');
+            r.push_str(&else_.accept(self)?);
+        }
+        r.push(')');
+        Ok(r)
+    }
+
+    // import "m" as u;  ->  (import "m" as u)
+    // from "m" import a, b;  ->  (from "m" import a b)
+    fn visit_import_stmt(
+        &mut self,
+        _keywd: &Token,
+        path: &Token,
+        alias: &Option<Token>,
+        names: &[Token],
+    ) -> Result<String, Error> {
+        Ok(match alias {
+            Some(alias) => format!("(import {} as {})", path.lexeme, alias.lexeme),
+            None => {
+                let names: Vec<&str> = names.iter().map(|n| n.lexeme.as_str()).collect();
+                format!("(from {} import {})", path.lexeme, names.join(" "))
+            }
+        })
+    }
+
+    // outer: while (c) body  ->  (label outer (while c body))
+    fn visit_labeled_stmt(&mut self, label: &Token, body: &Stmt) -> Result<String, Error> {
+        Ok(format!("(label {} {})", label.lexeme, body.accept(self)?))
+    }
+
+    // match (x) { 1, 2 => a; n if c => b; else => d; }
+    //   ->  (match x ((1 2) a) ((n) if c b) (else d))
+    fn visit_match_stmt(
+        &mut self,
+        _keywd: &Token,
+        subject: &Expr,
+        arms: &[Arm],
+        else_: &Option<Stmt>,
+        _close: Span,
+    ) -> Result<String, Error> {
+        let mut r = format!("(match {}", subject.accept(self)?);
+        for arm in arms {
+            let patterns = arm
+                .patterns
+                .iter()
+                .map(|p| p.accept(self))
+                .collect::<Result<Vec<String>, Error>>()?;
+            let guard = match &arm.guard {
+                Some(guard) => format!(" if {}", guard.accept(self)?),
+                None => String::new(),
+            };
+            r.push_str(&format!(
+                " (({}){} {})",
+                patterns.join(" "),
+                guard,
+                arm.body.accept(self)?
+            ));
+        }
+        if let Some(else_) = else_ {
+            r.push_str(&format!(" (else {})", else_.accept(self)?));
+        }
+        r.push(')');
+        Ok(r)
+    }
+
+    fn visit_print_stmt(&mut self, expr: &Expr, rest: &[Expr]) -> Result<String, Error> {
+        let exprs = std::iter::once(expr).chain(rest).collect();
+        self.parenthesize("print".to_string(), exprs)
+    }
+
+    fn visit_return_stmt(&mut self, _keywd: &Token, val: &Option<Expr>) -> Result<String, Error> {
+        self.parenthesize("return".to_string(), val.iter().collect())
+    }
+
+    fn visit_throw_stmt(&mut self, _keywd: &Token, val: &Expr) -> Result<String, Error> {
+        self.parenthesize("throw".to_string(), vec![val])
+    }
+
+    // (try (block ..) (catch e (block ..)) (finally (block ..)))
+    fn visit_try_stmt(
+        &mut self,
+        _keywd: &Token,
+        body: &Stmt,
+        catch: &Option<(Token, Stmt)>,
+        finally: &Option<Stmt>,
+    ) -> Result<String, Error> {
+        let mut r = format!("(try {}", body.accept(self)?);
+        if let Some((name, body)) = catch {
+            r.push_str(&format!(" (catch {} {})", name.lexeme, body.accept(self)?));
+        }
+        if let Some(finally) = finally {
+            r.push_str(&format!(" (finally {})", finally.accept(self)?));
+        }
+        r.push(')');
+        Ok(r)
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        name: &Token,
+        ty: &Option<Token>,
+        init: &Option<Expr>,
+    ) -> Result<String, Error> {
+        let head = format!("var {}", typed(&name.lexeme, ty));
+        self.parenthesize(head, init.iter().collect())
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        body: &Stmt,
     ) -> Result<String, Error> {
-        unimplemented!()
+        Ok(format!(
+            "(while {} {})",
+            self.condition(binding, cond)?,
+            body.accept(self)?
+        ))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::frontend::token::{Token, TokenType};
+    use crate::frontend::token::{Span, Token, TokenType};
 
     #[test]
     fn test_printer() {
         let expression = Expr::Binary {
             lhs: Box::new(Expr::Unary {
-                op: Token::new(TokenType::Minus, "-", 1),
+                op: Token::new(TokenType::Minus, "-", Span::new(0, 1, 1, 1)),
                 rhs: Box::new(Expr::Literal {
+                    token: Token::new(TokenType::Number, "123", Span::new(1, 4, 1, 2)),
                     val: LiteralValue::Number(123f64),
                 }),
             }),
-            op: Token::new(TokenType::Star, "*", 1),
+            op: Token::new(TokenType::Star, "*", Span::new(5, 6, 1, 6)),
             rhs: Box::new(Expr::Grouping {
                 expr: Box::new(Expr::Literal {
+                    token: Token::new(TokenType::Number, "45.67", Span::new(8, 13, 1, 9)),
                     val: LiteralValue::Number(45.67f64),
                 }),
             }),
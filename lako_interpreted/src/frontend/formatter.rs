@@ -0,0 +1,1353 @@
+// Prints a parsed program back as canonical Lako source, for `lako fmt`:
+//
+// - one statement per line, blocks indented by `indent` spaces
+// - spaces around binary operators and after commas
+// - a blank line between a function or class and the statements around it,
+//   otherwise at most one blank line where the source had some
+// - the arguments of a call that would run past `width` columns, one per line
+//
+// Parentheses, the spelling of numbers and strings, heredocs and sugar such as
+// for loops and `+=` are kept as written, so formatting never changes the meaning.
+//
+// Comments come from the trivia tokens of Scanner::with_trivia. Those on their own
+// line stay before the statement that follows them and those after code stay at
+// the end of the line their code is printed on, even when it ends in a '{' or comes
+// before an else. Comments inside an expression move to after its statement.
+
+use crate::frontend::error::Error;
+use crate::frontend::expr_ast::{self, Expr, LiteralValue};
+use crate::frontend::stmt_ast::{self, Arm, Param, Stmt};
+use crate::frontend::token::{Span, Token, TokenType};
+use std::borrow::Borrow;
+use std::collections::HashSet;
+
+struct Comment {
+    text: String,
+    span: Span,
+    // nothing but whitespace before it on its line
+    own_line: bool,
+}
+
+pub struct Formatter {
+    indent: usize,
+    width: usize,
+    level: usize,
+    // columns the current line runs past the width, while calls may still be broken
+    excess: usize,
+    comments: Vec<Comment>,
+    // comments before this one have been printed
+    next_comment: usize,
+    blank_lines: HashSet<i32>,
+}
+
+impl Default for Formatter {
+    fn default() -> Formatter {
+        Formatter::new(4, 80)
+    }
+}
+
+impl Formatter {
+    pub fn new(indent: usize, width: usize) -> Formatter {
+        Formatter {
+            indent,
+            width,
+            level: 0,
+            excess: 0,
+            comments: Vec::new(),
+            next_comment: 0,
+            blank_lines: HashSet::new(),
+        }
+    }
+
+    // Keeps the comments and blank lines found in the tokens of Scanner::with_trivia
+    pub fn with_trivia(mut self, tokens: &[Token]) -> Formatter {
+        for (i, token) in tokens.iter().enumerate() {
+            match token.t_type {
+                TokenType::Comment => {
+                    // a line comment ends before the newline, so only whitespace can
+                    // separate it from the one before
+                    let own_line = i == 0 || {
+                        let prev = &tokens[i - 1];
+                        prev.t_type == TokenType::Whitespace && prev.lexeme.contains('\n')
+                    };
+                    self.comments.push(Comment {
+                        text: token.lexeme.to_string(),
+                        span: token.span,
+                        own_line,
+                    });
+                }
+                // the lines between the first and last newline are blank
+                TokenType::Whitespace => {
+                    let newlines = token.lexeme.matches('\n').count() as i32;
+                    self.blank_lines
+                        .extend(token.span.line + 1..token.span.line + newlines);
+                }
+                _ => {}
+            }
+        }
+        self
+    }
+
+    pub fn format(&mut self, stmts: &[Stmt]) -> Result<String, Error> {
+        let mut r = self.stmts(stmts, None, false)?;
+        if !r.is_empty() {
+            r.push('\n');
+        }
+        Ok(r)
+    }
+
+    fn pad(&self) -> String {
+        " ".repeat(self.indent * self.level)
+    }
+
+    // Starts a new line in r at the current level
+    fn line(&self, r: &mut String, text: &str, blank: bool) {
+        if !r.is_empty() {
+            r.push('\n');
+            if blank {
+                r.push('\n');
+            }
+        }
+        r.push_str(&self.pad());
+        r.push_str(text);
+    }
+
+    // Prints the comments before `end`, None for all that are left. A blank line
+    // owed to what follows goes before the first of them. Returns whether it is
+    // still owed.
+    fn comments(&mut self, r: &mut String, end: Option<Span>, mut blank: bool) -> bool {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if end.is_some_and(|end| comment.span.start >= end.start) {
+                break;
+            }
+            let text = comment.text.clone();
+            if comment.own_line || r.is_empty() {
+                let blank_before = self.blank_lines.contains(&(comment.span.line - 1));
+                self.line(r, &text, blank || blank_before);
+                blank = false;
+            } else {
+                r.push(' ');
+                r.push_str(&text);
+            }
+            self.next_comment += 1;
+        }
+        blank
+    }
+
+    // The comments after code before `end`, up to the next one on its own line. They
+    // go at the end of the line being printed, before it is broken.
+    fn trailing(&mut self, end: Span) -> String {
+        let mut r = String::new();
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.own_line || comment.span.start >= end.start {
+                break;
+            }
+            r.push(' ');
+            r.push_str(&comment.text);
+            self.next_comment += 1;
+        }
+        r
+    }
+
+    // { body } for lines printed a level deeper, with the comments that were on
+    // the line of the opening brace still after it
+    fn braced(&self, trailing: &str, body: &str) -> String {
+        match (trailing.is_empty(), body.is_empty()) {
+            (true, true) => "{}".to_string(),
+            (false, true) => format!("{{{}\n{}}}", trailing, self.pad()),
+            _ => format!("{{{}\n{}\n{}}}", trailing, body, self.pad()),
+        }
+    }
+
+    // Statements at the current level, one per line, up to the closing brace.
+    // Class bodies hold methods, which are functions without the fn keyword.
+    fn stmts<S: Borrow<Stmt>>(
+        &mut self,
+        stmts: &[S],
+        close: Option<Span>,
+        methods: bool,
+    ) -> Result<String, Error> {
+        let mut r = String::new();
+        for (i, stmt) in stmts.iter().enumerate() {
+            let stmt = stmt.borrow();
+            let start = stmt.start();
+            let blank = i > 0 && (is_declaration(stmt) || is_declaration(stmts[i - 1].borrow()));
+            let blank = self.comments(&mut r, Some(start), blank);
+            let text = match stmt {
+                Stmt::Function {
+                    name,
+                    params,
+                    ret,
+                    body,
+                    close,
+                    static_,
+                } if methods => {
+                    let method = self.function(name, params, ret, body, *close)?;
+                    if *static_ {
+                        format!("static {}", method)
+                    } else {
+                        method
+                    }
+                }
+                _ => stmt.accept(self)?,
+            };
+            let blank_before = self.blank_lines.contains(&(start.line - 1));
+            self.line(&mut r, &text, blank || blank_before);
+        }
+        self.comments(&mut r, close, false);
+        Ok(r)
+    }
+
+    fn block(&mut self, stmts: &[Stmt], close: Span) -> Result<String, Error> {
+        let trailing = self.trailing(stmts.first().map_or(close, Stmt::start));
+        self.level += 1;
+        let body = self.stmts(stmts, Some(close), false);
+        self.level -= 1;
+        Ok(self.braced(&trailing, &body?))
+    }
+
+    // The body of an if, while or for: a block, or a statement on the same line
+    fn body(&mut self, stmt: &Stmt) -> Result<String, Error> {
+        match stmt {
+            Stmt::Block { stmts, close } => self.block(stmts, *close),
+            _ => stmt.accept(self),
+        }
+    }
+
+    // The condition of an if or while: cond, or var x = cond with a binding
+    fn condition(&mut self, binding: &Option<Token>, cond: &Expr) -> Result<String, Error> {
+        let cond = cond.accept(self)?;
+        Ok(match binding {
+            Some(name) => format!("var {} = {}", name.lexeme, cond),
+            None => cond,
+        })
+    }
+
+    // The arms of a match, `patterns if guard => body`, at the current level
+    fn arms(&mut self, arms: &[Arm], else_: &Option<Stmt>, close: Span) -> Result<String, Error> {
+        let mut r = String::new();
+        for arm in arms {
+            let blank = self.comments(&mut r, Some(arm.patterns[0].start()), false);
+            let mut text = arm
+                .patterns
+                .iter()
+                .map(|p| p.accept(self))
+                .collect::<Result<Vec<String>, Error>>()?
+                .join(", ");
+            if let Some(guard) = &arm.guard {
+                text.push_str(&format!(" if {}", guard.accept(self)?));
+            }
+            let text = format!("{} => {}", text, self.body(&arm.body)?);
+            self.line(&mut r, &text, blank);
+        }
+        if let Some(else_) = else_ {
+            let blank = self.comments(&mut r, Some(else_.start()), false);
+            let text = format!("else => {}", self.body(else_)?);
+            self.line(&mut r, &text, blank);
+        }
+        self.comments(&mut r, Some(close), false);
+        Ok(r)
+    }
+
+    // name(params) { body } - functions add the fn keyword, methods don't, and
+    // getters have no parameter list
+    fn function(
+        &mut self,
+        name: &Token,
+        params: &Option<Vec<Param>>,
+        ret: &Option<Token>,
+        body: &[Stmt],
+        close: Span,
+    ) -> Result<String, Error> {
+        let signature = match (params, ret) {
+            (Some(params), _) => signature(params, ret),
+            (None, Some(ret)) => format!(": {}", ret.lexeme),
+            (None, None) => String::new(),
+        };
+        Ok(format!(
+            "{}{} {}",
+            name.lexeme,
+            signature,
+            self.block(body, close)?
+        ))
+    }
+
+    // An expression on a line where `used` columns already go to the indentation and
+    // the text around it. If it doesn't fit, it is printed again breaking calls.
+    fn fitted(&mut self, expr: &Expr, used: usize) -> Result<String, Error> {
+        let flat = expr.accept(self)?;
+        self.excess = (used + first_line_width(&flat)).saturating_sub(self.width);
+        if self.excess == 0 {
+            return Ok(flat);
+        }
+        let r = expr.accept(self);
+        self.excess = 0;
+        r
+    }
+
+    // The statement `head expr;` as in print, return and expression statements
+    fn simple(&mut self, head: &str, expr: &Expr) -> Result<String, Error> {
+        let used = self.pad().len() + head.len() + 1;
+        Ok(format!("{}{};", head, self.fitted(expr, used)?))
+    }
+}
+
+// name: Type, or just the name without an annotation
+fn typed(name: &Token, ty: &Option<Token>) -> String {
+    match ty {
+        Some(ty) => format!("{}: {}", name.lexeme, ty.lexeme),
+        None => name.lexeme.to_string(),
+    }
+}
+
+// (a: Number, b): Number
+fn signature(params: &[Param], ret: &Option<Token>) -> String {
+    let params: Vec<String> = params.iter().map(|p| typed(&p.name, &p.ty)).collect();
+    match ret {
+        Some(ret) => format!("({}): {}", params.join(", "), ret.lexeme),
+        None => format!("({})", params.join(", ")),
+    }
+}
+
+// break; or break label;
+fn jump(keywd: &str, label: &Option<Token>) -> String {
+    match label {
+        Some(label) => format!("{} {};", keywd, label.lexeme),
+        None => format!("{};", keywd),
+    }
+}
+
+fn is_declaration(stmt: &Stmt) -> bool {
+    matches!(
+        stmt.declaration(),
+        Stmt::Function { .. } | Stmt::Class { .. }
+    )
+}
+
+fn first_line_width(text: &str) -> usize {
+    text.lines().next().map_or(0, |line| line.chars().count())
+}
+
+impl expr_ast::Visitor<String> for Formatter {
+    fn visit_assign_expr(&mut self, name: &Token, op: &Token, val: &Expr) -> Result<String, Error> {
+        Ok(format!(
+            "{} {} {}",
+            name.lexeme,
+            op.lexeme,
+            val.accept(self)?
+        ))
+    }
+
+    fn visit_binary_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<String, Error> {
+        let lhs = lhs.accept(self)?;
+        Ok(format!("{} {} {}", lhs, op.lexeme, rhs.accept(self)?))
+    }
+
+    // Breaks the arguments over several lines when that brings the line back
+    // under the width, otherwise leaves the chance to a call inside them.
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        _paren: &Token,
+        arg: &[Expr],
+    ) -> Result<String, Error> {
+        let excess = std::mem::replace(&mut self.excess, 0);
+        let callee = callee.accept(self)?;
+        let flat = arg
+            .iter()
+            .map(|a| a.accept(self))
+            .collect::<Result<Vec<String>, Error>>()?
+            .join(", ");
+        if excess == 0 || flat.chars().count() < excess {
+            self.excess = excess;
+            if excess == 0 {
+                return Ok(format!("{}({})", callee, flat));
+            }
+            let args = arg
+                .iter()
+                .map(|a| a.accept(self))
+                .collect::<Result<Vec<String>, Error>>()?;
+            return Ok(format!("{}({})", callee, args.join(", ")));
+        }
+
+        self.level += 1;
+        let mut lines = Vec::new();
+        for a in arg {
+            let used = self.pad().len() + 1;
+            lines.push(format!("{}{}", self.pad(), self.fitted(a, used)?));
+        }
+        self.level -= 1;
+        Ok(format!(
+            "{}(\n{}\n{})",
+            callee,
+            lines.join(",\n"),
+            self.pad()
+        ))
+    }
+
+    fn visit_conditional_expr(
+        &mut self,
+        cond: &Expr,
+        then_: &Expr,
+        else_: &Expr,
+    ) -> Result<String, Error> {
+        let cond = cond.accept(self)?;
+        let then_ = then_.accept(self)?;
+        Ok(format!("{} ? {} : {}", cond, then_, else_.accept(self)?))
+    }
+
+    fn visit_get_expr(&mut self, obj: &Expr, name: &Token) -> Result<String, Error> {
+        Ok(format!("{}.{}", obj.accept(self)?, name.lexeme))
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<String, Error> {
+        Ok(format!("({})", expr.accept(self)?))
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+    ) -> Result<String, Error> {
+        let obj = obj.accept(self)?;
+        Ok(format!("{}[{}]", obj, index.accept(self)?))
+    }
+
+    fn visit_index_set_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<String, Error> {
+        let obj = obj.accept(self)?;
+        let index = index.accept(self)?;
+        Ok(format!(
+            "{}[{}] {} {}",
+            obj,
+            index,
+            op.lexeme,
+            val.accept(self)?
+        ))
+    }
+
+    // The body is indented one level past the statement the function is in
+    fn visit_lambda_expr(
+        &mut self,
+        _keywd: &Token,
+        params: &[Param],
+        ret: &Option<Token>,
+        body: &[Stmt],
+        close: Span,
+    ) -> Result<String, Error> {
+        Ok(format!(
+            "fn {} {}",
+            signature(params, ret),
+            self.block(body, close)?
+        ))
+    }
+
+    fn visit_list_expr(&mut self, _bracket: &Token, elems: &[Expr]) -> Result<String, Error> {
+        let elems = elems
+            .iter()
+            .map(|e| e.accept(self))
+            .collect::<Result<Vec<String>, Error>>()?;
+        Ok(format!("[{}]", elems.join(", ")))
+    }
+
+    // As written. A heredoc runs up to its closing tag line, so what follows it
+    // has to start on the next line.
+    fn visit_literal_expr(&self, token: &Token, _val: &LiteralValue) -> Result<String, Error> {
+        if token.lexeme.starts_with("<<~") {
+            return Ok(format!("{}\n{}", token.lexeme, self.pad()));
+        }
+        Ok(token.lexeme.to_string())
+    }
+
+    fn visit_logical_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<String, Error> {
+        let lhs = lhs.accept(self)?;
+        Ok(format!("{} {} {}", lhs, op.lexeme, rhs.accept(self)?))
+    }
+
+    fn visit_map_expr(
+        &mut self,
+        _brace: &Token,
+        entries: &[(Expr, Expr)],
+    ) -> Result<String, Error> {
+        let mut items = Vec::new();
+        for (key, val) in entries {
+            let key = key.accept(self)?;
+            items.push(format!("{}: {}", key, val.accept(self)?));
+        }
+        Ok(format!("{{{}}}", items.join(", ")))
+    }
+
+    fn visit_set_expr(
+        &mut self,
+        obj: &Expr,
+        name: &Token,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<String, Error> {
+        let obj = obj.accept(self)?;
+        Ok(format!(
+            "{}.{} {} {}",
+            obj,
+            name.lexeme,
+            op.lexeme,
+            val.accept(self)?
+        ))
+    }
+
+    fn visit_slice_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        from: &Option<Expr>,
+        to: &Option<Expr>,
+    ) -> Result<String, Error> {
+        let mut r = format!("{}[", obj.accept(self)?);
+        if let Some(from) = from {
+            r.push_str(&from.accept(self)?);
+        }
+        r.push(':');
+        if let Some(to) = to {
+            r.push_str(&to.accept(self)?);
+        }
+        r.push(']');
+        Ok(r)
+    }
+
+    fn visit_super_expr(&mut self, _keywd: &Token, method: &Token) -> Result<String, Error> {
+        Ok(format!("super.{}", method.lexeme))
+    }
+
+    fn visit_this_expr(&mut self, _keywd: &Token) -> Result<String, Error> {
+        Ok("this".to_string())
+    }
+
+    fn visit_unary_expr(&mut self, op: &Token, rhs: &Expr) -> Result<String, Error> {
+        let rhs = rhs.accept(self)?;
+        // - -a and - --a must not run together into --
+        if op.lexeme.as_str() == "-" && rhs.starts_with('-') {
+            return Ok(format!("- {}", rhs));
+        }
+        Ok(format!("{}{}", op.lexeme, rhs))
+    }
+
+    fn visit_update_expr(
+        &mut self,
+        op: &Token,
+        target: &Expr,
+        prefix: bool,
+    ) -> Result<String, Error> {
+        let target = target.accept(self)?;
+        if prefix {
+            Ok(format!("{}{}", op.lexeme, target))
+        } else {
+            Ok(format!("{}{}", target, op.lexeme))
+        }
+    }
+
+    fn visit_variable_expr(&mut self, name: &Token) -> Result<String, Error> {
+        Ok(name.lexeme.to_string())
+    }
+}
+
+impl stmt_ast::Visitor<String> for Formatter {
+    fn visit_assert_stmt(
+        &mut self,
+        _keywd: &Token,
+        cond: &Expr,
+        message: &Option<Expr>,
+    ) -> Result<String, Error> {
+        match message {
+            Some(message) => Ok(format!(
+                "assert {}, {};",
+                cond.accept(self)?,
+                message.accept(self)?
+            )),
+            None => self.simple("assert ", cond),
+        }
+    }
+
+    fn visit_block_stmt(&mut self, stmts: &[Stmt], close: Span) -> Result<String, Error> {
+        self.block(stmts, close)
+    }
+
+    fn visit_break_stmt(&mut self, _keywd: &Token, label: &Option<Token>) -> Result<String, Error> {
+        Ok(jump("break", label))
+    }
+
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        sclass: &Option<Expr>,
+        fields: &[Stmt],
+        methods: &[Stmt],
+        close: Span,
+    ) -> Result<String, Error> {
+        let mut r = format!("class {} ", name.lexeme);
+        if let Some(sclass) = sclass {
+            r.push_str(&format!("< {} ", sclass.accept(self)?));
+        }
+        // fields and methods stay in the order they were written in
+        let mut members: Vec<&Stmt> = fields.iter().chain(methods).collect();
+        members.sort_by_key(|member| member.start().start);
+        let trailing = self.trailing(members.first().map_or(close, |member| member.start()));
+        self.level += 1;
+        let body = self.stmts(&members, Some(close), true);
+        self.level -= 1;
+        r.push_str(&self.braced(&trailing, &body?));
+        Ok(r)
+    }
+
+    fn visit_continue_stmt(
+        &mut self,
+        _keywd: &Token,
+        label: &Option<Token>,
+    ) -> Result<String, Error> {
+        Ok(jump("continue", label))
+    }
+
+    fn visit_export_stmt(&mut self, _keywd: &Token, decl: &Stmt) -> Result<String, Error> {
+        Ok(format!("export {}", decl.accept(self)?))
+    }
+
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<String, Error> {
+        self.simple("", expr)
+    }
+
+    fn visit_for_stmt(
+        &mut self,
+        init: &Option<Stmt>,
+        cond: &Option<Expr>,
+        incr: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<String, Error> {
+        let mut r = "for (".to_string();
+        match init {
+            Some(init) => r.push_str(&init.accept(self)?),
+            None => r.push(';'),
+        }
+        if let Some(cond) = cond {
+            r.push(' ');
+            r.push_str(&cond.accept(self)?);
+        }
+        r.push(';');
+        if let Some(incr) = incr {
+            r.push(' ');
+            r.push_str(&incr.accept(self)?);
+        }
+        r.push_str(") ");
+        r.push_str(&self.body(body)?);
+        Ok(r)
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        name: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+    ) -> Result<String, Error> {
+        let iterable = iterable.accept(self)?;
+        Ok(format!(
+            "for ({} in {}) {}",
+            name.lexeme,
+            iterable,
+            self.body(body)?
+        ))
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        name: &Token,
+        params: &Option<Vec<Param>>,
+        ret: &Option<Token>,
+        body: &[Stmt],
+        close: Span,
+        _static: bool,
+    ) -> Result<String, Error> {
+        Ok(format!(
+            "fn {}",
+            self.function(name, params, ret, body, close)?
+        ))
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        else_: &Option<Stmt>,
+        then_: &Stmt,
+    ) -> Result<String, Error> {
+        let cond = self.condition(binding, cond)?;
+        let mut r = format!("if ({}) {}", cond, self.body(then_)?);
+        if let Some(else_) = else_ {
+            // } else {  but a statement on its own line before the else
+            if matches!(then_, Stmt::Block { .. }) {
+                r.push(' ');
+            } else {
+                r.push_str(&self.trailing(else_.start()));
+                r.push('\n');
+                r.push_str(&self.pad());
+            }
+            r.push_str("else ");
+            r.push_str(&self.body(else_)?);
+        }
+        Ok(r)
+    }
+
+    fn visit_import_stmt(
+        &mut self,
+        _keywd: &Token,
+        path: &Token,
+        alias: &Option<Token>,
+        names: &[Token],
+    ) -> Result<String, Error> {
+        Ok(match alias {
+            Some(alias) => format!("import {} as {};", path.lexeme, alias.lexeme),
+            None => {
+                let names: Vec<&str> = names.iter().map(|n| n.lexeme.as_str()).collect();
+                format!("from {} import {};", path.lexeme, names.join(", "))
+            }
+        })
+    }
+
+    fn visit_labeled_stmt(&mut self, label: &Token, body: &Stmt) -> Result<String, Error> {
+        Ok(format!("{}: {}", label.lexeme, body.accept(self)?))
+    }
+
+    // One arm per line, a level deeper than the match
+    fn visit_match_stmt(
+        &mut self,
+        _keywd: &Token,
+        subject: &Expr,
+        arms: &[Arm],
+        else_: &Option<Stmt>,
+        close: Span,
+    ) -> Result<String, Error> {
+        let subject = subject.accept(self)?;
+        let first = match (arms.first(), else_) {
+            (Some(arm), _) => arm.patterns[0].start(),
+            (None, Some(else_)) => else_.start(),
+            (None, None) => close,
+        };
+        let trailing = self.trailing(first);
+        self.level += 1;
+        let body = self.arms(arms, else_, close);
+        self.level -= 1;
+        Ok(format!(
+            "match ({}) {}",
+            subject,
+            self.braced(&trailing, &body?)
+        ))
+    }
+
+    fn visit_print_stmt(&mut self, expr: &Expr, rest: &[Expr]) -> Result<String, Error> {
+        if rest.is_empty() {
+            return self.simple("print ", expr);
+        }
+        let exprs = std::iter::once(expr)
+            .chain(rest)
+            .map(|e| e.accept(self))
+            .collect::<Result<Vec<String>, Error>>()?;
+        Ok(format!("print {};", exprs.join(", ")))
+    }
+
+    fn visit_return_stmt(&mut self, _keywd: &Token, val: &Option<Expr>) -> Result<String, Error> {
+        match val {
+            Some(val) => self.simple("return ", val),
+            None => Ok("return;".to_string()),
+        }
+    }
+
+    fn visit_throw_stmt(&mut self, _keywd: &Token, val: &Expr) -> Result<String, Error> {
+        self.simple("throw ", val)
+    }
+
+    // try {
+    // } catch (e) {
+    // } finally {
+    // }
+    fn visit_try_stmt(
+        &mut self,
+        _keywd: &Token,
+        body: &Stmt,
+        catch: &Option<(Token, Stmt)>,
+        finally: &Option<Stmt>,
+    ) -> Result<String, Error> {
+        let mut r = format!("try {}", self.body(body)?);
+        if let Some((name, body)) = catch {
+            r.push_str(&format!(" catch ({}) {}", name.lexeme, self.body(body)?));
+        }
+        if let Some(finally) = finally {
+            r.push_str(&format!(" finally {}", self.body(finally)?));
+        }
+        Ok(r)
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        name: &Token,
+        ty: &Option<Token>,
+        init: &Option<Expr>,
+    ) -> Result<String, Error> {
+        let name = typed(name, ty);
+        match init {
+            Some(init) => self.simple(&format!("var {} = ", name), init),
+            None => Ok(format!("var {};", name)),
+        }
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        body: &Stmt,
+    ) -> Result<String, Error> {
+        Ok(format!(
+            "while ({}) {}",
+            self.condition(binding, cond)?,
+            self.body(body)?
+        ))
+    }
+}
+
+// A unified diff of two versions of a file, for `lako fmt --check`
+pub fn diff(path: &str, old: &str, new: &str) -> String {
+    const CONTEXT: usize = 3;
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+
+    // lcs[i][j]: length of the longest common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    // (' ' | '-' | '+', line, its index in a, its index in b)
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push((' ', a[i], i, j));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', a[i], i, j));
+            i += 1;
+        } else {
+            lines.push(('+', b[j], i, j));
+            j += 1;
+        }
+    }
+
+    // each change with CONTEXT lines around it, merged with the hunk before when
+    // they touch or overlap
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (k, line) in lines.iter().enumerate() {
+        if line.0 == ' ' {
+            continue;
+        }
+        let (from, to) = (
+            k.saturating_sub(CONTEXT),
+            (k + 1 + CONTEXT).min(lines.len()),
+        );
+        match hunks.last_mut() {
+            Some(last) if from <= last.1 => last.1 = to,
+            _ => hunks.push((from, to)),
+        }
+    }
+
+    let mut r = format!("--- {}\n+++ {}\n", path, path);
+    for (from, to) in hunks {
+        let hunk = &lines[from..to];
+        let old_len = hunk.iter().filter(|l| l.0 != '+').count();
+        let new_len = hunk.iter().filter(|l| l.0 != '-').count();
+        r.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk[0].2 + 1,
+            old_len,
+            hunk[0].3 + 1,
+            new_len
+        ));
+        for (kind, line, _, _) in hunk {
+            r.push_str(&format!("{}{}\n", kind, line));
+        }
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::expr_ast::AstPrinter;
+    use crate::frontend::parser::Parser;
+    use crate::frontend::scanner::Scanner;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    fn fmt(source: &str) -> String {
+        Formatter::default().format(&parse(source)).unwrap()
+    }
+
+    fn fmt_with_trivia(source: &str) -> String {
+        let tokens = Scanner::with_trivia(source.to_string())
+            .scan_tokens()
+            .unwrap();
+        let stmts = Parser::new(tokens.clone()).parse().unwrap();
+        Formatter::default()
+            .with_trivia(&tokens)
+            .format(&stmts)
+            .unwrap()
+    }
+
+    #[test]
+    fn canonical_layout() {
+        let source = "var a=1;fn add(x,y){return x+y;}\nclass B<A{init(n){this.n=n;}get(){return this.n;}}\nif(a>0)print -a;else{a+=1;}\nwhile(a<3)a++;\nfor(var i=0;i<3;i=i+1){print i;}\nfor(;;){}\nouter:while(a){break outer;continue;}\nmatch(x){1,2=>{}n if n>2=>print n;}\nwhile(var l=f())print l;";
+        assert_eq!(
+            fmt(source),
+            "var a = 1;
+
+fn add(x, y) {
+    return x + y;
+}
+
+class B < A {
+    init(n) {
+        this.n = n;
+    }
+
+    get() {
+        return this.n;
+    }
+}
+
+if (a > 0) print -a;
+else {
+    a += 1;
+}
+while (a < 3) a++;
+for (var i = 0; i < 3; i = i + 1) {
+    print i;
+}
+for (;;) {}
+outer: while (a) {
+    break outer;
+    continue;
+}
+match (x) {
+    1, 2 => {}
+    n if n > 2 => print n;
+}
+while (var l = f()) print l;
+"
+        );
+    }
+
+    #[test]
+    fn keeps_the_source_spelling() {
+        let source = "print (1 + 2) * 0xFF_FF;\nprint - -a;\nprint -(--a);\nprint !(a ? b : c);\nvar s = <<~END\n  text\n  END\n;\nprint \"a  b\";\n";
+        assert_eq!(fmt(source), source);
+    }
+
+    #[test]
+    fn type_annotations() {
+        let source =
+            "var n:Number=1;\nfn add(a:Number,b):Number{return a+b;}\nvar f=fn(x:Bool):Nil{};";
+        assert_eq!(
+            fmt(source),
+            "var n: Number = 1;
+
+fn add(a: Number, b): Number {
+    return a + b;
+}
+
+var f = fn (x: Bool): Nil {};
+"
+        );
+    }
+
+    #[test]
+    fn long_calls_are_broken() {
+        let mut formatter = Formatter::new(2, 30);
+        let stmts = parse("if (ok) { print greet(\"a long first name\", last(name, 1)); }");
+        assert_eq!(
+            formatter.format(&stmts).unwrap(),
+            "if (ok) {
+  print greet(
+    \"a long first name\",
+    last(name, 1)
+  );
+}
+"
+        );
+    }
+
+    #[test]
+    fn formatting_keeps_the_meaning_and_is_stable() {
+        let source = "fn f(a){if(a)return a--;else return --a;}\nvar x=f(1)+f(2)*-3;o.f-=x>1?x:1;\nprint o.f != !x | nil;\nvar l=[1,[x],f(2),];l[0]+=l[1][0];\nprint {\"a\":l,2:{}}[2];\nprint l[1:]+l[:x-1]+l[:];";
+        let formatted = fmt(source);
+        assert_eq!(fmt(&formatted), formatted);
+
+        let mut printer = AstPrinter;
+        let print = |stmts: Vec<Stmt>, printer: &mut AstPrinter| -> Vec<String> {
+            stmts
+                .iter()
+                .map(|s| printer.print_stmt(s).unwrap())
+                .collect()
+        };
+        assert_eq!(
+            print(parse(source), &mut printer),
+            print(parse(&formatted), &mut printer)
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        let source = "#!/usr/bin/env lako\n// greeting\nvar a = 1; // one\n\n\n\nprint a;\n// doc\nfn f() {\n    // nothing yet\n}\nif (a) {\n    a = 2;\n    // after\n} // end\nprint f(a, // odd\n    2);\nclass A {\n\n    m() {}\n    // last\n}\n";
+        let formatted = fmt_with_trivia(source);
+        assert_eq!(
+            formatted,
+            "#!/usr/bin/env lako
+// greeting
+var a = 1; // one
+
+print a;
+
+// doc
+fn f() {
+    // nothing yet
+}
+
+if (a) {
+    a = 2;
+    // after
+} // end
+print f(a, 2); // odd
+
+class A {
+    m() {}
+    // last
+}
+"
+        );
+        assert_eq!(fmt_with_trivia(&formatted), formatted);
+    }
+
+    #[test]
+    fn class_fields() {
+        assert_eq!(
+            fmt("class P{var x=0;var y:Number=1;init(){}}"),
+            "class P {\n    var x = 0;\n    var y: Number = 1;\n\n    init() {}\n}\n"
+        );
+        // fields and methods keep their order, and their comments
+        let source =
+            "class P {\n    // origin\n    var x = 0; // x\n\n    m() {}\n\n    var y = 0;\n}\n";
+        assert_eq!(fmt_with_trivia(source), source);
+    }
+
+    #[test]
+    fn imports() {
+        assert_eq!(
+            fmt("import \"utils.lako\"as u;from\"utils.lako\"import clamp,lerp;print u;"),
+            "import \"utils.lako\" as u;\nfrom \"utils.lako\" import clamp, lerp;\nprint u;\n"
+        );
+    }
+
+    #[test]
+    fn exports() {
+        assert_eq!(
+            fmt("export var a=1;export fn f(){}export class C{}"),
+            "export var a = 1;\n\nexport fn f() {}\n\nexport class C {}\n"
+        );
+    }
+
+    #[test]
+    fn static_methods() {
+        assert_eq!(
+            fmt("class M{static square(n){return n*n;}}"),
+            "class M {\n    static square(n) {\n        return n * n;\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn getters() {
+        assert_eq!(
+            fmt("class C{area:Number{return 1;}static n{return 2;}}"),
+            "class C {\n    area: Number {\n        return 1;\n    }\n\n    static n {\n        return 2;\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn comments_stay_on_the_line_of_their_code() {
+        insta::assert_snapshot!(
+            "comment_in_parameters",
+            fmt_with_trivia("fn f(a, // first\n  b) {\n  print a;\n}\n")
+        );
+        insta::assert_snapshot!(
+            "comment_after_condition",
+            fmt_with_trivia(
+                "if (a) // after cond\n{\n  print 1;\n} else {\n  print 2;\n}\n\
+                 if (a) // after cond\n  print 1;\nelse\n  print 2;\n"
+            )
+        );
+    }
+
+    #[test]
+    fn unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+        assert_eq!(
+            diff("f.lak", old, new),
+            "--- f.lak\n+++ f.lak\n@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n@@ -8,3 +8,4 @@\n h\n i\n j\n+k\n"
+        );
+        assert_eq!(diff("f.lak", old, old), "--- f.lak\n+++ f.lak\n");
+        // changes 2 * 3 lines apart share their context, in one hunk
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\nI\nj\n";
+        assert_eq!(
+            diff("f.lak", old, new),
+            "--- f.lak\n+++ f.lak\n@@ -1,10 +1,10 @@\n a\n-b\n+B\n c\n d\n e\n f\n g\n h\n-i\n+I\n j\n"
+        );
+    }
+
+    // Random programs for the round trip below. Operands that aren't atoms are
+    // grouped, the way the parser builds them from source.
+    mod generate {
+        use crate::frontend::expr_ast::{Expr, LiteralValue};
+        use crate::frontend::interner::Symbol;
+        use crate::frontend::stmt_ast::{Param, Stmt};
+        use crate::frontend::token::{Literal, Span, Token, TokenType};
+        use proptest::prelude::*;
+
+        fn token(t_type: TokenType, lexeme: &str) -> Token {
+            Token::new(t_type, lexeme, Span::default())
+        }
+
+        fn name() -> impl Strategy<Value = Token> {
+            prop::sample::select(vec!["a", "b", "total", "f", "xs"])
+                .prop_map(|name| token(TokenType::Identifier, name))
+        }
+
+        fn literal() -> impl Strategy<Value = Expr> {
+            let number = (0u32..100_000).prop_map(|n| {
+                let mut token = token(TokenType::Number, &n.to_string());
+                token.literal = Some(Literal::Number(n as f64));
+                Expr::Literal {
+                    token,
+                    val: LiteralValue::Number(n as f64),
+                }
+            });
+            let string = "[a-z ]{0,8}".prop_map(|s| {
+                let mut token = token(TokenType::String, &format!("\"{}\"", s));
+                token.literal = Some(Literal::String(Symbol::from(s.as_str())));
+                Expr::Literal {
+                    token,
+                    val: LiteralValue::String(Symbol::from(s.as_str())),
+                }
+            });
+            let keyword = prop::sample::select(vec![
+                (TokenType::True, "true", LiteralValue::Boolean(true)),
+                (TokenType::False, "false", LiteralValue::Boolean(false)),
+                (TokenType::Nil, "nil", LiteralValue::Nil),
+            ])
+            .prop_map(|(t_type, lexeme, val)| Expr::Literal {
+                token: token(t_type, lexeme),
+                val,
+            });
+            prop_oneof![number, string, keyword]
+        }
+
+        fn atom() -> impl Strategy<Value = Expr> {
+            prop_oneof![literal(), name().prop_map(|name| Expr::Variable { name })]
+        }
+
+        // An atom stays as it is, anything else goes in parentheses
+        fn operand(expr: Expr) -> Box<Expr> {
+            Box::new(match expr {
+                Expr::Literal { .. } | Expr::Variable { .. } | Expr::Grouping { .. } => expr,
+                expr => Expr::Grouping {
+                    expr: Box::new(expr),
+                },
+            })
+        }
+
+        pub fn expr() -> impl Strategy<Value = Expr> {
+            let binary = prop::sample::select(vec![
+                (TokenType::Plus, "+"),
+                (TokenType::Minus, "-"),
+                (TokenType::Star, "*"),
+                (TokenType::Slash, "/"),
+                (TokenType::Percent, "%"),
+                (TokenType::EqualEqual, "=="),
+                (TokenType::BangEqual, "!="),
+                (TokenType::Less, "<"),
+                (TokenType::GreaterEqual, ">="),
+                (TokenType::Ampersand, "&"),
+                (TokenType::Pipe, "|"),
+                (TokenType::Caret, "^"),
+                (TokenType::LessLess, "<<"),
+            ]);
+            let unary = prop::sample::select(vec![
+                (TokenType::Minus, "-"),
+                (TokenType::Bang, "!"),
+                (TokenType::Tilde, "~"),
+            ]);
+            atom().prop_recursive(4, 32, 4, move |inner| {
+                prop_oneof![
+                    (inner.clone(), binary.clone(), inner.clone()).prop_map(
+                        |(lhs, (t_type, op), rhs)| Expr::Binary {
+                            lhs: operand(lhs),
+                            op: token(t_type, op),
+                            rhs: operand(rhs),
+                        }
+                    ),
+                    (unary.clone(), inner.clone()).prop_map(|((t_type, op), rhs)| {
+                        Expr::Unary {
+                            op: token(t_type, op),
+                            rhs: operand(rhs),
+                        }
+                    }),
+                    (inner.clone(), inner.clone(), inner.clone()).prop_map(
+                        |(cond, then_, else_)| Expr::Conditional {
+                            cond: operand(cond),
+                            then_: operand(then_),
+                            else_: operand(else_),
+                        }
+                    ),
+                    (name(), prop::collection::vec(inner.clone(), 0..4)).prop_map(|(name, arg)| {
+                        Expr::Call {
+                            callee: Box::new(Expr::Variable { name }),
+                            paren: token(TokenType::LeftParen, "("),
+                            arg,
+                        }
+                    }),
+                    (inner.clone(), name()).prop_map(|(obj, name)| Expr::Get {
+                        obj: operand(obj),
+                        name,
+                    }),
+                    (inner.clone(), inner.clone()).prop_map(|(obj, index)| Expr::Index {
+                        obj: operand(obj),
+                        bracket: token(TokenType::LeftBracket, "["),
+                        index: Box::new(index),
+                    }),
+                    prop::collection::vec(inner.clone(), 0..4).prop_map(|elems| Expr::List {
+                        bracket: token(TokenType::LeftBracket, "["),
+                        elems,
+                    }),
+                    (name(), inner).prop_map(|(name, val)| Expr::Assign {
+                        name,
+                        op: token(TokenType::Equal, "="),
+                        val: Box::new(val),
+                    }),
+                ]
+            })
+        }
+
+        fn block(stmts: Vec<Stmt>) -> Stmt {
+            Stmt::Block {
+                stmts,
+                close: Span::default(),
+            }
+        }
+
+        pub fn stmt() -> impl Strategy<Value = Stmt> {
+            let simple = prop_oneof![
+                expr().prop_map(|expr| Stmt::Expression { expr }),
+                (expr(), prop::collection::vec(expr(), 0..3))
+                    .prop_map(|(expr, rest)| Stmt::Print { expr, rest }),
+                (name(), prop::option::of(expr())).prop_map(|(name, init)| Stmt::Var {
+                    name,
+                    ty: None,
+                    init,
+                }),
+                prop::option::of(expr()).prop_map(|val| Stmt::Return {
+                    keywd: token(TokenType::Return, "return"),
+                    val,
+                }),
+                prop::option::of(name()).prop_map(|label| Stmt::Break {
+                    keywd: token(TokenType::Break, "break"),
+                    label,
+                }),
+            ];
+            simple.prop_recursive(3, 24, 4, |inner| {
+                let body = prop::collection::vec(inner.clone(), 0..4);
+                prop_oneof![
+                    body.clone().prop_map(block),
+                    // a nested if without braces would take the outer else
+                    (expr(), body.clone(), prop::option::of(body.clone())).prop_map(
+                        |(cond, then_, else_)| Stmt::If {
+                            binding: None,
+                            cond,
+                            then_: Box::new(block(then_)),
+                            else_: Box::new(else_.map(block)),
+                        }
+                    ),
+                    // declarations only go in blocks
+                    (expr(), body.clone()).prop_map(|(cond, body)| Stmt::While {
+                        binding: None,
+                        cond,
+                        body: Box::new(block(body)),
+                    }),
+                    (name(), expr(), body.clone()).prop_map(|(label, cond, body)| {
+                        Stmt::Labeled {
+                            label,
+                            body: Box::new(Stmt::While {
+                                binding: None,
+                                cond,
+                                body: Box::new(block(body)),
+                            }),
+                        }
+                    }),
+                    (name(), prop::collection::vec(name(), 0..3), body).prop_map(
+                        |(name, params, body)| Stmt::Function {
+                            name,
+                            params: Some(
+                                params
+                                    .into_iter()
+                                    .map(|name| Param { name, ty: None })
+                                    .collect(),
+                            ),
+                            ret: None,
+                            body,
+                            close: Span::default(),
+                            static_: false,
+                        }
+                    ),
+                ]
+            })
+        }
+    }
+
+    proptest::proptest! {
+        // Formatting a program and parsing it back gives the same program, and
+        // formatting that again changes nothing
+        #[test]
+        fn format_then_parse_round_trips(
+            stmts in proptest::collection::vec(generate::stmt(), 1..6)
+        ) {
+            let mut printer = AstPrinter;
+            let print = |stmts: &[Stmt], printer: &mut AstPrinter| -> Vec<String> {
+                stmts.iter().map(|s| printer.print_stmt(s).unwrap()).collect()
+            };
+            let source = Formatter::default().format(&stmts).unwrap();
+            let parsed = parse(&source);
+            proptest::prop_assert_eq!(print(&parsed, &mut printer), print(&stmts, &mut printer));
+            let again = Formatter::default().format(&parsed).unwrap();
+            proptest::prop_assert_eq!(&again, &source);
+            proptest::prop_assert_eq!(parse(&again), parsed);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Machine-readable description of the language for editors and external tools.
+// Everything is generated from the tables the Scanner and Parser actually use
+// (KEYWORDS, PUNCTUATORS and PRECEDENCE), so it cannot drift from the implementation.
+
+use crate::frontend::parser::{Assoc, PRECEDENCE};
+use crate::frontend::token::{TokenType, KEYWORDS, PUNCTUATORS};
+
+// Source text of an operator token, looked up in the scanner tables.
+fn lexeme_of(t_type: &TokenType) -> Option<&'static str> {
+    PUNCTUATORS
+        .iter()
+        .find(|(_, tt)| tt == t_type)
+        .map(|(lexeme, _)| *lexeme)
+        .or_else(|| {
+            KEYWORDS
+                .iter()
+                .find(|(_, tt)| *tt == t_type)
+                .map(|(lexeme, _)| *lexeme)
+        })
+}
+
+// A JSON string literal, shared with the other JSON outputs
+pub(crate) fn json_string(s: &str) -> String {
+    let mut r = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            '\n' => r.push_str("\\n"),
+            '\r' => r.push_str("\\r"),
+            '\t' => r.push_str("\\t"),
+            c if c.is_control() => r.push_str(&format!("\\u{:04x}", c as u32)),
+            c => r.push(c),
+        }
+    }
+    r.push('"');
+    r
+}
+
+fn json_token(lexeme: &str, t_type: &TokenType) -> String {
+    format!(
+        "{{\"lexeme\":{},\"token\":{}}}",
+        json_string(lexeme),
+        json_string(&format!("{:?}", t_type))
+    )
+}
+
+// Dumps keywords, operators and the operator precedence levels as a JSON object.
+pub fn to_json() -> String {
+    let mut keywords: Vec<(&&str, &TokenType)> = KEYWORDS.iter().collect();
+    keywords.sort_by_key(|(lexeme, _)| **lexeme);
+    let keywords: Vec<String> = keywords
+        .iter()
+        .map(|(lexeme, tt)| json_token(lexeme, tt))
+        .collect();
+
+    let operators: Vec<String> = PUNCTUATORS
+        .iter()
+        .map(|(lexeme, tt)| json_token(lexeme, tt))
+        .collect();
+
+    let precedence: Vec<String> = PRECEDENCE
+        .iter()
+        .map(|level| {
+            let assoc = match level.assoc {
+                Assoc::Left => "left",
+                Assoc::Right => "right",
+            };
+            let ops: Vec<String> = level
+                .operators
+                .iter()
+                .map(|tt| {
+                    json_string(lexeme_of(tt).expect("Operator missing from scanner tables."))
+                })
+                .collect();
+            format!(
+                "{{\"rule\":{},\"assoc\":{},\"operators\":[{}]}}",
+                json_string(level.rule),
+                json_string(assoc),
+                ops.join(",")
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"keywords\":[{}],\"operators\":[{}],\"precedence\":[{}]}}",
+        keywords.join(","),
+        operators.join(","),
+        precedence.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_precedence_operator_has_a_lexeme() {
+        for level in PRECEDENCE {
+            for op in level.operators {
+                assert!(lexeme_of(op).is_some(), "no lexeme for {:?}", op);
+            }
+        }
+    }
+
+    #[test]
+    fn json_lists_levels_lowest_first() {
+        let json = to_json();
+        assert!(json.contains(r#"{"lexeme":"class","token":"Class"}"#));
+        assert!(json.contains(r#"{"lexeme":"!=","token":"BangEqual"}"#));
+        assert!(json.contains(r#"{"rule":"equality","assoc":"left","operators":["!=","=="]}"#));
+        assert!(json.find(r#""rule":"term""#) < json.find(r#""rule":"factor""#));
+    }
+}
@@ -0,0 +1,137 @@
+// String interning for lexemes and literals.
+//
+// The Scanner interns every identifier, keyword, operator and string literal it
+// produces, so equal text is stored once per source and shared by every token,
+// AST node and later environment that refers to it. A Symbol is a reference-counted
+// pointer into the pool: cloning it is a counter bump, and two symbols from the same
+// Interner are equal exactly when they point at the same string.
+
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+#[derive(Clone, Eq)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+// Interned symbols compare by pointer. The text comparison only runs for symbols
+// made outside an Interner (e.g. with `From<&str>`) or from different Interners.
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0 == other.0
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Symbol {
+        Symbol(Arc::from(s))
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Interner {
+    pool: HashSet<Symbol>,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    // The shared symbol for `s`, adding it to the pool on first use
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(sym) = self.pool.get(s) {
+            return sym.clone();
+        }
+        let sym = Symbol::from(s);
+        self.pool.insert(sym.clone());
+        sym
+    }
+
+    pub fn len(&self) -> usize {
+        self.pool.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pool.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_text_is_stored_once() {
+        let mut interner = Interner::new();
+        let a = interner.intern("count");
+        let b = interner.intern("count");
+        let c = interner.intern("total");
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn symbols_compare_with_text() {
+        let mut interner = Interner::new();
+        let a = interner.intern("école");
+        assert_eq!(a, "école");
+        assert_eq!(a, Symbol::from("école"));
+        assert_eq!(a.to_string(), "école");
+        assert_eq!(format!("{:?}", a), "\"école\"");
+    }
+}
@@ -1,6 +1,18 @@
+pub mod ast_json;
+pub mod cst;
+pub mod diagnostic;
+pub mod dialect;
 pub mod error;
 pub mod expr_ast;
+pub mod formatter;
+pub mod grammar;
+pub mod interner;
+pub mod optimizer;
 pub mod parser;
+pub mod reporter;
+pub mod resolver;
 pub mod scanner;
+pub mod stack;
 pub mod stmt_ast;
 pub mod token;
+pub mod typecheck;
@@ -0,0 +1,683 @@
+// Optimization passes over the AST, run after the resolver when lako is given --opt.
+// Each Pass takes the program and returns a rewritten one with the same meaning;
+// optimize runs them all in order.
+//
+// ConstantFolder evaluates the subexpressions whose operands are all literals:
+//
+// 2 * 3 + 1  ->  7        "a" + "b"  ->  "ab"        !true  ->  false
+//
+// Anything that would fail or depend on the runtime is left for the interpreter:
+// division by zero, operators on mismatched types and the bitwise operators.
+
+use crate::frontend::error::Error;
+use crate::frontend::expr_ast::{self, Expr, LiteralValue};
+use crate::frontend::interner::Symbol;
+use crate::frontend::stmt_ast::{self, Arm, Param, Stmt};
+use crate::frontend::token::{Span, Token, TokenType};
+
+pub trait Pass {
+    fn run(&mut self, stmts: &[Stmt]) -> Result<Vec<Stmt>, Error>;
+}
+
+// Runs every pass over the program
+pub fn optimize(stmts: &[Stmt]) -> Result<Vec<Stmt>, Error> {
+    let passes: Vec<Box<dyn Pass>> = vec![Box::new(ConstantFolder)];
+    passes
+        .into_iter()
+        .try_fold(stmts.to_vec(), |stmts, mut pass| pass.run(&stmts))
+}
+
+pub struct ConstantFolder;
+
+impl Pass for ConstantFolder {
+    fn run(&mut self, stmts: &[Stmt]) -> Result<Vec<Stmt>, Error> {
+        self.stmts(stmts)
+    }
+}
+
+impl ConstantFolder {
+    pub fn expr(&mut self, expr: &Expr) -> Result<Expr, Error> {
+        expr.accept(self)
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<Vec<Stmt>, Error> {
+        stmts.iter().map(|s| s.accept(self)).collect()
+    }
+
+    fn boxed(&mut self, expr: &Expr) -> Result<Box<Expr>, Error> {
+        Ok(Box::new(expr.accept(self)?))
+    }
+
+    fn opt_expr(&mut self, expr: &Option<Expr>) -> Result<Option<Expr>, Error> {
+        expr.as_ref().map(|e| e.accept(self)).transpose()
+    }
+}
+
+fn value(expr: &Expr) -> Option<&LiteralValue> {
+    match expr {
+        Expr::Literal { val, .. } => Some(val),
+        _ => None,
+    }
+}
+
+// nil and false are falsey, everything else is truthy
+fn truthy(val: &LiteralValue) -> bool {
+    !matches!(val, LiteralValue::Nil | LiteralValue::Boolean(false))
+}
+
+fn equal(a: &LiteralValue, b: &LiteralValue) -> bool {
+    match (a, b) {
+        (LiteralValue::Number(a), LiteralValue::Number(b)) => a == b,
+        (LiteralValue::String(a), LiteralValue::String(b)) => a == b,
+        (LiteralValue::Boolean(a), LiteralValue::Boolean(b)) => a == b,
+        (LiteralValue::Nil, LiteralValue::Nil) => true,
+        _ => false,
+    }
+}
+
+fn binary(op: TokenType, a: &LiteralValue, b: &LiteralValue) -> Option<LiteralValue> {
+    use LiteralValue::{Boolean, Number};
+    let val = match (op, a, b) {
+        (TokenType::EqualEqual, a, b) => Boolean(equal(a, b)),
+        (TokenType::BangEqual, a, b) => Boolean(!equal(a, b)),
+        (TokenType::Plus, LiteralValue::String(a), LiteralValue::String(b)) => {
+            LiteralValue::String(Symbol::from(format!("{}{}", a, b).as_str()))
+        }
+        (op, Number(a), Number(b)) => match op {
+            TokenType::Plus => Number(a + b),
+            TokenType::Minus => Number(a - b),
+            TokenType::Star => Number(a * b),
+            TokenType::Slash if *b != 0.0 => Number(a / b),
+            TokenType::Percent if *b != 0.0 => Number(a % b),
+            TokenType::Greater => Boolean(a > b),
+            TokenType::GreaterEqual => Boolean(a >= b),
+            TokenType::Less => Boolean(a < b),
+            TokenType::LessEqual => Boolean(a <= b),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(val)
+}
+
+// A literal for a folded value, placed where the expression started
+fn literal(val: LiteralValue, span: Span) -> Expr {
+    let token = match &val {
+        LiteralValue::Number(n) => Token::new(TokenType::Number, &n.to_string(), span),
+        LiteralValue::String(s) => Token::new(TokenType::String, &format!("\"{}\"", s), span),
+        LiteralValue::Boolean(true) => Token::new(TokenType::True, "true", span),
+        LiteralValue::Boolean(false) => Token::new(TokenType::False, "false", span),
+        LiteralValue::Nil => Token::new(TokenType::Nil, "nil", span),
+    };
+    Expr::Literal { token, val }
+}
+
+impl expr_ast::Visitor<Expr> for ConstantFolder {
+    fn visit_assign_expr(&mut self, name: &Token, op: &Token, val: &Expr) -> Result<Expr, Error> {
+        Ok(Expr::Assign {
+            name: name.clone(),
+            op: op.clone(),
+            val: self.boxed(val)?,
+        })
+    }
+
+    fn visit_binary_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<Expr, Error> {
+        let lhs = lhs.accept(self)?;
+        let rhs = rhs.accept(self)?;
+        if let (Some(a), Some(b)) = (value(&lhs), value(&rhs)) {
+            if let Some(val) = binary(op.t_type, a, b) {
+                return Ok(literal(val, lhs.start()));
+            }
+        }
+        Ok(Expr::Binary {
+            lhs: Box::new(lhs),
+            op: op.clone(),
+            rhs: Box::new(rhs),
+        })
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        arg: &[Expr],
+    ) -> Result<Expr, Error> {
+        Ok(Expr::Call {
+            callee: self.boxed(callee)?,
+            paren: paren.clone(),
+            arg: arg
+                .iter()
+                .map(|a| a.accept(self))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn visit_conditional_expr(
+        &mut self,
+        cond: &Expr,
+        then_: &Expr,
+        else_: &Expr,
+    ) -> Result<Expr, Error> {
+        let cond = cond.accept(self)?;
+        match value(&cond) {
+            Some(val) if truthy(val) => then_.accept(self),
+            Some(_) => else_.accept(self),
+            None => Ok(Expr::Conditional {
+                cond: Box::new(cond),
+                then_: self.boxed(then_)?,
+                else_: self.boxed(else_)?,
+            }),
+        }
+    }
+
+    fn visit_get_expr(&mut self, obj: &Expr, name: &Token) -> Result<Expr, Error> {
+        Ok(Expr::Get {
+            obj: self.boxed(obj)?,
+            name: name.clone(),
+        })
+    }
+
+    // (1 + 2) is just 3
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<Expr, Error> {
+        let expr = expr.accept(self)?;
+        match expr {
+            Expr::Literal { .. } => Ok(expr),
+            expr => Ok(Expr::Grouping {
+                expr: Box::new(expr),
+            }),
+        }
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        obj: &Expr,
+        bracket: &Token,
+        index: &Expr,
+    ) -> Result<Expr, Error> {
+        Ok(Expr::Index {
+            obj: self.boxed(obj)?,
+            bracket: bracket.clone(),
+            index: self.boxed(index)?,
+        })
+    }
+
+    fn visit_index_set_expr(
+        &mut self,
+        obj: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<Expr, Error> {
+        Ok(Expr::IndexSet {
+            obj: self.boxed(obj)?,
+            bracket: bracket.clone(),
+            index: self.boxed(index)?,
+            op: op.clone(),
+            val: self.boxed(val)?,
+        })
+    }
+
+    fn visit_lambda_expr(
+        &mut self,
+        keywd: &Token,
+        params: &[Param],
+        ret: &Option<Token>,
+        body: &[Stmt],
+        close: Span,
+    ) -> Result<Expr, Error> {
+        Ok(Expr::Lambda {
+            keywd: keywd.clone(),
+            params: params.to_vec(),
+            ret: ret.clone(),
+            body: self.stmts(body)?,
+            close,
+        })
+    }
+
+    fn visit_list_expr(&mut self, bracket: &Token, elems: &[Expr]) -> Result<Expr, Error> {
+        Ok(Expr::List {
+            bracket: bracket.clone(),
+            elems: elems
+                .iter()
+                .map(|e| e.accept(self))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn visit_literal_expr(&self, token: &Token, val: &LiteralValue) -> Result<Expr, Error> {
+        Ok(Expr::Literal {
+            token: token.clone(),
+            val: val.clone(),
+        })
+    }
+
+    // and/or give back one of their operands
+    fn visit_logical_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<Expr, Error> {
+        let lhs = lhs.accept(self)?;
+        if let Some(val) = value(&lhs) {
+            let short_circuits = (op.t_type == TokenType::Or) == truthy(val);
+            return if short_circuits {
+                Ok(lhs)
+            } else {
+                rhs.accept(self)
+            };
+        }
+        Ok(Expr::Logical {
+            lhs: Box::new(lhs),
+            op: op.clone(),
+            rhs: self.boxed(rhs)?,
+        })
+    }
+
+    fn visit_map_expr(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> Result<Expr, Error> {
+        let entries = entries
+            .iter()
+            .map(|(key, val)| Ok((key.accept(self)?, val.accept(self)?)))
+            .collect::<Result<_, Error>>()?;
+        Ok(Expr::Map {
+            brace: brace.clone(),
+            entries,
+        })
+    }
+
+    fn visit_set_expr(
+        &mut self,
+        obj: &Expr,
+        name: &Token,
+        op: &Token,
+        val: &Expr,
+    ) -> Result<Expr, Error> {
+        Ok(Expr::Set {
+            obj: self.boxed(obj)?,
+            name: name.clone(),
+            op: op.clone(),
+            val: self.boxed(val)?,
+        })
+    }
+
+    fn visit_slice_expr(
+        &mut self,
+        obj: &Expr,
+        bracket: &Token,
+        from: &Option<Expr>,
+        to: &Option<Expr>,
+    ) -> Result<Expr, Error> {
+        Ok(Expr::Slice {
+            obj: self.boxed(obj)?,
+            bracket: bracket.clone(),
+            from: Box::new(self.opt_expr(from)?),
+            to: Box::new(self.opt_expr(to)?),
+        })
+    }
+
+    fn visit_super_expr(&mut self, keywd: &Token, method: &Token) -> Result<Expr, Error> {
+        Ok(Expr::Super {
+            keywd: keywd.clone(),
+            method: method.clone(),
+        })
+    }
+
+    fn visit_this_expr(&mut self, keywd: &Token) -> Result<Expr, Error> {
+        Ok(Expr::This {
+            keywd: keywd.clone(),
+        })
+    }
+
+    fn visit_unary_expr(&mut self, op: &Token, rhs: &Expr) -> Result<Expr, Error> {
+        let rhs = rhs.accept(self)?;
+        let folded = match (op.t_type, value(&rhs)) {
+            (TokenType::Minus, Some(LiteralValue::Number(n))) => Some(LiteralValue::Number(-n)),
+            (TokenType::Bang, Some(val)) => Some(LiteralValue::Boolean(!truthy(val))),
+            _ => None,
+        };
+        match folded {
+            Some(val) => Ok(literal(val, op.span)),
+            None => Ok(Expr::Unary {
+                op: op.clone(),
+                rhs: Box::new(rhs),
+            }),
+        }
+    }
+
+    fn visit_update_expr(
+        &mut self,
+        op: &Token,
+        target: &Expr,
+        prefix: bool,
+    ) -> Result<Expr, Error> {
+        Ok(Expr::Update {
+            op: op.clone(),
+            target: self.boxed(target)?,
+            prefix,
+        })
+    }
+
+    fn visit_variable_expr(&mut self, name: &Token) -> Result<Expr, Error> {
+        Ok(Expr::Variable { name: name.clone() })
+    }
+}
+
+impl stmt_ast::Visitor<Stmt> for ConstantFolder {
+    fn visit_assert_stmt(
+        &mut self,
+        keywd: &Token,
+        cond: &Expr,
+        message: &Option<Expr>,
+    ) -> Result<Stmt, Error> {
+        Ok(Stmt::Assert {
+            keywd: keywd.clone(),
+            cond: cond.accept(self)?,
+            message: self.opt_expr(message)?,
+        })
+    }
+
+    fn visit_block_stmt(&mut self, stmts: &[Stmt], close: Span) -> Result<Stmt, Error> {
+        Ok(Stmt::Block {
+            stmts: self.stmts(stmts)?,
+            close,
+        })
+    }
+
+    fn visit_break_stmt(&mut self, keywd: &Token, label: &Option<Token>) -> Result<Stmt, Error> {
+        Ok(Stmt::Break {
+            keywd: keywd.clone(),
+            label: label.clone(),
+        })
+    }
+
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        sclass: &Option<Expr>,
+        fields: &[Stmt],
+        methods: &[Stmt],
+        close: Span,
+    ) -> Result<Stmt, Error> {
+        Ok(Stmt::Class {
+            name: name.clone(),
+            sclass: sclass.clone(),
+            fields: self.stmts(fields)?,
+            methods: self.stmts(methods)?,
+            close,
+        })
+    }
+
+    fn visit_continue_stmt(&mut self, keywd: &Token, label: &Option<Token>) -> Result<Stmt, Error> {
+        Ok(Stmt::Continue {
+            keywd: keywd.clone(),
+            label: label.clone(),
+        })
+    }
+
+    fn visit_export_stmt(&mut self, keywd: &Token, decl: &Stmt) -> Result<Stmt, Error> {
+        Ok(Stmt::Export {
+            keywd: keywd.clone(),
+            decl: Box::new(decl.accept(self)?),
+        })
+    }
+
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<Stmt, Error> {
+        Ok(Stmt::Expression {
+            expr: expr.accept(self)?,
+        })
+    }
+
+    fn visit_for_stmt(
+        &mut self,
+        init: &Option<Stmt>,
+        cond: &Option<Expr>,
+        incr: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<Stmt, Error> {
+        let init = init.as_ref().map(|s| s.accept(self)).transpose()?;
+        Ok(Stmt::For {
+            init: Box::new(init),
+            cond: self.opt_expr(cond)?,
+            incr: self.opt_expr(incr)?,
+            body: Box::new(body.accept(self)?),
+        })
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        name: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+    ) -> Result<Stmt, Error> {
+        Ok(Stmt::ForIn {
+            name: name.clone(),
+            iterable: iterable.accept(self)?,
+            body: Box::new(body.accept(self)?),
+        })
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        name: &Token,
+        params: &Option<Vec<Param>>,
+        ret: &Option<Token>,
+        body: &[Stmt],
+        close: Span,
+        static_: bool,
+    ) -> Result<Stmt, Error> {
+        Ok(Stmt::Function {
+            name: name.clone(),
+            params: params.clone(),
+            ret: ret.clone(),
+            body: self.stmts(body)?,
+            close,
+            static_,
+        })
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        else_: &Option<Stmt>,
+        then_: &Stmt,
+    ) -> Result<Stmt, Error> {
+        let else_ = else_.as_ref().map(|s| s.accept(self)).transpose()?;
+        Ok(Stmt::If {
+            binding: binding.clone(),
+            cond: cond.accept(self)?,
+            then_: Box::new(then_.accept(self)?),
+            else_: Box::new(else_),
+        })
+    }
+
+    fn visit_import_stmt(
+        &mut self,
+        keywd: &Token,
+        path: &Token,
+        alias: &Option<Token>,
+        names: &[Token],
+    ) -> Result<Stmt, Error> {
+        Ok(Stmt::Import {
+            keywd: keywd.clone(),
+            path: path.clone(),
+            alias: alias.clone(),
+            names: names.to_vec(),
+        })
+    }
+
+    fn visit_labeled_stmt(&mut self, label: &Token, body: &Stmt) -> Result<Stmt, Error> {
+        Ok(Stmt::Labeled {
+            label: label.clone(),
+            body: Box::new(body.accept(self)?),
+        })
+    }
+
+    fn visit_match_stmt(
+        &mut self,
+        keywd: &Token,
+        subject: &Expr,
+        arms: &[Arm],
+        else_: &Option<Stmt>,
+        close: Span,
+    ) -> Result<Stmt, Error> {
+        let arms = arms
+            .iter()
+            .map(|arm| {
+                Ok(Arm {
+                    patterns: arm
+                        .patterns
+                        .iter()
+                        .map(|p| p.accept(self))
+                        .collect::<Result<_, _>>()?,
+                    guard: self.opt_expr(&arm.guard)?,
+                    body: arm.body.accept(self)?,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        let else_ = else_.as_ref().map(|s| s.accept(self)).transpose()?;
+        Ok(Stmt::Match {
+            keywd: keywd.clone(),
+            subject: subject.accept(self)?,
+            arms,
+            else_: Box::new(else_),
+            close,
+        })
+    }
+
+    fn visit_print_stmt(&mut self, expr: &Expr, rest: &[Expr]) -> Result<Stmt, Error> {
+        Ok(Stmt::Print {
+            expr: expr.accept(self)?,
+            rest: rest
+                .iter()
+                .map(|e| e.accept(self))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn visit_return_stmt(&mut self, keywd: &Token, val: &Option<Expr>) -> Result<Stmt, Error> {
+        Ok(Stmt::Return {
+            keywd: keywd.clone(),
+            val: self.opt_expr(val)?,
+        })
+    }
+
+    fn visit_throw_stmt(&mut self, keywd: &Token, val: &Expr) -> Result<Stmt, Error> {
+        Ok(Stmt::Throw {
+            keywd: keywd.clone(),
+            val: val.accept(self)?,
+        })
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        keywd: &Token,
+        body: &Stmt,
+        catch: &Option<(Token, Stmt)>,
+        finally: &Option<Stmt>,
+    ) -> Result<Stmt, Error> {
+        let catch = match catch {
+            Some((name, body)) => Some((name.clone(), body.accept(self)?)),
+            None => None,
+        };
+        let finally = finally.as_ref().map(|s| s.accept(self)).transpose()?;
+        Ok(Stmt::Try {
+            keywd: keywd.clone(),
+            body: Box::new(body.accept(self)?),
+            catch: Box::new(catch),
+            finally: Box::new(finally),
+        })
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        name: &Token,
+        ty: &Option<Token>,
+        init: &Option<Expr>,
+    ) -> Result<Stmt, Error> {
+        Ok(Stmt::Var {
+            name: name.clone(),
+            ty: ty.clone(),
+            init: self.opt_expr(init)?,
+        })
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        body: &Stmt,
+    ) -> Result<Stmt, Error> {
+        Ok(Stmt::While {
+            binding: binding.clone(),
+            cond: cond.accept(self)?,
+            body: Box::new(body.accept(self)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::expr_ast::AstPrinter;
+    use crate::frontend::parser::Parser;
+    use crate::frontend::scanner::Scanner;
+
+    fn folded(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let mut printer = AstPrinter;
+        optimize(&stmts)
+            .unwrap()
+            .iter()
+            .map(|s| printer.print_stmt(s).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn folds_constant_expressions() {
+        assert_eq!(folded("print 2 * 3 + 1;"), vec!["(print 7)"]);
+        assert_eq!(folded("print \"a\" + \"b\";"), vec!["(print ab)"]);
+        assert_eq!(folded("print !true;"), vec!["(print false)"]);
+        assert_eq!(folded("print -(1 + 2) * 4 % 5;"), vec!["(print -2)"]);
+        assert_eq!(folded("print 1 < 2 == !nil;"), vec!["(print true)"]);
+        assert_eq!(folded("print 1 == \"1\";"), vec!["(print false)"]);
+    }
+
+    #[test]
+    fn folds_inside_other_nodes() {
+        assert_eq!(
+            folded("fn f(a) { return a * (2 + 3); }"),
+            vec!["(fn f (a) (return (* a 5)))"]
+        );
+        assert_eq!(
+            folded("var x = 1 > 2 ? a : b + 10 / 4;"),
+            vec!["(var x (+ b 2.5))"]
+        );
+        assert_eq!(
+            folded("while (x < 2 * 5) x = x + 1 + 1;"),
+            vec!["(while (< x 10) (; (x (+ (+ x 1) 1))))"]
+        );
+    }
+
+    #[test]
+    fn leaves_what_the_runtime_decides() {
+        assert_eq!(folded("print 1 / 0;"), vec!["(print (/ 1 0))"]);
+        assert_eq!(folded("print 1 + \"a\";"), vec!["(print (+ 1 a))"]);
+        assert_eq!(folded("print 6 & 3;"), vec!["(print (& 6 3))"]);
+        assert_eq!(folded("print -\"a\";"), vec!["(print (- a))"]);
+        assert_eq!(folded("print a + 1 + 2;"), vec!["(print (+ (+ a 1) 2))"]);
+    }
+
+    #[test]
+    fn folded_literals_keep_a_token() {
+        let tokens = Scanner::new("(1 + 2) * 4".to_string())
+            .scan_tokens()
+            .unwrap();
+        let expr = Parser::new(tokens).parse_expression().unwrap();
+        match ConstantFolder.expr(&expr).unwrap() {
+            Expr::Literal { token, .. } => {
+                assert_eq!(token.t_type, TokenType::Number);
+                assert_eq!(token.lexeme, "12");
+                assert_eq!(token.span, Span::new(1, 2, 1, 2));
+            }
+            expr => panic!("not folded: {:?}", expr),
+        }
+    }
+}
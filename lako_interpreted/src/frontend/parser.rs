@@ -1,31 +1,224 @@
-use crate::frontend::error::{parser_error, Error};
-//use crate::frontend::stmt_ast::Stmt;
 use super::expr_ast::{Expr, LiteralValue};
-use crate::frontend::token::{Token, TokenType};
+use crate::frontend::cst::{self, NodeKind};
+use crate::frontend::diagnostic::Help;
+use crate::frontend::dialect::Dialect;
+use crate::frontend::error::Error;
+use crate::frontend::stmt_ast::{Arm, Param, Stmt};
+use crate::frontend::token::{Literal, Token, TokenType};
+use std::mem;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+// One precedence level of the expression grammar and the operators it accepts.
+pub struct OperatorLevel {
+    pub rule: &'static str,
+    pub assoc: Assoc,
+    pub operators: &'static [TokenType],
+}
+
+// How deeply statements and expressions may nest, and how tall the tree of an
+// expression may grow with chains like `a + b + c` or `a.b.c()` on top. The parser
+// and every later pass walk the tree recursively, so deeper programs would overflow
+// the stack instead of failing; see frontend::stack for how much they get.
+const MAX_DEPTH: usize = 256;
+const MAX_HEIGHT: usize = 10_000;
+
+const ASSIGNMENT_OPS: &[TokenType] = &[
+    TokenType::Equal,
+    TokenType::PlusEqual,
+    TokenType::MinusEqual,
+    TokenType::StarEqual,
+    TokenType::SlashEqual,
+    TokenType::PercentEqual,
+];
+const CONDITIONAL_OPS: &[TokenType] = &[TokenType::Question, TokenType::Colon];
+const EQUALITY_OPS: &[TokenType] = &[TokenType::BangEqual, TokenType::EqualEqual];
+const BITWISE_OR_OPS: &[TokenType] = &[TokenType::Pipe];
+const BITWISE_XOR_OPS: &[TokenType] = &[TokenType::Caret];
+const BITWISE_AND_OPS: &[TokenType] = &[TokenType::Ampersand];
+const SHIFT_OPS: &[TokenType] = &[TokenType::LessLess, TokenType::GreaterGreater];
+const COMPARISON_OPS: &[TokenType] = &[
+    TokenType::Greater,
+    TokenType::GreaterEqual,
+    TokenType::Less,
+    TokenType::LessEqual,
+    TokenType::Is,
+];
+const TERM_OPS: &[TokenType] = &[TokenType::Minus, TokenType::Plus];
+const FACTOR_OPS: &[TokenType] = &[TokenType::Slash, TokenType::Star, TokenType::Percent];
+const UNARY_OPS: &[TokenType] = &[
+    TokenType::Bang,
+    TokenType::Minus,
+    TokenType::Tilde,
+    TokenType::PlusPlus,
+    TokenType::MinusMinus,
+];
+const POSTFIX_OPS: &[TokenType] = &[TokenType::PlusPlus, TokenType::MinusMinus];
+
+// Operator precedence from lowest to highest. The grammar rules below read their
+// operators from these tables, so tools dumping them see exactly what is parsed.
+pub const PRECEDENCE: &[OperatorLevel] = &[
+    OperatorLevel {
+        rule: "assignment",
+        assoc: Assoc::Right,
+        operators: ASSIGNMENT_OPS,
+    },
+    OperatorLevel {
+        rule: "conditional",
+        assoc: Assoc::Right,
+        operators: CONDITIONAL_OPS,
+    },
+    OperatorLevel {
+        rule: "equality",
+        assoc: Assoc::Left,
+        operators: EQUALITY_OPS,
+    },
+    OperatorLevel {
+        rule: "bitwise_or",
+        assoc: Assoc::Left,
+        operators: BITWISE_OR_OPS,
+    },
+    OperatorLevel {
+        rule: "bitwise_xor",
+        assoc: Assoc::Left,
+        operators: BITWISE_XOR_OPS,
+    },
+    OperatorLevel {
+        rule: "bitwise_and",
+        assoc: Assoc::Left,
+        operators: BITWISE_AND_OPS,
+    },
+    OperatorLevel {
+        rule: "shift",
+        assoc: Assoc::Left,
+        operators: SHIFT_OPS,
+    },
+    OperatorLevel {
+        rule: "comparison",
+        assoc: Assoc::Left,
+        operators: COMPARISON_OPS,
+    },
+    OperatorLevel {
+        rule: "term",
+        assoc: Assoc::Left,
+        operators: TERM_OPS,
+    },
+    OperatorLevel {
+        rule: "factor",
+        assoc: Assoc::Left,
+        operators: FACTOR_OPS,
+    },
+    OperatorLevel {
+        rule: "unary",
+        assoc: Assoc::Right,
+        operators: UNARY_OPS,
+    },
+    OperatorLevel {
+        rule: "postfix",
+        assoc: Assoc::Left,
+        operators: POSTFIX_OPS,
+    },
+];
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    dialect: Dialect,
+    // comments and whitespace, each with the index of the token that follows it
+    trivia: Vec<(usize, Token)>,
+    // parse_cst records every node as a range of token indices
+    cst: bool,
+    nodes: Vec<(NodeKind, usize, usize)>,
+    // statements and expressions being parsed, see MAX_DEPTH
+    depth: usize,
+    // about how tall the tree of the expression just parsed is, see MAX_HEIGHT
+    height: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser { tokens, current: 0 }
+        Parser::with_dialect(tokens, Dialect::default())
     }
 
-    // pub fn parse(&mut self) -> Result<Vec<Stmt>, Error> {
-    //     let mut statements: Vec<Stmt> = Vec::new();
-    //     while !self.is_at_end() {
-    //         statements.push(self.declaration()?);
-    //     }
-    //     Ok(statements)
-    // }
+    // Parser for a file with pragmas, see Dialect::from_source
+    // Trivia from Scanner::with_trivia is set aside for parse_cst.
+    pub fn with_dialect(all: Vec<Token>, dialect: Dialect) -> Parser {
+        let mut tokens = Vec::with_capacity(all.len());
+        let mut trivia = Vec::new();
+        for token in all {
+            if token.t_type.is_trivia() {
+                trivia.push((tokens.len(), token));
+            } else {
+                tokens.push(token);
+            }
+        }
+        Parser {
+            tokens,
+            current: 0,
+            dialect,
+            trivia,
+            cst: false,
+            nodes: Vec::new(),
+            depth: 0,
+            height: 0,
+        }
+    }
 
     // main public method
-    pub fn parse(&mut self) -> Result<Expr, Error> {
+    // Parses the whole program. On a syntax error we report it, resynchronize at the
+    // next statement boundary and keep going, so every error is returned at once.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<Error>> {
+        let mut statements: Vec<Stmt> = Vec::new();
+        let mut errors: Vec<Error> = Vec::new();
+        while !self.is_at_end() {
+            match self.top_level() {
+                Ok(stmt) => statements.push(stmt),
+                Err(e) => {
+                    errors.push(e);
+                    self.synchronize();
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Parses the program and also returns its lossless concrete syntax tree. Scan with
+    // Scanner::with_trivia so the tree keeps the comments and whitespace too.
+    pub fn parse_cst(&mut self) -> Result<(Vec<Stmt>, cst::Node), Vec<Error>> {
+        self.cst = true;
+        let statements = self.parse()?;
+        let nodes = mem::take(&mut self.nodes);
+        Ok((statements, cst::build(&self.tokens, &self.trivia, nodes)))
+    }
+
+    // Parses a single expression - handy for tests and tools
+    pub fn parse_expression(&mut self) -> Result<Expr, Error> {
         self.expression()
     }
 
+    // CST helpers - the node parsed from token start up to the current one
+    fn mark_expr(&mut self, start: usize, expr: &Expr) {
+        if self.cst {
+            self.nodes
+                .push((NodeKind::of_expr(expr), start, self.current));
+        }
+    }
+
+    fn mark_stmt(&mut self, start: usize, stmt: &Stmt) {
+        if self.cst {
+            self.nodes
+                .push((NodeKind::of_stmt(stmt), start, self.current));
+        }
+    }
+
     // token stream helper methods
     // checks if we reached the end of the token stream
     fn is_at_end(&self) -> bool {
@@ -58,6 +251,13 @@ impl Parser {
         t_type == self.peek().t_type
     }
 
+    // checks the token after the current one
+    fn check_next(&self, t_type: TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|t| t.t_type == t_type)
+    }
+
     // consumes tokens until finding ")". If does not find it returns error message
     fn consume(&mut self, t_type: TokenType, message: &str) -> Result<Token, Error> {
         if self.check(t_type) {
@@ -67,13 +267,77 @@ impl Parser {
         }
     }
 
+    // is the current token where a statement may end without its ';'?
+    // Only with the optional-semicolons pragma: at a line break, a '}' or the end.
+    fn at_implicit_semicolon(&self) -> bool {
+        self.dialect.optional_semicolons
+            && (self.is_at_end()
+                || self.check(TokenType::RightBrace)
+                || self.peek().span.line > self.previous().span.line)
+    }
+
+    // consumes the ';' ending a statement, see at_implicit_semicolon
+    // When the next token is on a new line the ';' was most likely just forgotten,
+    // so the error points at where it goes.
+    fn consume_semicolon(&mut self, message: &str) -> Result<(), Error> {
+        if self.t_match(&[TokenType::Semicolon]) || self.at_implicit_semicolon() {
+            return Ok(());
+        }
+        let help = if !self.is_at_end() && self.peek().span.line > self.previous().span.line {
+            Some(Box::new(Help::new(
+                "add ';' after this expression",
+                self.previous().span,
+            )))
+        } else {
+            None
+        };
+        Err(Error::Parse {
+            token: self.peek().clone(),
+            message: message.to_string(),
+            help,
+        })
+    }
+
     // returns parse error
     fn error(&self, token: &Token, message: &str) -> Error {
-        parser_error(token, message);
-        Error::Parse
+        Error::Parse {
+            token: token.clone(),
+            message: message.to_string(),
+            help: None,
+        }
+    }
+
+    // Parses one more level of nesting, or fails once there are too many. What it
+    // parsed is as tall as the tallest of it and what came before at this level.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Parser) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error(self.peek(), "Too deeply nested."));
+        }
+        let before = self.height;
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        self.height = before.max(self.height + 1);
+        parsed
+    }
+
+    // The height of a chain of binary operators, calls, properties or indexing,
+    // `height` tall so far, with one more link whose operand was just parsed. Loops
+    // parse these chains, so they don't nest, but every link makes the tree one
+    // level taller and the passes walk it recursively.
+    fn taller(&self, height: usize, link: &Token) -> Result<usize, Error> {
+        let height = height.max(self.height) + 1;
+        if height > MAX_HEIGHT {
+            return Err(self.error(link, "Expression is too long."));
+        }
+        Ok(height)
     }
 
-    // unused - and don't remember why I coded this
+    // panic mode recovery: discards tokens until we are probably at the start of the
+    // next statement, so one syntax error doesn't cascade into many
     fn synchronize(&mut self) {
         self.advance();
 
@@ -83,14 +347,22 @@ impl Parser {
             }
 
             match self.peek().t_type {
-                TokenType::Class
+                TokenType::Assert
+                | TokenType::Break
+                | TokenType::Class
+                | TokenType::Continue
+                | TokenType::Export
                 | TokenType::Fn
+                | TokenType::From
                 | TokenType::Var
                 | TokenType::For
                 | TokenType::If
+                | TokenType::Match
                 | TokenType::While
                 | TokenType::Print
-                | TokenType::Return => return,
+                | TokenType::Return
+                | TokenType::Throw
+                | TokenType::Try => return,
                 _ => {} //_ => self.advance(),
             };
             self.advance();
@@ -100,7 +372,7 @@ impl Parser {
     // compares current token to array of tokens
     fn t_match(&mut self, token_types: &[TokenType]) -> bool {
         for tt in token_types {
-            if self.check(tt.clone()) {
+            if self.check(*tt) {
                 self.advance();
                 return true;
             }
@@ -109,147 +381,1119 @@ impl Parser {
     }
 
     // GRAMMAR:
-    // expression     → equality ;
-    // equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-    // comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+    // program        → ( importDecl | exportDecl | declaration )* EOF ;
+    // importDecl     → "import" STRING "as" IDENTIFIER ";"
+    //                | "from" STRING "import" IDENTIFIER ( "," IDENTIFIER )* ";" ;
+    // exportDecl     → "export" ( classDecl | funDecl | varDecl ) ;
+    // declaration    → classDecl | funDecl | varDecl | statement ;
+    // classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" member* "}" ;
+    // member         → varDecl | "static"? ( function | getter ) ;
+    // funDecl        → "fn" function ;
+    // function       → IDENTIFIER "(" parameters? ")" type? block ;
+    // getter         → IDENTIFIER type? block ;
+    // parameters     → IDENTIFIER type? ( "," IDENTIFIER type? )* ;
+    // type           → ":" IDENTIFIER ;
+    // varDecl        → "var" IDENTIFIER type? ( "=" expression )? ";" ;
+    // statement      → assertStmt | breakStmt | continueStmt | exprStmt | forStmt | ifStmt
+    //                | labeledStmt | matchStmt | printStmt | returnStmt | throwStmt
+    //                | tryStmt | whileStmt | block ;
+    // assertStmt     → "assert" expression ( "," expression )? ";" ;
+    // breakStmt      → "break" IDENTIFIER? ";" ;
+    // continueStmt   → "continue" IDENTIFIER? ";" ;
+    // exprStmt       → expression ";" ;
+    // forStmt        → "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")"
+    //                  statement
+    //                | "for" "(" IDENTIFIER "in" expression ")" statement ;
+    // ifStmt         → "if" "(" condition ")" statement ( "else" statement )? ;
+    // labeledStmt    → IDENTIFIER ":" ( forStmt | whileStmt ) ;
+    // matchStmt      → "match" "(" expression ")" "{" arm* ( "else" "=>" statement )? "}" ;
+    // arm            → pattern ( "," pattern )* ( "if" expression )? "=>" statement ;
+    // pattern        → "-"? NUMBER | STRING | "true" | "false" | "nil" | IDENTIFIER ;
+    // printStmt      → "print" expression ( "," expression )* ";" ;
+    // returnStmt     → "return" expression? ";" ;
+    // throwStmt      → "throw" expression ";" ;
+    // tryStmt        → "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+    // whileStmt      → "while" "(" condition ")" statement ;
+    // condition      → ( "var" IDENTIFIER "=" )? expression ;
+    // block          → "{" declaration* "}" ;
+    //
+    // expression     → assignment ;
+    // assignment     → ( call "." IDENTIFIER | call "[" expression "]" | IDENTIFIER )
+    //                  ( "=" | "+=" | "-=" | "*=" | "/=" | "%=" ) assignment
+    //                | conditional ;
+    // conditional    → equality ( "?" expression ":" conditional )? ;
+    // equality       → bitwise_or ( ( "!=" | "==" ) bitwise_or )* ;
+    // bitwise_or     → bitwise_xor ( "|" bitwise_xor )* ;
+    // bitwise_xor    → bitwise_and ( "^" bitwise_and )* ;
+    // bitwise_and    → shift ( "&" shift )* ;
+    // shift          → comparison ( ( "<<" | ">>" ) comparison )* ;
+    // comparison     → term ( ( ">" | ">=" | "<" | "<=" | "is" ) term )* ;
     // term           → factor ( ( "-" | "+" ) factor )* ;
-    // factor         → unary ( ( "/" | "*" ) unary )* ;
-    // unary          → ( "!" | "-" ) unary
-    //                | primary ;
+    // factor         → unary ( ( "/" | "*" | "%" ) unary )* ;
+    // unary          → ( "!" | "-" | "~" | "++" | "--" ) unary
+    //                | postfix ;
+    // postfix        → call ( "++" | "--" )? ;
+    // call           → primary ( "(" arguments? ")" | "." IDENTIFIER | "[" index "]" )* ;
+    // index          → expression | expression? ":" expression? ;
+    // arguments      → expression ( "," expression )* ;
     // primary        → NUMBER | STRING | "true" | "false" | "nil"
-    //                | "(" expression ")" ;
+    //                | IDENTIFIER | "(" expression ")" | lambda | list | map ;
+    // lambda         → "fn" "(" parameters? ")" type? block ;
+    // list           → "[" ( expression ( "," expression )* ","? )? "]" ;
+    // map            → "{" ( entry ( "," entry )* ","? )? "}" ;
+    // entry          → expression ":" expression ;
+    // A '{' starting a statement is a block, anywhere else it is a map.
 
     // *** Grammar rules - Each grammar rule is a method ***
-    // expression     → equality ;
+    // program        → ( importDecl | exportDecl | declaration )* EOF ;
+    fn top_level(&mut self) -> Result<Stmt, Error> {
+        let start = self.current;
+        let stmt = if self.check(TokenType::Import) || self.check(TokenType::From) {
+            self.import_declaration()
+        } else if self.t_match(&[TokenType::Export]) {
+            self.export_declaration()
+        } else {
+            return self.declaration();
+        }?;
+        self.mark_stmt(start, &stmt);
+        Ok(stmt)
+    }
+
+    // exportDecl     → "export" ( classDecl | funDecl | varDecl ) ;
+    fn export_declaration(&mut self) -> Result<Stmt, Error> {
+        let keywd = self.previous().clone();
+        let declaration = self.check(TokenType::Class)
+            || self.check(TokenType::Var)
+            || (self.check(TokenType::Fn) && !self.check_next(TokenType::LeftParen));
+        if !declaration {
+            return Err(self.error(
+                self.peek(),
+                "Expect class, function or variable declaration after 'export'.",
+            ));
+        }
+        Ok(Stmt::Export {
+            keywd,
+            decl: Box::new(self.declaration()?),
+        })
+    }
+
+    // importDecl     → "import" STRING "as" IDENTIFIER ";"
+    //                | "from" STRING "import" IDENTIFIER ( "," IDENTIFIER )* ";" ;
+    fn import_declaration(&mut self) -> Result<Stmt, Error> {
+        let keywd = self.advance().clone();
+        let path = self.consume(TokenType::String, "Expect module path.")?;
+        let (alias, names) = if keywd.t_type == TokenType::Import {
+            self.consume(TokenType::As, "Expect 'as' after module path.")?;
+            let alias = self.consume(TokenType::Identifier, "Expect module name.")?;
+            (Some(alias), Vec::new())
+        } else {
+            self.consume(TokenType::Import, "Expect 'import' after module path.")?;
+            let mut names = Vec::new();
+            loop {
+                names.push(self.consume(TokenType::Identifier, "Expect name to import.")?);
+                if !self.t_match(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+            (None, names)
+        };
+        self.consume_semicolon("Expect ';' after import.")?;
+        Ok(Stmt::Import {
+            keywd,
+            path,
+            alias,
+            names,
+        })
+    }
+
+    // declaration    → classDecl | funDecl | varDecl | statement ;
+    fn declaration(&mut self) -> Result<Stmt, Error> {
+        if self.check(TokenType::Import) || self.check(TokenType::From) {
+            return Err(self.error(self.peek(), "Imports must be at the top level."));
+        }
+        if self.check(TokenType::Export) {
+            return Err(self.error(self.peek(), "Only top level declarations can be exported."));
+        }
+        let start = self.current;
+        let stmt = if self.t_match(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.check(TokenType::Fn) && !self.check_next(TokenType::LeftParen) {
+            // `fn (` starts an expression statement with a lambda
+            self.advance();
+            self.function("function", false)
+        } else if self.t_match(&[TokenType::Var]) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        }?;
+        self.mark_stmt(start, &stmt);
+        Ok(stmt)
+    }
+
+    // classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" member* "}" ;
+    // member         → varDecl | "static"? ( function | getter ) ;
+    fn class_declaration(&mut self) -> Result<Stmt, Error> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.")?;
+
+        let sclass = if self.t_match(&[TokenType::Less]) {
+            let start = self.current;
+            let name = self.consume(TokenType::Identifier, "Expect superclass name.")?;
+            let sclass = Expr::Variable { name };
+            self.mark_expr(start, &sclass);
+            Some(sclass)
+        } else {
+            None
+        };
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let start = self.current;
+            if self.t_match(&[TokenType::Var]) {
+                let field = self.var_declaration()?;
+                self.mark_stmt(start, &field);
+                fields.push(field);
+            } else {
+                let static_ = self.t_match(&[TokenType::Static]);
+                let method = self.function("method", static_)?;
+                if let Stmt::Function { name, params, .. } = &method {
+                    if name.lexeme.as_str() == "init" && static_ {
+                        return Err(self.error(name, "The initializer can't be static."));
+                    }
+                    if name.lexeme.as_str() == "init" && params.is_none() {
+                        return Err(self.error(name, "The initializer can't be a getter."));
+                    }
+                }
+                self.mark_stmt(start, &method);
+                methods.push(method);
+            }
+        }
+        let close = self
+            .consume(TokenType::RightBrace, "Expect '}' after class body.")?
+            .span;
+
+        Ok(Stmt::Class {
+            name,
+            sclass,
+            fields,
+            methods,
+            close,
+        })
+    }
+
+    // function       → IDENTIFIER "(" parameters? ")" type? block ;
+    // getter         → IDENTIFIER type? block ;
+    // kind is "function" or "method", and only methods can be getters
+    fn function(&mut self, kind: &str, static_: bool) -> Result<Stmt, Error> {
+        let name = self.consume(TokenType::Identifier, &format!("Expect {} name.", kind))?;
+        let getter =
+            kind == "method" && (self.check(TokenType::LeftBrace) || self.check(TokenType::Colon));
+        let params = if getter {
+            None
+        } else {
+            self.consume(
+                TokenType::LeftParen,
+                &format!("Expect '(' after {} name.", kind),
+            )?;
+            Some(self.parameters()?)
+        };
+        let ret = self.annotation()?;
+
+        self.consume(
+            TokenType::LeftBrace,
+            &format!("Expect '{{' before {} body.", kind),
+        )?;
+        let body = self.block()?;
+        Ok(Stmt::Function {
+            name,
+            params,
+            ret,
+            body,
+            close: self.previous().span,
+            static_,
+        })
+    }
+
+    // parameters     → IDENTIFIER type? ( "," IDENTIFIER type? )* ;
+    // Also consumes the ')' after them.
+    fn parameters(&mut self) -> Result<Vec<Param>, Error> {
+        let mut params = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    return Err(self.error(self.peek(), "Can't have more than 255 parameters."));
+                }
+                let name = self.consume(TokenType::Identifier, "Expect parameter name.")?;
+                let ty = self.annotation()?;
+                params.push(Param { name, ty });
+                if !self.t_match(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+        Ok(params)
+    }
+
+    // type           → ":" IDENTIFIER ;
+    // Annotations are optional everywhere; the type is just a name for now.
+    fn annotation(&mut self) -> Result<Option<Token>, Error> {
+        if !self.t_match(&[TokenType::Colon]) {
+            return Ok(None);
+        }
+        Ok(Some(
+            self.consume(TokenType::Identifier, "Expect type name.")?,
+        ))
+    }
+
+    // varDecl        → "var" IDENTIFIER type? ( "=" expression )? ";" ;
+    fn var_declaration(&mut self) -> Result<Stmt, Error> {
+        let name = self.consume(TokenType::Identifier, "Expect variable name.")?;
+        let ty = self.annotation()?;
+
+        let init = if self.t_match(&[TokenType::Equal]) {
+            Some(self.expression()?)
+        } else if self.dialect.strict {
+            return Err(self.error(
+                self.peek(),
+                "Expect '=' after variable name: strict mode requires an initializer.",
+            ));
+        } else {
+            None
+        };
+
+        self.consume_semicolon("Expect ';' after variable declaration.")?;
+        Ok(Stmt::Var { name, ty, init })
+    }
+
+    // statement      → assertStmt | breakStmt | continueStmt | exprStmt | forStmt | ifStmt
+    //                | labeledStmt | matchStmt | printStmt | returnStmt | throwStmt
+    //                | tryStmt | whileStmt | block ;
+    fn statement(&mut self) -> Result<Stmt, Error> {
+        self.nested(Parser::any_statement)
+    }
+
+    fn any_statement(&mut self) -> Result<Stmt, Error> {
+        let start = self.current;
+        let stmt = if self.t_match(&[TokenType::Assert]) {
+            self.assert_statement()
+        } else if self.t_match(&[TokenType::Break, TokenType::Continue]) {
+            self.jump_statement()
+        } else if self.check(TokenType::Identifier) && self.check_next(TokenType::Colon) {
+            self.labeled_statement()
+        } else if self.t_match(&[TokenType::For]) {
+            self.for_statement()
+        } else if self.t_match(&[TokenType::If]) {
+            self.if_statement()
+        } else if self.t_match(&[TokenType::Match]) {
+            self.match_statement()
+        } else if self.t_match(&[TokenType::Print]) {
+            self.print_statement()
+        } else if self.t_match(&[TokenType::Return]) {
+            self.return_statement()
+        } else if self.t_match(&[TokenType::Throw]) {
+            self.throw_statement()
+        } else if self.t_match(&[TokenType::Try]) {
+            self.try_statement()
+        } else if self.t_match(&[TokenType::While]) {
+            self.while_statement()
+        } else if self.t_match(&[TokenType::LeftBrace]) {
+            Ok(Stmt::Block {
+                stmts: self.block()?,
+                close: self.previous().span,
+            })
+        } else {
+            self.expression_statement()
+        }?;
+        self.mark_stmt(start, &stmt);
+        Ok(stmt)
+    }
+
+    // assertStmt     → "assert" expression ( "," expression )? ";" ;
+    // The expression after the comma is the message shown when the assertion fails.
+    fn assert_statement(&mut self) -> Result<Stmt, Error> {
+        let keywd = self.previous().clone();
+        let cond = self.expression()?;
+        let message = if self.t_match(&[TokenType::Comma]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume_semicolon("Expect ';' after assertion.")?;
+        Ok(Stmt::Assert {
+            keywd,
+            cond,
+            message,
+        })
+    }
+
+    // breakStmt      → "break" IDENTIFIER? ";" ;
+    // continueStmt   → "continue" IDENTIFIER? ";" ;
+    fn jump_statement(&mut self) -> Result<Stmt, Error> {
+        let keywd = self.previous().clone();
+        let label = if self.check(TokenType::Identifier) && !self.at_implicit_semicolon() {
+            Some(self.advance().clone())
+        } else {
+            None
+        };
+        let message = format!("Expect ';' after '{}'.", keywd.lexeme);
+        self.consume_semicolon(&message)?;
+        Ok(match keywd.t_type {
+            TokenType::Break => Stmt::Break { keywd, label },
+            _ => Stmt::Continue { keywd, label },
+        })
+    }
+
+    // forStmt        → "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")"
+    //                  statement
+    //                | "for" "(" IDENTIFIER "in" expression ")" statement ;
+    fn for_statement(&mut self) -> Result<Stmt, Error> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+        if self.check(TokenType::Identifier) && self.check_next(TokenType::In) {
+            return self.for_in_statement();
+        }
+
+        let start = self.current;
+        let init = if self.t_match(&[TokenType::Semicolon]) {
+            None
+        } else if self.t_match(&[TokenType::Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+        if let Some(init) = &init {
+            self.mark_stmt(start, init);
+        }
+
+        let cond = if !self.check(TokenType::Semicolon) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
+
+        let incr = if !self.check(TokenType::RightParen) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let body = self.statement()?;
+        Ok(Stmt::For {
+            init: Box::new(init),
+            cond,
+            incr,
+            body: Box::new(body),
+        })
+    }
+
+    // The loop variable is a new local for every iteration
+    fn for_in_statement(&mut self) -> Result<Stmt, Error> {
+        let name = self.advance().clone();
+        self.advance(); // in
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after for-in collection.")?;
+        let body = self.statement()?;
+        Ok(Stmt::ForIn {
+            name,
+            iterable,
+            body: Box::new(body),
+        })
+    }
+
+    // ifStmt         → "if" "(" condition ")" statement ( "else" statement )? ;
+    // The else binds to the nearest if.
+    fn if_statement(&mut self) -> Result<Stmt, Error> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let (binding, cond) = self.condition()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_ = self.statement()?;
+        let else_ = if self.t_match(&[TokenType::Else]) {
+            Some(self.statement()?)
+        } else {
+            None
+        };
+
+        Ok(Stmt::If {
+            binding,
+            cond,
+            then_: Box::new(then_),
+            else_: Box::new(else_),
+        })
+    }
+
+    // labeledStmt    → IDENTIFIER ":" ( forStmt | whileStmt ) ;
+    // break and continue name the label to leave or go on with an outer loop.
+    fn labeled_statement(&mut self) -> Result<Stmt, Error> {
+        let label = self.advance().clone();
+        self.advance(); // :
+        if !self.check(TokenType::For) && !self.check(TokenType::While) {
+            return Err(self.error(self.peek(), "Expect a loop after label."));
+        }
+        let body = self.statement()?;
+        Ok(Stmt::Labeled {
+            label,
+            body: Box::new(body),
+        })
+    }
+
+    // matchStmt      → "match" "(" expression ")" "{" arm* ( "else" "=>" statement )? "}" ;
+    // arm            → pattern ( "," pattern )* ( "if" expression )? "=>" statement ;
+    // The else arm, if any, comes last.
+    fn match_statement(&mut self) -> Result<Stmt, Error> {
+        let keywd = self.previous().clone();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'match'.")?;
+        let subject = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after match value.")?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before match arms.")?;
+
+        let mut arms = Vec::new();
+        let mut else_ = None;
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            if self.t_match(&[TokenType::Else]) {
+                self.consume(TokenType::EqualGreater, "Expect '=>' after 'else'.")?;
+                else_ = Some(self.statement()?);
+                break;
+            }
+            let mut patterns = vec![self.pattern()?];
+            while self.t_match(&[TokenType::Comma]) {
+                patterns.push(self.pattern()?);
+            }
+            let guard = if self.t_match(&[TokenType::If]) {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            let message = if guard.is_some() {
+                "Expect '=>' after match guard."
+            } else {
+                "Expect '=>' after match patterns."
+            };
+            self.consume(TokenType::EqualGreater, message)?;
+            arms.push(Arm {
+                patterns,
+                guard,
+                body: self.statement()?,
+            });
+        }
+        let message = if else_.is_some() {
+            "Expect '}' after the else arm."
+        } else {
+            "Expect '}' after match arms."
+        };
+        let close = self.consume(TokenType::RightBrace, message)?.span;
+
+        Ok(Stmt::Match {
+            keywd,
+            subject,
+            arms,
+            else_: Box::new(else_),
+            close,
+        })
+    }
+
+    // pattern        → "-"? NUMBER | STRING | "true" | "false" | "nil" | IDENTIFIER ;
+    // A name matches anything and is bound to the subject, `_` included.
+    fn pattern(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let pattern = if self.check(TokenType::Minus) && self.check_next(TokenType::Number) {
+            let op = self.advance().clone();
+            Expr::Unary {
+                op,
+                rhs: Box::new(self.pattern()?),
+            }
+        } else if matches!(
+            self.peek().t_type,
+            TokenType::Number
+                | TokenType::String
+                | TokenType::True
+                | TokenType::False
+                | TokenType::Nil
+        ) {
+            self.primary()?
+        } else if self.check(TokenType::Identifier) {
+            Expr::Variable {
+                name: self.advance().clone(),
+            }
+        } else {
+            return Err(self.error(self.peek(), "Expect a literal or a name as pattern."));
+        };
+        self.mark_expr(start, &pattern);
+        Ok(pattern)
+    }
+
+    // printStmt      → "print" expression ( "," expression )* ";" ;
+    fn print_statement(&mut self) -> Result<Stmt, Error> {
+        let expr = self.expression()?;
+        let mut rest = Vec::new();
+        while self.t_match(&[TokenType::Comma]) {
+            rest.push(self.expression()?);
+        }
+        self.consume_semicolon("Expect ';' after value.")?;
+        Ok(Stmt::Print { expr, rest })
+    }
+
+    // returnStmt     → "return" expression? ";" ;
+    fn return_statement(&mut self) -> Result<Stmt, Error> {
+        let keywd = self.previous().clone();
+        let val = if !self.check(TokenType::Semicolon) && !self.at_implicit_semicolon() {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume_semicolon("Expect ';' after return value.")?;
+        Ok(Stmt::Return { keywd, val })
+    }
+
+    // throwStmt      → "throw" expression ";" ;
+    fn throw_statement(&mut self) -> Result<Stmt, Error> {
+        let keywd = self.previous().clone();
+        let val = self.expression()?;
+        self.consume_semicolon("Expect ';' after thrown value.")?;
+        Ok(Stmt::Throw { keywd, val })
+    }
+
+    // tryStmt        → "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+    fn try_statement(&mut self) -> Result<Stmt, Error> {
+        let keywd = self.previous().clone();
+        let body = self.block_statement("Expect '{' after 'try'.")?;
+        let catch = if self.t_match(&[TokenType::Catch]) {
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.")?;
+            let name = self.consume(TokenType::Identifier, "Expect exception name.")?;
+            self.consume(TokenType::RightParen, "Expect ')' after exception name.")?;
+            Some((
+                name,
+                self.block_statement("Expect '{' after catch clause.")?,
+            ))
+        } else {
+            None
+        };
+        let finally = if self.t_match(&[TokenType::Finally]) {
+            Some(self.block_statement("Expect '{' after 'finally'.")?)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error(self.peek(), "Expect 'catch' or 'finally' after try block."));
+        }
+        Ok(Stmt::Try {
+            keywd,
+            body: Box::new(body),
+            catch: Box::new(catch),
+            finally: Box::new(finally),
+        })
+    }
+
+    // whileStmt      → "while" "(" condition ")" statement ;
+    fn while_statement(&mut self) -> Result<Stmt, Error> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let (binding, cond) = self.condition()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = self.statement()?;
+        Ok(Stmt::While {
+            binding,
+            cond,
+            body: Box::new(body),
+        })
+    }
+
+    // condition      → ( "var" IDENTIFIER "=" )? expression ;
+    // The variable is bound to the value of the expression, see Stmt::If.
+    fn condition(&mut self) -> Result<(Option<Token>, Expr), Error> {
+        let binding = if self.t_match(&[TokenType::Var]) {
+            let name = self.consume(TokenType::Identifier, "Expect variable name.")?;
+            self.consume(TokenType::Equal, "Expect '=' after variable name.")?;
+            Some(name)
+        } else {
+            None
+        };
+        Ok((binding, self.expression()?))
+    }
+
+    // block          → "{" declaration* "}" ;
+    // the opening brace has already been consumed, the closing one is previous() after
+    fn block(&mut self) -> Result<Vec<Stmt>, Error> {
+        let mut stmts = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            stmts.push(self.declaration()?);
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(stmts)
+    }
+
+    // A block that has to be there, as in try
+    fn block_statement(&mut self, message: &str) -> Result<Stmt, Error> {
+        let start = self.current;
+        self.consume(TokenType::LeftBrace, message)?;
+        let block = Stmt::Block {
+            stmts: self.block()?,
+            close: self.previous().span,
+        };
+        self.mark_stmt(start, &block);
+        Ok(block)
+    }
+
+    // exprStmt       → expression ";" ;
+    fn expression_statement(&mut self) -> Result<Stmt, Error> {
+        let expr = self.expression()?;
+        self.consume_semicolon("Expect ';' after expression.")?;
+        Ok(Stmt::Expression { expr })
+    }
+
+    // expression     → assignment ;
     fn expression(&mut self) -> Result<Expr, Error> {
-        self.equality()
+        self.nested(Parser::assignment)
     }
 
-    // equality       → comparison ( ( "!=" | "==" ) comparison )* ;
+    // assignment     → ( call "." IDENTIFIER | call "[" expression "]" | IDENTIFIER )
+    //                  ( "=" | "+=" | "-=" | "*=" | "/=" | "%=" ) assignment
+    //                | conditional ;
+    // We parse the left side as an expression and then check it is a valid target.
+    fn assignment(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let expr = self.conditional()?;
+
+        if self.t_match(ASSIGNMENT_OPS) {
+            let op = self.previous().clone();
+            let val = Box::new(self.nested(Parser::assignment)?);
+
+            let expr = match expr {
+                Expr::Variable { name } => Expr::Assign { name, op, val },
+                Expr::Get { obj, name } => Expr::Set { obj, name, op, val },
+                Expr::Index {
+                    obj,
+                    bracket,
+                    index,
+                } => Expr::IndexSet {
+                    obj,
+                    bracket,
+                    index,
+                    op,
+                    val,
+                },
+                _ => return Err(self.error(&op, "Invalid assignment target.")),
+            };
+            self.mark_expr(start, &expr);
+            return Ok(expr);
+        }
+        Ok(expr)
+    }
+
+    // conditional    → equality ( "?" expression ":" conditional )? ;
+    // Right associative: a ? b : c ? d : e  is  a ? b : (c ? d : e)
+    fn conditional(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let expr = self.equality()?;
+
+        if self.t_match(&[TokenType::Question]) {
+            let then_ = self.expression()?;
+            self.consume(
+                TokenType::Colon,
+                "Expect ':' after then branch of conditional expression.",
+            )?;
+            let else_ = self.nested(Parser::conditional)?;
+            let expr = Expr::Conditional {
+                cond: Box::new(expr),
+                then_: Box::new(then_),
+                else_: Box::new(else_),
+            };
+            self.mark_expr(start, &expr);
+            return Ok(expr);
+        }
+        Ok(expr)
+    }
+
+    // equality       → bitwise_or ( ( "!=" | "==" ) bitwise_or )* ;
     fn equality(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut expr = self.bitwise_or()?;
+
+        let mut height = self.height;
+        while self.t_match(EQUALITY_OPS) {
+            let op = self.previous().clone();
+            let rhs = self.bitwise_or()?;
+            height = self.taller(height, &op)?;
+            expr = Expr::Binary {
+                lhs: Box::new(expr),
+                op,
+                rhs: Box::new(rhs),
+            };
+            self.mark_expr(start, &expr);
+        }
+        self.height = height;
+        Ok(expr)
+    }
+
+    // bitwise_or     → bitwise_xor ( "|" bitwise_xor )* ;
+    fn bitwise_or(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut expr = self.bitwise_xor()?;
+
+        let mut height = self.height;
+        while self.t_match(BITWISE_OR_OPS) {
+            let op = self.previous().clone();
+            let rhs = self.bitwise_xor()?;
+            height = self.taller(height, &op)?;
+            expr = Expr::Binary {
+                lhs: Box::new(expr),
+                op,
+                rhs: Box::new(rhs),
+            };
+            self.mark_expr(start, &expr);
+        }
+        self.height = height;
+        Ok(expr)
+    }
+
+    // bitwise_xor    → bitwise_and ( "^" bitwise_and )* ;
+    fn bitwise_xor(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut expr = self.bitwise_and()?;
+
+        let mut height = self.height;
+        while self.t_match(BITWISE_XOR_OPS) {
+            let op = self.previous().clone();
+            let rhs = self.bitwise_and()?;
+            height = self.taller(height, &op)?;
+            expr = Expr::Binary {
+                lhs: Box::new(expr),
+                op,
+                rhs: Box::new(rhs),
+            };
+            self.mark_expr(start, &expr);
+        }
+        self.height = height;
+        Ok(expr)
+    }
+
+    // bitwise_and    → shift ( "&" shift )* ;
+    fn bitwise_and(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut expr = self.shift()?;
+
+        let mut height = self.height;
+        while self.t_match(BITWISE_AND_OPS) {
+            let op = self.previous().clone();
+            let rhs = self.shift()?;
+            height = self.taller(height, &op)?;
+            expr = Expr::Binary {
+                lhs: Box::new(expr),
+                op,
+                rhs: Box::new(rhs),
+            };
+            self.mark_expr(start, &expr);
+        }
+        self.height = height;
+        Ok(expr)
+    }
+
+    // shift          → comparison ( ( "<<" | ">>" ) comparison )* ;
+    fn shift(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
         let mut expr = self.comparison()?;
 
-        while self.t_match(&[TokenType::BangEqual, TokenType::EqualEqual]) {
+        let mut height = self.height;
+        while self.t_match(SHIFT_OPS) {
             let op = self.previous().clone();
             let rhs = self.comparison()?;
+            height = self.taller(height, &op)?;
             expr = Expr::Binary {
                 lhs: Box::new(expr),
                 op,
                 rhs: Box::new(rhs),
             };
+            self.mark_expr(start, &expr);
         }
+        self.height = height;
         Ok(expr)
     }
 
-    // comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+    // comparison     → term ( ( ">" | ">=" | "<" | "<=" | "is" ) term )* ;
     fn comparison(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
         let mut expr = self.term()?;
 
-        while self.t_match(&[
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-            TokenType::Less,
-            TokenType::LessEqual,
-        ]) {
+        let mut height = self.height;
+        while self.t_match(COMPARISON_OPS) {
             let op = self.previous().clone();
             let rhs = self.term()?;
+            height = self.taller(height, &op)?;
             expr = Expr::Binary {
                 lhs: Box::new(expr),
                 op,
                 rhs: Box::new(rhs),
             };
+            self.mark_expr(start, &expr);
         }
+        self.height = height;
         Ok(expr)
     }
 
     // term           → factor ( ( "-" | "+" ) factor )* ;
     fn term(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
         let mut expr = self.factor()?;
 
-        while self.t_match(&[TokenType::Minus, TokenType::Plus]) {
+        let mut height = self.height;
+        while self.t_match(TERM_OPS) {
             let op = self.previous().clone();
             let rhs = self.factor()?;
+            height = self.taller(height, &op)?;
             expr = Expr::Binary {
                 lhs: Box::new(expr),
                 op,
                 rhs: Box::new(rhs),
             };
+            self.mark_expr(start, &expr);
         }
+        self.height = height;
         Ok(expr)
     }
 
-    // factor         → unary ( ( "/" | "*" ) unary )* ;
+    // factor         → unary ( ( "/" | "*" | "%" ) unary )* ;
     fn factor(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
         let mut expr = self.unary()?;
 
-        while self.t_match(&[TokenType::Slash, TokenType::Star]) {
+        let mut height = self.height;
+        while self.t_match(FACTOR_OPS) {
             let op = self.previous().clone();
             let rhs = self.unary()?;
+            height = self.taller(height, &op)?;
             expr = Expr::Binary {
                 lhs: Box::new(expr),
                 op,
                 rhs: Box::new(rhs),
             };
+            self.mark_expr(start, &expr);
         }
+        self.height = height;
         Ok(expr)
     }
 
-    // unary          → ( "!" | "-" ) unary
-    //                | primary ;
+    // unary          → ( "!" | "-" | "~" | "++" | "--" ) unary
+    //                | postfix ;
     fn unary(&mut self) -> Result<Expr, Error> {
-        if self.t_match(&[TokenType::Bang, TokenType::Minus]) {
+        let start = self.current;
+        if self.t_match(UNARY_OPS) {
             let op = self.previous().clone();
-            let rhs = self.unary()?;
-            Ok(Expr::Unary {
+            self.height = 0;
+            let rhs = self.nested(Parser::unary)?;
+            let expr = if POSTFIX_OPS.contains(&op.t_type) {
+                self.update(op, rhs, true)?
+            } else {
+                Expr::Unary {
+                    op,
+                    rhs: Box::new(rhs),
+                }
+            };
+            self.mark_expr(start, &expr);
+            Ok(expr)
+        } else {
+            self.postfix()
+        }
+    }
+
+    // postfix        → call ( "++" | "--" )? ;
+    // x++ yields the old value of x, ++x the new one
+    fn postfix(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let expr = self.call()?;
+
+        if self.t_match(POSTFIX_OPS) {
+            let op = self.previous().clone();
+            let expr = self.update(op, expr, false)?;
+            self.mark_expr(start, &expr);
+            return Ok(expr);
+        }
+        Ok(expr)
+    }
+
+    // "++" / "--" need an assignable target, like assignment
+    fn update(&self, op: Token, target: Expr, prefix: bool) -> Result<Expr, Error> {
+        match target {
+            Expr::Variable { .. } | Expr::Get { .. } | Expr::Index { .. } => Ok(Expr::Update {
                 op,
-                rhs: Box::new(rhs),
-            })
+                target: Box::new(target),
+                prefix,
+            }),
+            _ => Err(self.error(&op, &format!("Invalid target for '{}'.", op.lexeme))),
+        }
+    }
+
+    // call           → primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" )* ;
+    fn call(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut expr = self.primary()?;
+        self.mark_expr(start, &expr);
+
+        let mut height = self.height;
+        loop {
+            if self.t_match(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.t_match(&[TokenType::Dot]) {
+                let name =
+                    self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
+                expr = Expr::Get {
+                    obj: Box::new(expr),
+                    name,
+                };
+            } else if self.t_match(&[TokenType::LeftBracket]) {
+                expr = self.finish_index(expr)?;
+            } else {
+                break;
+            }
+            height = self.taller(height, self.previous())?;
+            self.mark_expr(start, &expr);
+        }
+        self.height = height;
+        Ok(expr)
+    }
+
+    // index          → expression | expression? ":" expression? ;
+    fn finish_index(&mut self, obj: Expr) -> Result<Expr, Error> {
+        let bracket = self.previous().clone();
+        let from = if self.check(TokenType::Colon) {
+            None
         } else {
-            self.primary()
+            Some(self.expression()?)
+        };
+        let expr = match from {
+            Some(index) if !self.check(TokenType::Colon) => Expr::Index {
+                obj: Box::new(obj),
+                bracket,
+                index: Box::new(index),
+            },
+            from => {
+                self.consume(TokenType::Colon, "Expect ':' in slice.")?;
+                let to = if self.check(TokenType::RightBracket) {
+                    None
+                } else {
+                    Some(self.expression()?)
+                };
+                Expr::Slice {
+                    obj: Box::new(obj),
+                    bracket,
+                    from: Box::new(from),
+                    to: Box::new(to),
+                }
+            }
+        };
+        self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+        Ok(expr)
+    }
+
+    // arguments      → expression ( "," expression )* ;
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, Error> {
+        let mut arg = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if arg.len() >= 255 {
+                    return Err(self.error(self.peek(), "Can't have more than 255 arguments."));
+                }
+                arg.push(self.expression()?);
+                if !self.t_match(&[TokenType::Comma]) {
+                    break;
+                }
+            }
         }
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+
+        Ok(Expr::Call {
+            callee: Box::new(callee),
+            paren,
+            arg,
+        })
+    }
+
+    // lambda         → "fn" "(" parameters? ")" type? block ;
+    fn finish_lambda(&mut self, keywd: Token) -> Result<Expr, Error> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'fn'.")?;
+        let params = self.parameters()?;
+        let ret = self.annotation()?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.")?;
+        let body = self.block()?;
+        Ok(Expr::Lambda {
+            keywd,
+            params,
+            ret,
+            body,
+            close: self.previous().span,
+        })
+    }
+
+    // list           → "[" ( expression ( "," expression )* ","? )? "]" ;
+    fn finish_list(&mut self, bracket: Token) -> Result<Expr, Error> {
+        let mut elems = Vec::new();
+        while !self.check(TokenType::RightBracket) {
+            elems.push(self.expression()?);
+            if !self.t_match(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+        Ok(Expr::List { bracket, elems })
+    }
+
+    // map            → "{" ( entry ( "," entry )* ","? )? "}" ;
+    fn finish_map(&mut self, brace: Token) -> Result<Expr, Error> {
+        let mut entries = Vec::new();
+        while !self.check(TokenType::RightBrace) {
+            let key = self.expression()?;
+            self.consume(TokenType::Colon, "Expect ':' after map key.")?;
+            entries.push((key, self.expression()?));
+            if !self.t_match(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+        Ok(Expr::Map { brace, entries })
     }
 
     //                | primary ;
     // we match on primary type and extract the literals
     fn primary(&mut self) -> Result<Expr, Error> {
-        let expr = match &self.peek().t_type {
+        self.height = 0;
+        let token = self.peek().clone();
+        let expr = match self.peek().t_type {
             TokenType::False => Expr::Literal {
+                token,
                 val: LiteralValue::Boolean(false),
             },
             TokenType::True => Expr::Literal {
+                token,
                 val: LiteralValue::Boolean(true),
             },
             TokenType::Nil => Expr::Literal {
+                token,
                 val: LiteralValue::Nil,
             },
-            TokenType::String { literal } => Expr::Literal {
-                val: LiteralValue::String(literal.clone()),
+            TokenType::String | TokenType::Number => Expr::Literal {
+                token,
+                val: match &self.peek().literal {
+                    Some(Literal::String(s)) => LiteralValue::String(s.clone()),
+                    Some(Literal::Number(n)) => LiteralValue::Number(*n),
+                    None => unreachable!("The scanner gives literal tokens a value."),
+                },
             },
-            TokenType::Number { literal } => Expr::Literal {
-                val: LiteralValue::Number(*literal),
+            TokenType::Super => {
+                let keywd = self.advance().clone();
+                self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+                let method =
+                    self.consume(TokenType::Identifier, "Expect superclass method name.")?;
+
+                // We already advanced so we cut it short here.
+                return Ok(Expr::Super { keywd, method });
+            }
+            TokenType::This => Expr::This {
+                keywd: self.peek().clone(),
+            },
+            TokenType::Identifier => Expr::Variable {
+                name: self.peek().clone(),
             },
-            // TokenType::Super => {
-            //     let keyword = self.advance().clone();
-            //     self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
-            //     let method = self.consume(
-            //         TokenType::Identifier {
-            //             literal: "".to_string(),
-            //         },
-            //         "Expect superclass method name.",
-            //     )?;
-
-            //     // We already advance so we cut it short here.
-            //     return Ok(Expr::Super {
-            //         keywd: keyword,
-            //         method,
-            //     });
-            // }
-            // TokenType::This => Expr::This {
-            //     keywd: self.peek().clone(),
-            // },
-            // TokenType::Identifier { literal } => Expr::Variable {
-            //     name: self.peek().clone(),
-            // },
             TokenType::LeftParen => {
                 self.advance(); // if not we enter a recursive loop with '(' and we overflow the stack
                 let expression = self.expression()?;
@@ -258,6 +1502,18 @@ impl Parser {
                     expr: Box::new(expression),
                 });
             }
+            TokenType::Fn => {
+                let keywd = self.advance().clone();
+                return self.finish_lambda(keywd);
+            }
+            TokenType::LeftBracket => {
+                let bracket = self.advance().clone();
+                return self.finish_list(bracket);
+            }
+            TokenType::LeftBrace => {
+                let brace = self.advance().clone();
+                return self.finish_map(brace);
+            }
             _ => return Err(self.error(self.peek(), "Expect expression.")),
         };
 
@@ -272,22 +1528,28 @@ mod tests {
     use super::*;
     use crate::frontend::expr_ast::AstPrinter;
     use crate::frontend::scanner::Scanner;
+    use crate::frontend::stack;
+    use crate::frontend::token::Span;
 
     #[test]
     fn test_parser_equality() {
         // "!=" | "=="
         // 1 + 3 == 4  ->  (== (+ 1 3) 4)
         let mut scanner = Scanner::new("1 + 3 == 4".to_string());
-        let mut tokens = scanner.scan_tokens().clone();
+        let mut tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens);
-        let mut statements = parser.parse().expect("Could not parse sample code.");
+        let mut statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         let mut printer = AstPrinter;
         assert_eq!(printer.print(statements).unwrap(), "(== (+ 1 3) 4)");
         // 1 + 3 != 2  ->  (!= (+ 1 3) 2)
         scanner = Scanner::new("1 + 3 != 2".to_string());
-        tokens = scanner.scan_tokens().clone();
+        tokens = scanner.scan_tokens().unwrap();
         parser = Parser::new(tokens);
-        statements = parser.parse().expect("Could not parse sample code.");
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         assert_eq!(printer.print(statements).unwrap(), "(!= (+ 1 3) 2)");
     }
 
@@ -296,28 +1558,36 @@ mod tests {
         // ">" | ">=" | "<" | "<="
         // 4 > 2  ->  (> 4 2)
         let mut scanner = Scanner::new("4 > 2".to_string());
-        let mut tokens = scanner.scan_tokens().clone();
+        let mut tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens);
-        let mut statements = parser.parse().expect("Could not parse sample code.");
+        let mut statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         let mut printer = AstPrinter;
         assert_eq!(printer.print(statements).unwrap(), "(> 4 2)");
         // 3 >= 3  ->  (>= 3 3)
         scanner = Scanner::new("3 >= 3".to_string());
-        tokens = scanner.scan_tokens().clone();
+        tokens = scanner.scan_tokens().unwrap();
         parser = Parser::new(tokens);
-        statements = parser.parse().expect("Could not parse sample code.");
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         assert_eq!(printer.print(statements).unwrap(), "(>= 3 3)");
         // 6 < 7  ->  (< 6 7)
         scanner = Scanner::new("6 < 7".to_string());
-        tokens = scanner.scan_tokens().clone();
+        tokens = scanner.scan_tokens().unwrap();
         parser = Parser::new(tokens);
-        statements = parser.parse().expect("Could not parse sample code.");
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         assert_eq!(printer.print(statements).unwrap(), "(< 6 7)");
         // 8 <= 8  ->  (<= 8 8)
         scanner = Scanner::new("8 <= 8".to_string());
-        tokens = scanner.scan_tokens().clone();
+        tokens = scanner.scan_tokens().unwrap();
         parser = Parser::new(tokens);
-        statements = parser.parse().expect("Could not parse sample code.");
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         assert_eq!(printer.print(statements).unwrap(), "(<= 8 8)");
     }
 
@@ -326,9 +1596,11 @@ mod tests {
         //  "-" | "+"
         // 7 - 2 + 3  ->  (+ (- 7 2) 3)
         let mut scanner = Scanner::new("7 - 2 + 3".to_string());
-        let tokens = scanner.scan_tokens().clone();
+        let tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens);
-        let statements = parser.parse().expect("Could not parse sample code.");
+        let statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         let mut printer = AstPrinter;
         assert_eq!(printer.print(statements).unwrap(), "(+ (- 7 2) 3)");
     }
@@ -338,28 +1610,48 @@ mod tests {
         // "/" | "*"
         // 8 * 2 / 4  ->  (/ (* 8 2) 4)
         let mut scanner = Scanner::new("8 * 2 / 4".to_string());
-        let tokens = scanner.scan_tokens().clone();
+        let tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens);
-        let statements = parser.parse().expect("Could not parse sample code.");
+        let statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         let mut printer = AstPrinter;
         assert_eq!(printer.print(statements).unwrap(), "(/ (* 8 2) 4)");
     }
 
+    #[test]
+    fn test_parser_modulo() {
+        // "%" binds like "*" and "/"
+        // 1 + 7 % 4 * 2  ->  (+ 1 (* (% 7 4) 2))
+        let mut scanner = Scanner::new("1 + 7 % 4 * 2".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+        assert_eq!(printer.print(statements).unwrap(), "(+ 1 (* (% 7 4) 2))");
+    }
+
     #[test]
     fn test_parser_unary() {
         // "!" | "-"
         // -4 + 5 ->  (+ (- 4) 5)
         let mut scanner = Scanner::new("-4 + 5".to_string());
-        let mut tokens = scanner.scan_tokens().clone();
+        let mut tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens);
-        let mut statements = parser.parse().expect("Could not parse sample code.");
+        let mut statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         let mut printer = AstPrinter;
         assert_eq!(printer.print(statements).unwrap(), "(+ (- 4) 5)");
         // !3  ->  (! 3)
         scanner = Scanner::new("!3".to_string());
-        tokens = scanner.scan_tokens().clone();
+        tokens = scanner.scan_tokens().unwrap();
         parser = Parser::new(tokens);
-        statements = parser.parse().expect("Could not parse sample code.");
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         assert_eq!(printer.print(statements).unwrap(), "(! 3)");
     }
 
@@ -367,34 +1659,44 @@ mod tests {
     fn test_parser_primary() {
         // false
         let mut scanner = Scanner::new("false".to_string());
-        let mut tokens = scanner.scan_tokens().clone();
+        let mut tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens);
-        let mut statements = parser.parse().expect("Could not parse sample code.");
+        let mut statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         let mut printer = AstPrinter;
         assert_eq!(printer.print(statements).unwrap(), "false");
         // true
         scanner = Scanner::new("true".to_string());
-        tokens = scanner.scan_tokens().clone();
+        tokens = scanner.scan_tokens().unwrap();
         parser = Parser::new(tokens);
-        statements = parser.parse().expect("Could not parse sample code.");
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         assert_eq!(printer.print(statements).unwrap(), "true");
         // nil
         scanner = Scanner::new("nil".to_string());
-        tokens = scanner.scan_tokens().clone();
+        tokens = scanner.scan_tokens().unwrap();
         parser = Parser::new(tokens);
-        statements = parser.parse().expect("Could not parse sample code.");
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         assert_eq!(printer.print(statements).unwrap(), "nil");
         // string
         scanner = Scanner::new("\"hello\"".to_string());
-        tokens = scanner.scan_tokens().clone();
+        tokens = scanner.scan_tokens().unwrap();
         parser = Parser::new(tokens);
-        statements = parser.parse().expect("Could not parse sample code.");
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         assert_eq!(printer.print(statements).unwrap(), "hello");
         // number
         scanner = Scanner::new("3.141519".to_string());
-        tokens = scanner.scan_tokens().clone();
+        tokens = scanner.scan_tokens().unwrap();
         parser = Parser::new(tokens);
-        statements = parser.parse().expect("Could not parse sample code.");
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         assert_eq!(printer.print(statements).unwrap(), "3.141519");
     }
 
@@ -402,9 +1704,11 @@ mod tests {
     fn test_parser_grouping() {
         // (..)
         let mut scanner = Scanner::new("(2 + 3) * 5".to_string());
-        let tokens = scanner.scan_tokens().clone();
+        let tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens);
-        let statements = parser.parse().expect("Could not parse sample code.");
+        let statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         let mut printer = AstPrinter;
         assert_eq!(printer.print(statements).unwrap(), "(* (group (+ 2 3)) 5)");
     }
@@ -412,10 +1716,737 @@ mod tests {
     #[test]
     fn test_parser_sample_code() {
         let mut scanner = Scanner::new("-123 * 45.67".to_string());
-        let tokens = scanner.scan_tokens().clone();
+        let tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens);
-        let statements = parser.parse().expect("Could not parse sample code.");
+        let statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
         let mut printer = AstPrinter;
         assert_eq!(printer.print(statements).unwrap(), "(* (- 123) 45.67)");
     }
+
+    #[test]
+    fn test_parser_conditional() {
+        // 1 == 2 ? 3 + 4 : 5  ->  (?: (== 1 2) (+ 3 4) 5)
+        let mut scanner = Scanner::new("1 == 2 ? 3 + 4 : 5".to_string());
+        let mut tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let mut statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+        assert_eq!(
+            printer.print(statements).unwrap(),
+            "(?: (== 1 2) (+ 3 4) 5)"
+        );
+        // right associative: true ? 1 : false ? 2 : 3  ->  (?: true 1 (?: false 2 3))
+        scanner = Scanner::new("true ? 1 : false ? 2 : 3".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(
+            printer.print(statements).unwrap(),
+            "(?: true 1 (?: false 2 3))"
+        );
+        // nested in the then branch: true ? false ? 1 : 2 : 3
+        scanner = Scanner::new("true ? false ? 1 : 2 : 3".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(
+            printer.print(statements).unwrap(),
+            "(?: true (?: false 1 2) 3)"
+        );
+    }
+
+    #[test]
+    fn test_parser_conditional_missing_colon() {
+        let mut scanner = Scanner::new("true ? 1".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        assert!(parser.parse_expression().is_err());
+    }
+
+    #[test]
+    fn test_parser_assignment() {
+        // a = b = 1  ->  (a (b 1))
+        let mut scanner = Scanner::new("a = b = 1".to_string());
+        let mut tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let mut statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+        assert_eq!(printer.print(statements).unwrap(), "(a (b 1))");
+        // f(1, 2).y = 3  ->  (y (call f 1 2) 3)
+        scanner = Scanner::new("f(1, 2).y = 3".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(printer.print(statements).unwrap(), "(y (call f 1 2) 3)");
+        // 1 = 2 is not a valid target
+        scanner = Scanner::new("1 = 2".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        assert!(parser.parse_expression().is_err());
+    }
+
+    #[test]
+    fn test_parser_compound_assignment() {
+        // a += 2 * 3  ->  (a (+ a (* 2 3)))
+        let mut scanner = Scanner::new("a += 2 * 3".to_string());
+        let mut tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let mut statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+        assert_eq!(printer.print(statements).unwrap(), "(a (+ a (* 2 3)))");
+        // p.x -= 1  ->  (x p (- (x p) 1))
+        scanner = Scanner::new("p.x -= 1".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(printer.print(statements).unwrap(), "(x p (- (x p) 1))");
+        // a *= b /= 2  ->  (a (* a (b (/ b 2))))
+        scanner = Scanner::new("a *= b /= 2".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(printer.print(statements).unwrap(), "(a (* a (b (/ b 2))))");
+        // 3 %= 2 is not a valid target
+        scanner = Scanner::new("3 %= 2".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        assert!(parser.parse_expression().is_err());
+    }
+
+    #[test]
+    fn test_parser_increment_decrement() {
+        // ++a  ->  (a (+ a 1))
+        let mut scanner = Scanner::new("++a".to_string());
+        let mut tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let mut statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+        assert_eq!(printer.print(statements).unwrap(), "(a (+ a 1))");
+        // --p.x  ->  (x p (- (x p) 1))
+        scanner = Scanner::new("--p.x".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(printer.print(statements).unwrap(), "(x p (- (x p) 1))");
+        // a++ * 2  ->  (* (- (a (+ a 1)) 1) 2)
+        scanner = Scanner::new("a++ * 2".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(
+            printer.print(statements).unwrap(),
+            "(* (- (a (+ a 1)) 1) 2)"
+        );
+        // a--  ->  (+ (a (- a 1)) 1)
+        scanner = Scanner::new("a--".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(printer.print(statements).unwrap(), "(+ (a (- a 1)) 1)");
+    }
+
+    #[test]
+    fn test_parser_invalid_increment_target() {
+        for source in ["++3", "(a)--", "f()++", "++-a"] {
+            let mut scanner = Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens().unwrap();
+            let mut parser = Parser::new(tokens);
+            assert!(
+                parser.parse_expression().is_err(),
+                "{} should not parse",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_bitwise() {
+        // lowest to highest: | ^ & << >>, all between == and <
+        // 1 | 2 ^ 3 & 4 << 5  ->  (| 1 (^ 2 (& 3 (<< 4 5))))
+        let mut scanner = Scanner::new("1 | 2 ^ 3 & 4 << 5".to_string());
+        let mut tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let mut statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+        assert_eq!(
+            printer.print(statements).unwrap(),
+            "(| 1 (^ 2 (& 3 (<< 4 5))))"
+        );
+        // a == b & c < d >> 1  ->  (== a (& b (>> (< c d) 1)))
+        scanner = Scanner::new("a == b & c < d >> 1".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(
+            printer.print(statements).unwrap(),
+            "(== a (& b (>> (< c d) 1)))"
+        );
+        // ~a << 1  ->  (<< (~ a) 1)
+        scanner = Scanner::new("~a << 1".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(printer.print(statements).unwrap(), "(<< (~ a) 1)");
+    }
+
+    #[test]
+    fn test_parser_is() {
+        // a is B  ->  (is a B)
+        let mut scanner = Scanner::new("a is B".to_string());
+        let mut tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let mut statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+        assert_eq!(printer.print(statements).unwrap(), "(is a B)");
+        // a.b is m.C == !c  ->  (== (is (b a) (C m)) (! c))
+        scanner = Scanner::new("a.b is m.C == !c".to_string());
+        tokens = scanner.scan_tokens().unwrap();
+        parser = Parser::new(tokens);
+        statements = parser
+            .parse_expression()
+            .expect("Could not parse sample code.");
+        assert_eq!(
+            printer.print(statements).unwrap(),
+            "(== (is (b a) (C m)) (! c))"
+        );
+    }
+
+    // Parses a whole program and prints every statement on its own line
+    fn print_program(source: &str) -> String {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse().expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+        let printed: Vec<String> = statements
+            .iter()
+            .map(|stmt| printer.print_stmt(stmt).unwrap())
+            .collect();
+        printed.join("\n")
+    }
+
+    #[test]
+    fn test_parser_statements() {
+        assert_eq!(
+            print_program("var a; var b = 1; print a + b; a = 2;"),
+            "(var a)\n(var b 1)\n(print (+ a b))\n(; (a 2))"
+        );
+        assert_eq!(
+            print_program("if (a) { print 1; } else if (b) print 2; else print 3;"),
+            "(if a (block (print 1)) (if b (print 2) (print 3)))"
+        );
+        assert_eq!(
+            print_program("while (a < 3) a = a + 1;"),
+            "(while (< a 3) (; (a (+ a 1))))"
+        );
+    }
+
+    #[test]
+    fn test_parser_conditional_bindings() {
+        assert_eq!(
+            print_program("if (var x = f()) print x; else print 0;"),
+            "(if (var x (call f)) (print x) (print 0))"
+        );
+        assert_eq!(
+            print_program("while (var line = read()) print line;"),
+            "(while (var line (call read)) (print line))"
+        );
+        let tokens = Scanner::new("if (var x) print x;".to_string())
+            .scan_tokens()
+            .unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1, col 10] Error at ')': Expect '=' after variable name."
+        );
+    }
+
+    #[test]
+    fn test_parser_for_desugars_to_while() {
+        assert_eq!(
+            print_program("for (var i = 0; i < 3; i = i + 1) print i;"),
+            "(block (var i 0) (while (< i 3) (block (print i) (; (i (+ i 1))))))"
+        );
+        assert_eq!(print_program("for (;;) {}"), "(while true (block))");
+    }
+
+    #[test]
+    fn test_parser_labeled_loops() {
+        assert_eq!(
+            print_program("outer: while (a) { for (x in xs) { break outer; } continue; }"),
+            "(label outer (while a (block (for-in x xs (block (break outer))) (continue))))"
+        );
+        assert_eq!(
+            print_program("l: for (;;) break;"),
+            "(label l (while true (break)))"
+        );
+        let tokens = Scanner::new("l: print 1;".to_string())
+            .scan_tokens()
+            .unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1, col 4] Error at 'print': Expect a loop after label."
+        );
+    }
+
+    #[test]
+    fn test_parser_lists_and_indexing() {
+        assert_eq!(
+            print_program("var l = [1, 2 + 3, [],];"),
+            "(var l (list 1 (+ 2 3) (list)))"
+        );
+        assert_eq!(
+            print_program("print l[0][i + 1] + f()[2];"),
+            "(print (+ (index (index l 0) (+ i 1)) (index (call f) 2)))"
+        );
+        assert_eq!(
+            print_program("l[0] = 1; o.l[i] += 2; l[0]++;"),
+            concat!(
+                "(; (index= l 0 1))\n",
+                "(; (index= (l o) i (+ (index (l o) i) 2)))\n",
+                "(; (- (index= l 0 (+ (index l 0) 1)) 1))"
+            )
+        );
+        for source in ["[1, 2", "l[]", "l[0", "[,]"] {
+            let mut scanner = Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens().unwrap();
+            let mut parser = Parser::new(tokens);
+            assert!(
+                parser.parse_expression().is_err(),
+                "{} should not parse",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_slices() {
+        assert_eq!(
+            print_program("print s[1:n - 1] + s[:2] + s[i:] + s[:];"),
+            "(print (+ (+ (+ (slice s 1 (- n 1)) (slice s _ 2)) (slice s i _)) (slice s _ _)))"
+        );
+        // the ':' of a conditional is not a slice
+        assert_eq!(
+            print_program("print s[a ? 1 : 2];"),
+            "(print (index s (?: a 1 2)))"
+        );
+        for source in ["s[1:2] = x", "s[1:2:3]", "s[1:"] {
+            let mut scanner = Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens().unwrap();
+            let mut parser = Parser::new(tokens);
+            assert!(
+                parser.parse_expression().is_err(),
+                "{} should not parse",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_maps() {
+        assert_eq!(
+            print_program("var m = {\"a\": 1, 2: [x], \"c\": {},};"),
+            "(var m (map (a 1) (2 (list x)) (c (map))))"
+        );
+        assert_eq!(
+            print_program("m[\"a\"] = c ? 1 : 2; print {k: v}[k];"),
+            "(; (index= m a (?: c 1 2)))\n(print (index (map (k v)) k))"
+        );
+        // at the start of a statement it is still a block
+        assert_eq!(print_program("{ a; }"), "(block (; a))");
+        for source in ["{1}", "{1: }", "{1: 2", "{1: 2 3: 4}"] {
+            let mut scanner = Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens().unwrap();
+            let mut parser = Parser::new(tokens);
+            assert!(
+                parser.parse_expression().is_err(),
+                "{} should not parse",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_for_in() {
+        assert_eq!(
+            print_program("for (x in [1, 2]) print x;"),
+            "(for-in x (list 1 2) (print x))"
+        );
+        assert_eq!(
+            print_program("for (k in m) { print m[k]; }"),
+            "(for-in k m (block (print (index m k))))"
+        );
+        // still a plain for loop when the name is not followed by 'in'
+        assert_eq!(
+            print_program("for (i = 0; i < 2; i++) {}"),
+            "(block (; (i 0)) (while (< i 2) (block (block) (; (- (i (+ i 1)) 1)))))"
+        );
+    }
+
+    #[test]
+    fn test_parser_functions_and_classes() {
+        assert_eq!(
+            print_program("fn add(a, b) { return a + b; } fn f() { return; }"),
+            "(fn add (a b) (return (+ a b)))\n(fn f () (return))"
+        );
+        assert_eq!(
+            print_program("class B < A { init(x) { this.x = x; } }"),
+            "(class B < A (fn init (x) (; (x this x))))"
+        );
+        assert_eq!(
+            print_program("class B < A { f() { return super.f() + super.g; } }"),
+            "(class B < A (fn f () (return (+ (call (super f)) (super g)))))"
+        );
+        assert_eq!(
+            print_program("class P { var x = 0; init() {} var y: Number; }"),
+            "(class P (var x 0) (var y:Number) (fn init ()))"
+        );
+        assert_eq!(
+            print_program("class M { static square(n) { return n * n; } f() {} }"),
+            "(class M (static fn square (n) (return (* n n))) (fn f ()))"
+        );
+        let tokens = Scanner::new("class M { static init() {} }".to_string())
+            .scan_tokens()
+            .unwrap();
+        assert_eq!(
+            Parser::new(tokens).parse().unwrap_err()[0].to_string(),
+            "[line 1, col 18] Error at 'init': The initializer can't be static."
+        );
+        assert_eq!(
+            print_program("class C { area { return 1; } static n: Number { return 2; } }"),
+            "(class C (get area (return 1)) (static get n:Number (return 2)))"
+        );
+        let tokens = Scanner::new("class C { init { } }".to_string())
+            .scan_tokens()
+            .unwrap();
+        assert_eq!(
+            Parser::new(tokens).parse().unwrap_err()[0].to_string(),
+            "[line 1, col 11] Error at 'init': The initializer can't be a getter."
+        );
+    }
+
+    #[test]
+    fn test_parser_imports() {
+        assert_eq!(
+            print_program("import \"utils.lako\" as u;\nfrom \"utils.lako\" import clamp, lerp;"),
+            "(import \"utils.lako\" as u)\n(from \"utils.lako\" import clamp lerp)"
+        );
+        let tokens = Scanner::new("import \"u\";\n{ from \"u\" import a; }".to_string())
+            .scan_tokens()
+            .unwrap();
+        let errors: Vec<String> = Parser::new(tokens)
+            .parse()
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors[..2],
+            [
+                "[line 1, col 11] Error at ';': Expect 'as' after module path.",
+                "[line 2, col 3] Error at 'from': Imports must be at the top level.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parser_exports() {
+        assert_eq!(
+            print_program("export fn f() {}\nexport var x = 1;\nexport class C {}"),
+            "(export (fn f ()))\n(export (var x 1))\n(export (class C))"
+        );
+        let tokens = Scanner::new("export print 1;\nfn f() { export var x; }".to_string())
+            .scan_tokens()
+            .unwrap();
+        let errors: Vec<String> = Parser::new(tokens)
+            .parse()
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors[..2],
+            [
+                "[line 1, col 8] Error at 'print': Expect class, function or variable declaration after 'export'.",
+                "[line 2, col 10] Error at 'export': Only top level declarations can be exported.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parser_match() {
+        assert_eq!(
+            print_program("match (x) { 1, -2 => print \"a\"; \"s\" => {} else => print x; }"),
+            "(match x ((1 (- 2)) (print a)) ((s) (block)) (else (print x)))"
+        );
+        assert_eq!(print_program("match (f()) {}"), "(match (call f))");
+        assert_eq!(
+            print_program("match (x) { 1 if a => {} n if n > 10 => print n; _ => {} }"),
+            "(match x ((1) if a (block)) ((n) if (> n 10) (print n)) ((_) (block)))"
+        );
+        let errors = |source: &str| {
+            let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+            let errors = Parser::new(tokens).parse().unwrap_err();
+            // recovery goes on after the first error
+            errors[0].to_string()
+        };
+        assert_eq!(
+            errors("match (x) { [] => 1; }"),
+            "[line 1, col 13] Error at '[': Expect a literal or a name as pattern."
+        );
+        assert_eq!(
+            errors("match (x) { n if n > 1 print n; }"),
+            "[line 1, col 24] Error at 'print': Expect '=>' after match guard."
+        );
+        assert_eq!(
+            errors("match (x) { else => 1; 2 => 3; }"),
+            "[line 1, col 24] Error at '2': Expect '}' after the else arm."
+        );
+    }
+
+    #[test]
+    fn test_parser_print_values() {
+        assert_eq!(
+            print_program("print a, b + 1, \"c\";"),
+            "(print a (+ b 1) c)"
+        );
+    }
+
+    #[test]
+    fn test_parser_assert() {
+        assert_eq!(
+            print_program("assert a == 1; assert b, \"b is set\";"),
+            "(assert (== a 1))\n(assert b b is set)"
+        );
+    }
+
+    #[test]
+    fn test_parser_try() {
+        assert_eq!(
+            print_program("try { f(); } catch (e) { throw e; } finally { print 1; }"),
+            "(try (block (; (call f))) (catch e (block (throw e))) (finally (block (print 1))))"
+        );
+        assert_eq!(
+            print_program("try {} finally {}"),
+            "(try (block) (finally (block)))"
+        );
+        let tokens = Scanner::new("try {} print 1;".to_string())
+            .scan_tokens()
+            .unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1, col 8] Error at 'print': Expect 'catch' or 'finally' after try block."
+        );
+    }
+
+    #[test]
+    fn test_parser_type_annotations() {
+        assert_eq!(
+            print_program("var x: Number = 3; var s: String;"),
+            "(var x:Number 3)\n(var s:String)"
+        );
+        assert_eq!(
+            print_program("fn add(a: Number, b: Number): Number { return a + b; }"),
+            "(fn add (a:Number b:Number):Number (return (+ a b)))"
+        );
+        assert_eq!(
+            print_program("var f = fn (x, y: Point) {};"),
+            "(var f (fn (x y:Point)))"
+        );
+    }
+
+    #[test]
+    fn test_parser_lambdas() {
+        assert_eq!(
+            print_program("var add = fn (a, b) { return a + b; };"),
+            "(var add (fn (a b) (return (+ a b))))"
+        );
+        assert_eq!(
+            print_program("each(xs, fn (x) { print x; });"),
+            "(; (call each xs (fn (x) (print x))))"
+        );
+        // at the start of a statement too, then called right away
+        assert_eq!(print_program("fn () {}();"), "(; (call (fn ())))");
+    }
+
+    #[test]
+    fn test_parser_reports_every_error() {
+        let source = "var = 1;\nprint ;\nvar ok = 2;\nfn 1() {}\nprint ok".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().expect_err("Sample code should not parse.");
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[line 1, col 5] Error at '=': Expect variable name.",
+                "[line 2, col 7] Error at ';': Expect expression.",
+                "[line 4, col 4] Error at '1': Expect function name.",
+                "[line 5, col 9] Error at end: Expect ';' after value.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parser_suggests_missing_semicolon() {
+        let errors = parse_with_pragmas("var a = 1\nprint a;").unwrap_err();
+        match &errors[0] {
+            Error::Parse {
+                token,
+                help: Some(help),
+                ..
+            } => {
+                assert_eq!(token.lexeme, "print");
+                assert_eq!(help.message, "add ';' after this expression");
+                assert_eq!(help.span, Span::new(8, 9, 1, 9));
+            }
+            e => panic!("expected a help note, got {:?}", e),
+        }
+        // Only when the statement could have ended at the line break
+        for source in &["var a = 1 print a;", "print a"] {
+            match &parse_with_pragmas(source).unwrap_err()[0] {
+                Error::Parse { help, .. } => assert_eq!(help, &None),
+                e => panic!("unexpected error {:?}", e),
+            }
+        }
+    }
+
+    fn parse_with_pragmas(source: &str) -> Result<Vec<Stmt>, Vec<Error>> {
+        let dialect = Dialect::from_source(source)?;
+        let tokens = Scanner::new(source.to_string()).scan_tokens()?;
+        Parser::with_dialect(tokens, dialect).parse()
+    }
+
+    #[test]
+    fn test_parser_optional_semicolons() {
+        let source =
+            "//! optional-semicolons\nvar a = 1\nprint a\nfn f() { return }\nfn g() { return a }";
+        let statements = parse_with_pragmas(source).expect("Could not parse sample code.");
+        let mut printer = AstPrinter;
+        let printed: Vec<String> = statements
+            .iter()
+            .map(|stmt| printer.print_stmt(stmt).unwrap())
+            .collect();
+        assert_eq!(
+            printed,
+            vec![
+                "(var a 1)",
+                "(print a)",
+                "(fn f () (return))",
+                "(fn g () (return a))"
+            ]
+        );
+        // Statements on the same line still need a ';'
+        assert!(parse_with_pragmas("//! optional-semicolons\nvar a = 1 print a").is_err());
+        // Without the pragma semicolons are required
+        assert!(parse_with_pragmas("var a = 1\nprint a").is_err());
+    }
+
+    #[test]
+    fn test_parser_strict_requires_initializers() {
+        assert!(parse_with_pragmas("var a;").is_ok());
+        let errors = parse_with_pragmas("//! strict\nvar a;").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 2, col 6] Error at ';': \
+             Expect '=' after variable name: strict mode requires an initializer."
+        );
+    }
+
+    #[test]
+    fn test_parser_nesting_limit() {
+        let parse = |source: String| {
+            stack::run(move || {
+                let tokens = Scanner::new(source).scan_tokens().unwrap();
+                Parser::new(tokens)
+                    .parse()
+                    .map_err(|errors| errors[0].to_string())
+            })
+        };
+        let nested = |depth| format!("print {}1{};", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(nested(250)).is_ok());
+        assert_eq!(
+            parse(nested(100_000)).unwrap_err(),
+            "[line 1, col 262] Error at '(': Too deeply nested."
+        );
+        assert!(parse(format!("{}1;", "- ".repeat(100_000))).is_err());
+        assert!(parse(format!("{}{}", "{".repeat(100_000), "}".repeat(100_000))).is_err());
+
+        // loops parse chains of operators, calls and properties, so they don't nest
+        let chain = |first: &str, link: &str, n| format!("print {}{};", first, link.repeat(n));
+        assert!(parse(chain("\"x\"", "+\"x\"", 120)).is_ok());
+        assert!(parse(chain("a", ".b", 101)).is_ok());
+        assert!(parse(chain("1", "+1", 9_999)).is_ok());
+        assert_eq!(
+            parse(chain("1", "+1", 100_000)).unwrap_err(),
+            "[line 1, col 20008] Error at '+': Expression is too long."
+        );
+        for link in &[
+            "*1", "==1", "|1", "^1", "&1", "<<1", "<1", ".b", "()", "[0]",
+        ] {
+            assert!(parse(chain("1", link, 100_000)).is_err(), "{}", link);
+        }
+        // but the tree is as tall as the chain and what its operands nest
+        let grouped = format!("{}1{}", "(".repeat(200), "+1)".repeat(200));
+        assert!(parse(format!("print {}{};", grouped, "+1".repeat(9_000))).is_ok());
+        assert!(parse(format!("print {}{};", grouped, "+1".repeat(9_900))).is_err());
+    }
+
+    // Source made of random tokens, most of it nonsense
+    fn token_soup() -> impl proptest::strategy::Strategy<Value = String> {
+        use crate::frontend::token::{KEYWORDS, PUNCTUATORS};
+        use proptest::prelude::*;
+
+        let mut lexemes: Vec<&str> = PUNCTUATORS.iter().map(|(p, _)| *p).collect();
+        lexemes.extend(KEYWORDS.keys());
+        lexemes.extend(&["x", "y", "1", "2.5", "\"s\"", "\"\"", "Number"]);
+        proptest::collection::vec(proptest::sample::select(lexemes), 0..64)
+            .prop_map(|tokens| tokens.join(" "))
+    }
+
+    proptest::proptest! {
+        // Parsing ends with statements or errors, whatever the tokens
+        #[test]
+        fn parses_any_tokens(source in token_soup()) {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            let _ = Parser::new(tokens).parse();
+        }
+    }
 }
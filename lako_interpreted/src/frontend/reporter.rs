@@ -0,0 +1,215 @@
+// Where diagnostics go. The scanner, parser and static passes return their errors and
+// warnings as values; whoever drives them hands each one to a Reporter. The lako binary
+// renders them to stderr as they come, or prints them as JSON lines with
+// --error-format=json, tests and embedders keep them in memory. A LintReporter in
+// front of any of them applies the lint levels.
+
+use crate::frontend::diagnostic::{Diagnostic, Lints, Severity};
+use crate::frontend::error::Error;
+use crate::frontend::grammar::json_string;
+
+pub trait Reporter {
+    fn report(&mut self, diagnostic: Diagnostic);
+
+    // An error with nothing in the source to point at, e.g. an io error
+    fn report_unlocated(&mut self, error: &Error);
+
+    // Whether an error (not just warnings) was reported so far
+    fn had_error(&self) -> bool;
+
+    fn report_errors(&mut self, errors: &[Error]) {
+        for e in errors {
+            match Diagnostic::from_error(e) {
+                Some(diagnostic) => self.report(diagnostic),
+                None => self.report_unlocated(e),
+            }
+        }
+    }
+
+    fn report_all(&mut self, diagnostics: Vec<Diagnostic>) {
+        for diagnostic in diagnostics {
+            self.report(diagnostic);
+        }
+    }
+}
+
+// Renders each diagnostic against the source it came from, on stderr
+pub struct StderrReporter<'a> {
+    source: &'a str,
+    color: bool,
+    had_error: bool,
+}
+
+impl<'a> StderrReporter<'a> {
+    pub fn new(source: &'a str, color: bool) -> StderrReporter<'a> {
+        StderrReporter {
+            source,
+            color,
+            had_error: false,
+        }
+    }
+}
+
+impl Reporter for StderrReporter<'_> {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.had_error |= diagnostic.severity == Severity::Error;
+        eprintln!("{}", diagnostic.render(self.source, self.color));
+    }
+
+    fn report_unlocated(&mut self, error: &Error) {
+        self.had_error = true;
+        eprintln!("{}", error);
+    }
+
+    fn had_error(&self) -> bool {
+        self.had_error
+    }
+}
+
+// Prints each diagnostic as one line of JSON on stderr, see Diagnostic::to_json.
+// Unlocated errors get the same fields, null where there is no location or code.
+pub struct JsonReporter<'a> {
+    file: &'a str,
+    had_error: bool,
+}
+
+impl<'a> JsonReporter<'a> {
+    pub fn new(file: &'a str) -> JsonReporter<'a> {
+        JsonReporter {
+            file,
+            had_error: false,
+        }
+    }
+}
+
+impl Reporter for JsonReporter<'_> {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.had_error |= diagnostic.severity == Severity::Error;
+        eprintln!("{}", diagnostic.to_json(self.file));
+    }
+
+    fn report_unlocated(&mut self, error: &Error) {
+        self.had_error = true;
+        eprintln!("{}", unlocated_json(self.file, error));
+    }
+
+    fn had_error(&self) -> bool {
+        self.had_error
+    }
+}
+
+fn unlocated_json(file: &str, error: &Error) -> String {
+    format!(
+        "{{\"file\":{},\"line\":null,\"col\":null,\"span\":null,\"code\":null,\"severity\":\"error\",\"message\":{},\"help\":null}}",
+        json_string(file),
+        json_string(&error.to_string())
+    )
+}
+
+// Drops the allowed lints and makes the denied ones errors before passing diagnostics on
+pub struct LintReporter<'a> {
+    lints: Lints,
+    inner: Box<dyn Reporter + 'a>,
+}
+
+impl<'a> LintReporter<'a> {
+    pub fn new(lints: Lints, inner: Box<dyn Reporter + 'a>) -> LintReporter<'a> {
+        LintReporter { lints, inner }
+    }
+}
+
+impl Reporter for LintReporter<'_> {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        if let Some(diagnostic) = self.lints.apply(diagnostic) {
+            self.inner.report(diagnostic);
+        }
+    }
+
+    fn report_unlocated(&mut self, error: &Error) {
+        self.inner.report_unlocated(error);
+    }
+
+    fn had_error(&self) -> bool {
+        self.inner.had_error()
+    }
+}
+
+// Keeps everything reported, in order
+#[derive(Debug, Default)]
+pub struct MemoryReporter {
+    pub diagnostics: Vec<Diagnostic>,
+    // the unlocated errors, as they display
+    pub messages: Vec<String>,
+}
+
+impl MemoryReporter {
+    pub fn new() -> MemoryReporter {
+        MemoryReporter::default()
+    }
+}
+
+impl Reporter for MemoryReporter {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn report_unlocated(&mut self, error: &Error) {
+        self.messages.push(error.to_string());
+    }
+
+    fn had_error(&self) -> bool {
+        !self.messages.is_empty()
+            || self
+                .diagnostics
+                .iter()
+                .any(|d| d.severity == Severity::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::diagnostic::UNUSED_VARIABLE;
+    use crate::frontend::scanner::Scanner;
+    use crate::frontend::token::Span;
+    use std::io;
+
+    #[test]
+    fn memory_reporter_keeps_diagnostics() {
+        let mut reporter = MemoryReporter::new();
+        reporter.report(Diagnostic::warning(
+            UNUSED_VARIABLE,
+            "Unused variable 'a'.",
+            Span::new(6, 7, 1, 7),
+        ));
+        assert!(!reporter.had_error());
+
+        let errors = Scanner::new("print @;".to_string())
+            .scan_tokens()
+            .unwrap_err();
+        reporter.report_errors(&errors);
+        assert!(reporter.had_error());
+        let codes: Vec<&str> = reporter.diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["W0201", "E0011"]);
+    }
+
+    #[test]
+    fn memory_reporter_keeps_unlocated_errors() {
+        let mut reporter = MemoryReporter::new();
+        let error = Error::Io(io::Error::other("disk on fire"));
+        reporter.report_errors(&[error]);
+        assert!(reporter.had_error());
+        assert!(reporter.diagnostics.is_empty());
+        assert_eq!(reporter.messages, vec!["disk on fire"]);
+    }
+
+    #[test]
+    fn unlocated_errors_as_json() {
+        let error = Error::Io(io::Error::other("disk on fire"));
+        assert_eq!(
+            unlocated_json("a.lak", &error),
+            "{\"file\":\"a.lak\",\"line\":null,\"col\":null,\"span\":null,\"code\":null,\
+             \"severity\":\"error\",\"message\":\"disk on fire\",\"help\":null}"
+        );
+    }
+}
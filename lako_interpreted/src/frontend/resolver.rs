@@ -0,0 +1,1278 @@
+// Static pass over the parsed program, run before it is executed. It walks the
+// scopes the way the interpreter will and for now reports warnings:
+// - locals (in a block, function or for loop) that are declared but never read.
+//   Globals may be used by a later REPL input so they are left alone, and so are
+//   parameters and names starting with '_'.
+// - statements after an unconditional return, throw, break or continue, which can
+//   never run.
+// - match patterns already matched by an earlier arm without a guard.
+// - property accesses, calls and indexing on a variable that is still nil: declared
+//   without a value (or as nil) and not assigned since. Only uses in the function
+//   that declares it count, as a closure may run after the assignment.
+// - calls of names that are declared nowhere, and calls of functions and classes
+//   with the wrong number of arguments. Top level functions, classes and variables
+//   are known everywhere, even before their declaration.
+//
+// Warnings never stop a program from running, they are returned as Diagnostics. So
+// are the errors it finds: break and continue outside of a loop, or naming a label
+// that no loop around them has. Loops outside a function can't be left from inside.
+// A name imported twice, or imported and also declared at the top level, is an
+// error too. So is importing a name that the module doesn't export, or reading it
+// from an import alias, for the modules given to with_module.
+//
+// On the way it records which declaration every use of a name resolves to, for
+// editor tools like go-to-definition.
+
+use crate::frontend::diagnostic::{self, Diagnostic, Help};
+use crate::frontend::error::Error;
+use crate::frontend::expr_ast::{self, Expr, LiteralValue};
+use crate::frontend::interner::Symbol;
+use crate::frontend::stmt_ast::{self, module_path, Arm, Param, Stmt};
+use crate::frontend::token::{Span, Token};
+use std::collections::HashMap;
+use std::mem;
+
+struct Local {
+    name: Token,
+    // what the warning calls it: variable, function or class
+    kind: &'static str,
+    read: bool,
+    // declared without a value and not assigned since
+    nil: bool,
+    // how many functions deep it was declared
+    function: usize,
+    // the arguments a call takes, for functions and classes that weren't reassigned
+    arity: Option<usize>,
+    // the path of the module, for an import alias
+    module: Option<String>,
+}
+
+// A use of a name and the name in the declaration it resolves to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reference {
+    pub span: Span,
+    pub declaration: Span,
+    // what was declared: variable, parameter, function, class, module (the alias
+    // of an import) or import (a name imported from a module)
+    pub kind: &'static str,
+}
+
+#[derive(Default)]
+pub struct Resolver {
+    // innermost last; empty at the top level
+    scopes: Vec<Vec<Local>>,
+    // only tracked for nil uses, globals are never unused
+    globals: Vec<Local>,
+    // how many function bodies deep the resolver is
+    function: usize,
+    // how many loops deep, and the labels of those with one, in the current function
+    loops: usize,
+    labels: Vec<Symbol>,
+    diagnostics: Vec<Diagnostic>,
+    references: Vec<Reference>,
+    // the names exported by each module given to with_module, by path
+    modules: HashMap<String, Vec<Symbol>>,
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver::default()
+    }
+
+    // A resolver that knows the globals of earlier programs, like previous REPL inputs
+    pub fn with_prelude(prelude: &[Stmt]) -> Resolver {
+        let mut resolver = Resolver::new();
+        resolver.hoist(prelude);
+        resolver
+    }
+
+    // Knows what the module imported by that path exports, so the program can be
+    // checked to only use those names. Imports of other modules aren't checked.
+    pub fn with_module(mut self, path: &str, stmts: &[Stmt]) -> Resolver {
+        let exports = stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Export { decl, .. } => declared(decl),
+                _ => None,
+            })
+            .map(|name| name.lexeme.clone())
+            .collect();
+        self.modules.insert(path.to_string(), exports);
+        self
+    }
+
+    // The warnings and errors for the program, in source order
+    pub fn resolve(self, stmts: &[Stmt]) -> Result<Vec<Diagnostic>, Error> {
+        self.resolve_references(stmts)
+            .map(|(diagnostics, _)| diagnostics)
+    }
+
+    // The warnings and errors, and the references of every name that resolved, both
+    // in source order
+    pub fn resolve_references(
+        mut self,
+        stmts: &[Stmt],
+    ) -> Result<(Vec<Diagnostic>, Vec<Reference>), Error> {
+        self.hoist(stmts);
+        self.imports(stmts);
+        self.stmts(stmts)?;
+        self.diagnostics.sort_by_key(|w| w.span.start);
+        self.references.sort_by_key(|r| r.span.start);
+        Ok((self.diagnostics, self.references))
+    }
+
+    // Resolves a statement list, warning once about the first statement that
+    // follows a return, throw, break or continue
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<(), Error> {
+        let mut returned: Option<&Token> = None;
+        let mut warned = false;
+        for stmt in stmts {
+            match returned {
+                Some(keywd) if !warned => {
+                    warned = true;
+                    self.diagnostics.push(Diagnostic {
+                        help: Some(Help::new(
+                            &format!("Any code after this {} is unreachable.", keywd.lexeme),
+                            keywd.span,
+                        )),
+                        ..Diagnostic::warning(
+                            diagnostic::UNREACHABLE_CODE,
+                            "Unreachable statement.",
+                            stmt.start(),
+                        )
+                    });
+                }
+                Some(_) => {}
+                None => returned = always_returns(stmt),
+            }
+            stmt.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap_or_default();
+        for local in scope {
+            if !local.read && !local.name.lexeme.starts_with('_') {
+                let message = format!("Unused {} '{}'.", local.kind, local.name.lexeme);
+                self.diagnostics.push(Diagnostic::warning(
+                    diagnostic::UNUSED_VARIABLE,
+                    &message,
+                    local.name.span,
+                ));
+            }
+        }
+    }
+
+    // Declares the top level names up front so they can be called from anywhere
+    fn hoist(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt.declaration() {
+                Stmt::Var { name, .. } => self.declare(name, "variable", true),
+                Stmt::Function { name, params, .. } => {
+                    self.declare(name, "function", true);
+                    self.callable(name, params.as_ref().map(Vec::len));
+                }
+                Stmt::Class {
+                    name,
+                    sclass,
+                    methods,
+                    ..
+                } => {
+                    self.declare(name, "class", true);
+                    self.callable(name, class_arity(sclass, methods));
+                }
+                Stmt::Import {
+                    path, alias, names, ..
+                } => {
+                    if let Some(alias) = alias {
+                        self.declare(alias, "module", true);
+                        if let Some(local) = self.lookup(alias) {
+                            local.module = Some(module_path(path).to_string());
+                        }
+                    }
+                    for name in names {
+                        self.declare(name, "import", true);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Each imported name has to be unique among the imports and the top level
+    // declarations of the program, and exported by its module
+    fn imports(&mut self, stmts: &[Stmt]) {
+        let mut imported: Vec<&Token> = Vec::new();
+        for stmt in stmts {
+            if let Stmt::Import {
+                path, alias, names, ..
+            } = stmt
+            {
+                for name in names {
+                    self.exported(module_path(path), name);
+                }
+                for name in alias.iter().chain(names) {
+                    match imported.iter().find(|i| i.lexeme == name.lexeme) {
+                        Some(first) => self.diagnostics.push(Diagnostic {
+                            help: Some(Help::new("It is first imported here.", first.span)),
+                            ..Diagnostic::error(
+                                diagnostic::DUPLICATE_IMPORT,
+                                &format!("'{}' is already imported.", name.lexeme),
+                                name.span,
+                            )
+                        }),
+                        None => imported.push(name),
+                    }
+                }
+            }
+        }
+        for stmt in stmts {
+            let name = match declared(stmt.declaration()) {
+                Some(name) => name,
+                None => continue,
+            };
+            if let Some(import) = imported.iter().find(|i| i.lexeme == name.lexeme) {
+                self.diagnostics.push(Diagnostic {
+                    help: Some(Help::new("It is imported here.", import.span)),
+                    ..Diagnostic::error(
+                        diagnostic::IMPORT_CONFLICT,
+                        &format!("'{}' conflicts with an import.", name.lexeme),
+                        name.span,
+                    )
+                });
+            }
+        }
+    }
+
+    // Reports a name that the module at path doesn't export, if the module is known
+    fn exported(&mut self, path: &str, name: &Token) {
+        let exported = match self.modules.get(path) {
+            Some(exports) => exports.contains(&name.lexeme),
+            None => return,
+        };
+        if !exported {
+            self.diagnostics.push(Diagnostic::error(
+                diagnostic::NOT_EXPORTED,
+                &format!("'{}' is not exported by \"{}\".", name.lexeme, path),
+                name.span,
+            ));
+        }
+    }
+
+    // A property read or set on an import alias has to be exported by its module
+    fn member(&mut self, obj: &Expr, name: &Token) {
+        let path = match obj {
+            Expr::Variable { name: alias } => self.lookup(alias).and_then(|l| l.module.clone()),
+            _ => None,
+        };
+        if let Some(path) = path {
+            self.exported(&path, name);
+        }
+    }
+
+    // Declares a name in the innermost scope, or as a global at the top level
+    fn declare(&mut self, name: &Token, kind: &'static str, read: bool) {
+        let local = Local {
+            name: name.clone(),
+            kind,
+            read,
+            nil: false,
+            function: self.function,
+            arity: None,
+            module: None,
+        };
+        match self.scopes.last_mut() {
+            Some(scope) => scope.push(local),
+            None => self.globals.push(local),
+        }
+    }
+
+    // The innermost variable with the name, local or global
+    fn lookup(&mut self, name: &Token) -> Option<&mut Local> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .chain(std::iter::once(&mut self.globals))
+            .find_map(|scope| {
+                scope
+                    .iter_mut()
+                    .rev()
+                    .find(|l| l.name.lexeme == name.lexeme)
+            })
+    }
+
+    // Marks the innermost local with the name as read
+    fn read(&mut self, name: &Token) {
+        self.reference(name);
+        if let Some(local) = self.lookup(name) {
+            local.read = true;
+        }
+    }
+
+    // Records which declaration a use of the name resolves to
+    fn reference(&mut self, name: &Token) {
+        if let Some((declaration, kind)) = self.lookup(name).map(|l| (l.name.span, l.kind)) {
+            self.references.push(Reference {
+                span: name.span,
+                declaration,
+                kind,
+            });
+        }
+    }
+
+    // Records how many arguments the name just declared takes when called
+    fn callable(&mut self, name: &Token, arity: Option<usize>) {
+        if let Some(local) = self.lookup(name) {
+            local.arity = arity;
+        }
+    }
+
+    // Warns about calling a name that doesn't exist or with the wrong arguments
+    fn call(&mut self, callee: &Expr, args: &[Expr]) {
+        let name = match callee {
+            Expr::Variable { name } => name,
+            _ => return,
+        };
+        let warning = match self.lookup(name) {
+            None => Diagnostic::warning(
+                diagnostic::UNKNOWN_FUNCTION,
+                &format!("Call of undefined function '{}'.", name.lexeme),
+                name.span,
+            ),
+            Some(Local {
+                arity: Some(arity),
+                name: declared,
+                ..
+            }) if *arity != args.len() => Diagnostic {
+                help: Some(Help::new("It is declared here.", declared.span)),
+                ..Diagnostic::warning(
+                    diagnostic::WRONG_ARITY,
+                    &format!(
+                        "'{}' expects {}, got {}.",
+                        name.lexeme,
+                        arguments(*arity),
+                        args.len()
+                    ),
+                    name.span,
+                )
+            },
+            _ => return,
+        };
+        self.diagnostics.push(warning);
+    }
+
+    // Warns if the object of a property access, call or index is a variable that
+    // is still nil
+    fn dereference(&mut self, obj: &Expr) {
+        let function = self.function;
+        let name = match obj {
+            Expr::Variable { name } => name,
+            _ => return,
+        };
+        let declared = match self.lookup(name) {
+            Some(local) if local.nil && local.function == function => local.name.span,
+            _ => return,
+        };
+        let message = format!("'{}' is nil here.", name.lexeme);
+        self.diagnostics.push(Diagnostic {
+            help: Some(Help::new("It is declared here without a value.", declared)),
+            ..Diagnostic::warning(diagnostic::NIL_DEREFERENCE, &message, name.span)
+        });
+    }
+
+    // A type annotation naming a local class reads it
+    fn annotation(&mut self, ty: &Option<Token>) {
+        if let Some(ty) = ty {
+            self.read(ty);
+        }
+    }
+
+    fn function(
+        &mut self,
+        params: &[Param],
+        ret: &Option<Token>,
+        body: &[Stmt],
+    ) -> Result<(), Error> {
+        for param in params {
+            self.annotation(&param.ty);
+        }
+        self.annotation(ret);
+        self.function += 1;
+        let loops = mem::take(&mut self.loops);
+        let labels = mem::take(&mut self.labels);
+        self.begin_scope();
+        for param in params {
+            self.declare(&param.name, "parameter", true);
+        }
+        self.stmts(body)?;
+        self.end_scope();
+        self.loops = loops;
+        self.labels = labels;
+        self.function -= 1;
+        Ok(())
+    }
+
+    // Declares the variable of `if (var x = cond)` or `while (var x = cond)`
+    fn bind(&mut self, binding: &Option<Token>) {
+        if let Some(name) = binding {
+            self.declare(name, "variable", false);
+        }
+    }
+
+    fn loop_body(&mut self, body: &Stmt) -> Result<(), Error> {
+        self.loops += 1;
+        let r = body.accept(self);
+        self.loops -= 1;
+        r
+    }
+
+    // A break or continue needs a loop around it with the label, if it names one
+    fn jump(&mut self, keywd: &Token, label: &Option<Token>) {
+        let error = match label {
+            Some(label) if !self.labels.contains(&label.lexeme) => Diagnostic::error(
+                diagnostic::UNDEFINED_LABEL,
+                &format!("Undefined label '{}'.", label.lexeme),
+                label.span,
+            ),
+            None if self.loops == 0 => Diagnostic::error(
+                diagnostic::JUMP_OUTSIDE_LOOP,
+                &format!("'{}' outside of a loop.", keywd.lexeme),
+                keywd.span,
+            ),
+            _ => return,
+        };
+        self.diagnostics.push(error);
+    }
+}
+
+// The name a class, function or variable declaration declares
+fn declared(stmt: &Stmt) -> Option<&Token> {
+    match stmt {
+        Stmt::Var { name, .. } | Stmt::Function { name, .. } | Stmt::Class { name, .. } => {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+// The value a match pattern stands for: a literal or a negated number
+fn pattern_value(pattern: &Expr) -> Option<LiteralValue> {
+    match pattern {
+        Expr::Literal { val, .. } => Some(val.clone()),
+        Expr::Unary { rhs, .. } => match pattern_value(rhs) {
+            Some(LiteralValue::Number(n)) => Some(LiteralValue::Number(-n)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// "1 argument", "2 arguments"
+fn arguments(n: usize) -> String {
+    if n == 1 {
+        "1 argument".to_string()
+    } else {
+        format!("{} arguments", n)
+    }
+}
+
+// Calling a class takes the arguments of its init method, which may be inherited
+fn class_arity(sclass: &Option<Expr>, methods: &[Stmt]) -> Option<usize> {
+    let init = methods.iter().find_map(|method| match method {
+        Stmt::Function {
+            name,
+            params: Some(params),
+            ..
+        } if name.lexeme == "init" => Some(params.len()),
+        _ => None,
+    });
+    match (init, sclass) {
+        (Some(arity), _) => Some(arity),
+        (None, None) => Some(0),
+        (None, Some(_)) => None,
+    }
+}
+
+fn is_nil(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Literal {
+            val: LiteralValue::Nil,
+            ..
+        }
+    )
+}
+
+// The return, throw, break or continue keyword when the statement leaves whatever
+// happens
+fn always_returns(stmt: &Stmt) -> Option<&Token> {
+    match stmt {
+        Stmt::Return { keywd, .. }
+        | Stmt::Throw { keywd, .. }
+        | Stmt::Break { keywd, .. }
+        | Stmt::Continue { keywd, .. } => Some(keywd),
+        Stmt::Block { stmts, .. } => stmts.iter().find_map(always_returns),
+        _ => None,
+    }
+}
+
+impl expr_ast::Visitor<()> for Resolver {
+    fn visit_assign_expr(&mut self, name: &Token, op: &Token, val: &Expr) -> Result<(), Error> {
+        // a compound assignment reads the variable first
+        if expr_ast::binary_operator(op).is_some() {
+            self.read(name);
+        } else {
+            self.reference(name);
+        }
+        val.accept(self)?;
+        let nil = is_nil(val);
+        if let Some(local) = self.lookup(name) {
+            local.nil = nil;
+            local.arity = None;
+            local.module = None;
+        }
+        Ok(())
+    }
+
+    fn visit_binary_expr(&mut self, lhs: &Expr, _op: &Token, rhs: &Expr) -> Result<(), Error> {
+        lhs.accept(self)?;
+        rhs.accept(self)
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        _paren: &Token,
+        arg: &[Expr],
+    ) -> Result<(), Error> {
+        callee.accept(self)?;
+        self.dereference(callee);
+        self.call(callee, arg);
+        arg.iter().try_for_each(|a| a.accept(self))
+    }
+
+    fn visit_conditional_expr(
+        &mut self,
+        cond: &Expr,
+        then_: &Expr,
+        else_: &Expr,
+    ) -> Result<(), Error> {
+        cond.accept(self)?;
+        then_.accept(self)?;
+        else_.accept(self)
+    }
+
+    fn visit_get_expr(&mut self, obj: &Expr, name: &Token) -> Result<(), Error> {
+        obj.accept(self)?;
+        self.dereference(obj);
+        self.member(obj, name);
+        Ok(())
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<(), Error> {
+        expr.accept(self)
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+    ) -> Result<(), Error> {
+        obj.accept(self)?;
+        self.dereference(obj);
+        index.accept(self)
+    }
+
+    fn visit_index_set_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        _op: &Token,
+        val: &Expr,
+    ) -> Result<(), Error> {
+        obj.accept(self)?;
+        self.dereference(obj);
+        index.accept(self)?;
+        val.accept(self)
+    }
+
+    fn visit_lambda_expr(
+        &mut self,
+        _keywd: &Token,
+        params: &[Param],
+        ret: &Option<Token>,
+        body: &[Stmt],
+        _close: Span,
+    ) -> Result<(), Error> {
+        self.function(params, ret, body)
+    }
+
+    fn visit_list_expr(&mut self, _bracket: &Token, elems: &[Expr]) -> Result<(), Error> {
+        elems.iter().try_for_each(|e| e.accept(self))
+    }
+
+    fn visit_literal_expr(&self, _token: &Token, _val: &LiteralValue) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn visit_logical_expr(&mut self, lhs: &Expr, _op: &Token, rhs: &Expr) -> Result<(), Error> {
+        lhs.accept(self)?;
+        rhs.accept(self)
+    }
+
+    fn visit_map_expr(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> Result<(), Error> {
+        for (key, val) in entries {
+            key.accept(self)?;
+            val.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_set_expr(
+        &mut self,
+        obj: &Expr,
+        name: &Token,
+        _op: &Token,
+        val: &Expr,
+    ) -> Result<(), Error> {
+        obj.accept(self)?;
+        self.dereference(obj);
+        self.member(obj, name);
+        val.accept(self)
+    }
+
+    fn visit_slice_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        from: &Option<Expr>,
+        to: &Option<Expr>,
+    ) -> Result<(), Error> {
+        obj.accept(self)?;
+        for bound in from.iter().chain(to.iter()) {
+            bound.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_super_expr(&mut self, _keywd: &Token, _method: &Token) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn visit_this_expr(&mut self, _keywd: &Token) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn visit_unary_expr(&mut self, _op: &Token, rhs: &Expr) -> Result<(), Error> {
+        rhs.accept(self)
+    }
+
+    fn visit_update_expr(
+        &mut self,
+        _op: &Token,
+        target: &Expr,
+        _prefix: bool,
+    ) -> Result<(), Error> {
+        target.accept(self)
+    }
+
+    fn visit_variable_expr(&mut self, name: &Token) -> Result<(), Error> {
+        self.read(name);
+        Ok(())
+    }
+}
+
+impl stmt_ast::Visitor<()> for Resolver {
+    fn visit_assert_stmt(
+        &mut self,
+        _keywd: &Token,
+        cond: &Expr,
+        message: &Option<Expr>,
+    ) -> Result<(), Error> {
+        cond.accept(self)?;
+        if let Some(message) = message {
+            message.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_block_stmt(&mut self, stmts: &[Stmt], _close: Span) -> Result<(), Error> {
+        self.begin_scope();
+        self.stmts(stmts)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_break_stmt(&mut self, keywd: &Token, label: &Option<Token>) -> Result<(), Error> {
+        self.jump(keywd, label);
+        Ok(())
+    }
+
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        sclass: &Option<Expr>,
+        fields: &[Stmt],
+        methods: &[Stmt],
+        _close: Span,
+    ) -> Result<(), Error> {
+        self.declare(name, "class", false);
+        self.callable(name, class_arity(sclass, methods));
+        if let Some(sclass) = sclass {
+            sclass.accept(self)?;
+        }
+        // field initializers run whenever an instance is made, as if in a method
+        for field in fields {
+            if let Stmt::Var { ty, init, .. } = field {
+                self.annotation(ty);
+                if let Some(init) = init {
+                    self.function += 1;
+                    init.accept(self)?;
+                    self.function -= 1;
+                }
+            }
+        }
+        for method in methods {
+            if let Stmt::Function {
+                params, ret, body, ..
+            } = method
+            {
+                self.function(params.as_deref().unwrap_or_default(), ret, body)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_continue_stmt(&mut self, keywd: &Token, label: &Option<Token>) -> Result<(), Error> {
+        self.jump(keywd, label);
+        Ok(())
+    }
+
+    fn visit_export_stmt(&mut self, _keywd: &Token, decl: &Stmt) -> Result<(), Error> {
+        decl.accept(self)
+    }
+
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<(), Error> {
+        expr.accept(self)
+    }
+
+    fn visit_for_stmt(
+        &mut self,
+        init: &Option<Stmt>,
+        cond: &Option<Expr>,
+        incr: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<(), Error> {
+        // the loop variable lives in its own scope around the loop
+        self.begin_scope();
+        if let Some(init) = init {
+            init.accept(self)?;
+        }
+        if let Some(cond) = cond {
+            cond.accept(self)?;
+        }
+        if let Some(incr) = incr {
+            incr.accept(self)?;
+        }
+        self.loop_body(body)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        name: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+    ) -> Result<(), Error> {
+        iterable.accept(self)?;
+        self.begin_scope();
+        self.declare(name, "variable", false);
+        self.loop_body(body)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        name: &Token,
+        params: &Option<Vec<Param>>,
+        ret: &Option<Token>,
+        body: &[Stmt],
+        _close: Span,
+        _static: bool,
+    ) -> Result<(), Error> {
+        // declared first so the function can call itself
+        self.declare(name, "function", false);
+        self.callable(name, params.as_ref().map(Vec::len));
+        self.function(params.as_deref().unwrap_or_default(), ret, body)
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        else_: &Option<Stmt>,
+        then_: &Stmt,
+    ) -> Result<(), Error> {
+        cond.accept(self)?;
+        self.begin_scope();
+        self.bind(binding);
+        then_.accept(self)?;
+        self.end_scope();
+        if let Some(else_) = else_ {
+            else_.accept(self)?;
+        }
+        Ok(())
+    }
+
+    // the names are declared by hoist, as imports only come at the top level
+    fn visit_import_stmt(
+        &mut self,
+        _keywd: &Token,
+        _path: &Token,
+        _alias: &Option<Token>,
+        _names: &[Token],
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn visit_labeled_stmt(&mut self, label: &Token, body: &Stmt) -> Result<(), Error> {
+        self.labels.push(label.lexeme.clone());
+        let r = body.accept(self);
+        self.labels.pop();
+        r
+    }
+
+    fn visit_match_stmt(
+        &mut self,
+        _keywd: &Token,
+        subject: &Expr,
+        arms: &[Arm],
+        else_: &Option<Stmt>,
+        _close: Span,
+    ) -> Result<(), Error> {
+        subject.accept(self)?;
+        let mut seen: Vec<(LiteralValue, Span)> = Vec::new();
+        for arm in arms {
+            // names in the patterns hold the subject in the guard and the body
+            self.begin_scope();
+            for pattern in &arm.patterns {
+                if let Expr::Variable { name } = pattern {
+                    self.declare(name, "variable", false);
+                }
+                let val = match pattern_value(pattern) {
+                    Some(val) => val,
+                    None => continue,
+                };
+                match seen.iter().find(|(v, _)| *v == val) {
+                    Some((_, first)) => self.diagnostics.push(Diagnostic {
+                        help: Some(Help::new("It is already matched here.", *first)),
+                        ..Diagnostic::warning(
+                            diagnostic::DUPLICATE_PATTERN,
+                            "Duplicate match pattern.",
+                            pattern.start(),
+                        )
+                    }),
+                    // a later arm may still match when the guard is false
+                    None if arm.guard.is_none() => seen.push((val, pattern.start())),
+                    None => {}
+                }
+            }
+            if let Some(guard) = &arm.guard {
+                guard.accept(self)?;
+            }
+            arm.body.accept(self)?;
+            self.end_scope();
+        }
+        if let Some(else_) = else_ {
+            else_.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_print_stmt(&mut self, expr: &Expr, rest: &[Expr]) -> Result<(), Error> {
+        expr.accept(self)?;
+        rest.iter().try_for_each(|e| e.accept(self))
+    }
+
+    fn visit_return_stmt(&mut self, _keywd: &Token, val: &Option<Expr>) -> Result<(), Error> {
+        if let Some(val) = val {
+            val.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_throw_stmt(&mut self, _keywd: &Token, val: &Expr) -> Result<(), Error> {
+        val.accept(self)
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        _keywd: &Token,
+        body: &Stmt,
+        catch: &Option<(Token, Stmt)>,
+        finally: &Option<Stmt>,
+    ) -> Result<(), Error> {
+        body.accept(self)?;
+        if let Some((name, body)) = catch {
+            // like a parameter, the caught value needn't be used
+            self.begin_scope();
+            self.declare(name, "variable", true);
+            body.accept(self)?;
+            self.end_scope();
+        }
+        if let Some(finally) = finally {
+            finally.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        name: &Token,
+        ty: &Option<Token>,
+        init: &Option<Expr>,
+    ) -> Result<(), Error> {
+        // the initializer sees the variable it shadows, not the new one
+        self.annotation(ty);
+        if let Some(init) = init {
+            init.accept(self)?;
+        }
+        self.declare(name, "variable", false);
+        let nil = init.as_ref().is_none_or(is_nil);
+        if let Some(local) = self.lookup(name) {
+            local.nil = nil;
+        }
+        Ok(())
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        body: &Stmt,
+    ) -> Result<(), Error> {
+        cond.accept(self)?;
+        self.begin_scope();
+        self.bind(binding);
+        self.loop_body(body)?;
+        self.end_scope();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::diagnostic::Severity;
+    use crate::frontend::parser::Parser;
+    use crate::frontend::scanner::Scanner;
+
+    fn warnings(source: &str) -> Vec<(&'static str, String, usize)> {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let warnings = Resolver::new().resolve(&stmts).unwrap();
+        assert!(warnings.iter().all(|w| w.severity == Severity::Warning));
+        warnings
+            .into_iter()
+            .map(|w| (w.code, w.message, w.span.start))
+            .collect()
+    }
+
+    #[test]
+    fn unused_locals() {
+        let source = "var g = 1;\n\
+                      {\n\
+                        var a = 1; var b = 2; var _c = 3;\n\
+                        var n = 0; n += 1;\n\
+                        fn f(x) { var a = g; }\n\
+                        print b;\n\
+                      }\n\
+                      for (var i = 0; i < 3; i++) { var k = i; k = 2; }";
+        assert_eq!(
+            warnings(source),
+            vec![
+                (UNUSED, "Unused variable 'a'.".to_string(), 17),
+                (UNUSED, "Unused function 'f'.".to_string(), 69),
+                (UNUSED, "Unused variable 'a'.".to_string(), 80),
+                (UNUSED, "Unused variable 'k'.".to_string(), 134),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_resolve_to_the_innermost_local() {
+        let source = "{ var a = 1; { var a = a; print a; } }";
+        assert_eq!(warnings(source), vec![]);
+    }
+
+    #[test]
+    fn statements_after_return() {
+        let source = "fn f() {\n  print 1;\n  { return 2; }\n  print 3;\n  print 4;\n}\nprint f();";
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let warnings = Resolver::new().resolve(&stmts).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, diagnostic::UNREACHABLE_CODE);
+        assert_eq!(warnings[0].span, Span::new(44, 45, 4, 9));
+        assert_eq!(
+            warnings[0].help,
+            Some(Help::new(
+                "Any code after this return is unreachable.",
+                Span::new(24, 30, 3, 5)
+            ))
+        );
+    }
+
+    #[test]
+    fn break_and_continue_need_their_loop() {
+        let source =
+            "outer: while (a) {\n  for (x in xs) { if (x) break outer; continue nope; }\n}\n\
+                      if (a) break;\nwhile (a) { fn f() { continue; } f(); }";
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let errors: Vec<_> = Resolver::new()
+            .resolve(&stmts)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.severity, e.code, e.message, e.span.start))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    Severity::Error,
+                    diagnostic::UNDEFINED_LABEL,
+                    "Undefined label 'nope'.".to_string(),
+                    66
+                ),
+                (
+                    Severity::Error,
+                    diagnostic::JUMP_OUTSIDE_LOOP,
+                    "'break' outside of a loop.".to_string(),
+                    83
+                ),
+                // a function body can't leave the loop it is declared in
+                (
+                    Severity::Error,
+                    diagnostic::JUMP_OUTSIDE_LOOP,
+                    "'continue' outside of a loop.".to_string(),
+                    111
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_match_patterns() {
+        let source = "match (x) { 1, -1 => {} \"a\" => {} -1, \"a\", 1.0 => {} else => {} }";
+        let duplicate = |at| (DUPLICATE, "Duplicate match pattern.".to_string(), at);
+        assert_eq!(
+            warnings(source),
+            vec![duplicate(34), duplicate(38), duplicate(43)]
+        );
+    }
+
+    #[test]
+    fn match_bindings_and_guards() {
+        // n is read by its guard and gone after the match; a guarded 1 doesn't
+        // match everything a later 1 would
+        let source = "match (x) { n if n > 1 => {} 1 if x => {} 1 => {} m => {} _ => {} }\nn();";
+        assert_eq!(
+            warnings(source),
+            vec![
+                (UNUSED, "Unused variable 'm'.".to_string(), 50),
+                (
+                    diagnostic::UNKNOWN_FUNCTION,
+                    "Call of undefined function 'n'.".to_string(),
+                    68
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn conditional_bindings() {
+        // x is never nil in the then branch, and the else branch doesn't see it
+        let source = "fn f() {}\nif (var x = f()) x.y; else x();\nwhile (var l = f()) {}";
+        assert_eq!(
+            warnings(source),
+            vec![
+                (
+                    diagnostic::UNKNOWN_FUNCTION,
+                    "Call of undefined function 'x'.".to_string(),
+                    37
+                ),
+                (UNUSED, "Unused variable 'l'.".to_string(), 53),
+            ]
+        );
+    }
+
+    #[test]
+    fn nil_dereferences() {
+        let source = "var a; a.f();\n\
+                      var b = nil; b();\n\
+                      var c; c = 1; c.f;\n\
+                      fn f() { a[0]; var d; d.x = 1; }\n\
+                      var e; e = nil; print e.x;";
+        let nil = |name, at| (NIL, format!("'{}' is nil here.", name), at);
+        assert_eq!(
+            warnings(source),
+            vec![nil("a", 7), nil("b", 27), nil("d", 73), nil("e", 106)]
+        );
+    }
+
+    #[test]
+    fn calls() {
+        let source = "fn f() { return g(1, 2); }\n\
+                      fn g(a, b) {}\n\
+                      class P { init(x) {} } class Q < P {} class R {}\n\
+                      g(1); P(); Q(); R(1); h();\n\
+                      { var g = fn (x) {}; g(1); }";
+        let arity = |message: &str, at| (ARITY, message.to_string(), at);
+        assert_eq!(
+            warnings(source),
+            vec![
+                arity("'g' expects 2 arguments, got 1.", 90),
+                arity("'P' expects 1 argument, got 0.", 96),
+                arity("'R' expects 0 arguments, got 1.", 106),
+                (
+                    diagnostic::UNKNOWN_FUNCTION,
+                    "Call of undefined function 'h'.".to_string(),
+                    112
+                ),
+            ]
+        );
+        let prelude = Parser::new(Scanner::new("fn h() {}".to_string()).scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+        let stmts = Parser::new(Scanner::new("h(1);".to_string()).scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+        let warnings = Resolver::with_prelude(&prelude).resolve(&stmts).unwrap();
+        assert_eq!(warnings[0].message, "'h' expects 0 arguments, got 1.");
+    }
+
+    #[test]
+    fn references() {
+        let source = "fn f(a) { var b = a; b = 2; return b; }\n\
+                      print f(1);\n\
+                      { var f = 1; f += g; }\n\
+                      fn g() {}";
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let (_, references) = Resolver::new().resolve_references(&stmts).unwrap();
+        let spans: Vec<(usize, usize, &str)> = references
+            .iter()
+            .map(|r| (r.span.start, r.declaration.start, r.kind))
+            .collect();
+        // a, b = 2, b, the global f, the local f, and g before its declaration
+        assert_eq!(
+            spans,
+            vec![
+                (18, 5, "parameter"),
+                (21, 14, "variable"),
+                (35, 14, "variable"),
+                (46, 3, "function"),
+                (65, 58, "variable"),
+                (70, 78, "function")
+            ]
+        );
+    }
+
+    #[test]
+    fn imported_names_are_unique() {
+        let source = "import \"a\" as u;\n\
+                      from \"b\" import clamp, lerp, clamp;\n\
+                      from \"c\" import u;\n\
+                      fn lerp() {}";
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let errors = Resolver::new().resolve(&stmts).unwrap();
+        let errors: Vec<_> = errors
+            .into_iter()
+            .map(|e| {
+                (
+                    e.code,
+                    e.message,
+                    e.span.start,
+                    e.help.map(|h| h.span.start),
+                )
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    diagnostic::DUPLICATE_IMPORT,
+                    "'clamp' is already imported.".to_string(),
+                    46,
+                    Some(33)
+                ),
+                (
+                    diagnostic::DUPLICATE_IMPORT,
+                    "'u' is already imported.".to_string(),
+                    69,
+                    Some(14)
+                ),
+                (
+                    diagnostic::IMPORT_CONFLICT,
+                    "'lerp' conflicts with an import.".to_string(),
+                    75,
+                    Some(40)
+                ),
+            ]
+        );
+
+        let source = "import \"a\" as u; from \"b\" import clamp; print clamp(u.x);";
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let (errors, references) = Resolver::new().resolve_references(&stmts).unwrap();
+        assert!(errors.is_empty());
+        let kinds: Vec<_> = references.iter().map(|r| (r.span.start, r.kind)).collect();
+        assert_eq!(kinds, vec![(46, "import"), (52, "module")]);
+    }
+
+    #[test]
+    fn imports_need_exports() {
+        let parse = |source: &str| {
+            let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+            Parser::new(tokens).parse().unwrap()
+        };
+        let module = parse("export fn clamp(x) {} fn helper() {} export var scale = 2;");
+        let stmts = parse(
+            "import \"m\" as m; from \"m\" import clamp, helper; from \"n\" import any;\n\
+             print m.clamp(m.scale) + m.helper; m.scale = 1;\n\
+             { var m = {}; m.helper; }",
+        );
+        let errors: Vec<_> = Resolver::new()
+            .with_module("m", &module)
+            .resolve(&stmts)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.code, e.message, e.span.start))
+            .collect();
+        let not_exported = |at| {
+            (
+                diagnostic::NOT_EXPORTED,
+                "'helper' is not exported by \"m\".".to_string(),
+                at,
+            )
+        };
+        // "n" wasn't given, and the local m is no module
+        assert_eq!(errors, vec![not_exported(40), not_exported(96)]);
+    }
+
+    const UNUSED: &str = diagnostic::UNUSED_VARIABLE;
+    const DUPLICATE: &str = diagnostic::DUPLICATE_PATTERN;
+    const NIL: &str = diagnostic::NIL_DEREFERENCE;
+    const ARITY: &str = diagnostic::WRONG_ARITY;
+}
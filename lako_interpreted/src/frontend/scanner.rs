@@ -1,45 +1,104 @@
-use crate::frontend::error;
-use crate::frontend::token::{Token, TokenType, KEYWORDS};
-
+use crate::frontend::diagnostic;
+use crate::frontend::error::Error;
+use crate::frontend::interner::Interner;
+use crate::frontend::token::{Literal, Span, Token, TokenType, KEYWORDS};
+use std::mem;
+
+// The scanner walks the source by byte offset: start/current always sit on UTF-8
+// character boundaries, so slicing lexemes out of the source is O(1) and safe.
+// Lexemes and string literals are interned, so repeated names share one allocation.
 pub struct Scanner {
     source: String,
+    // keep whitespace and comments as tokens, see with_trivia
+    trivia: bool,
     tokens: Vec<Token>,
+    interner: Interner,
+    errors: Vec<Error>,
     start: usize,
     current: usize,
     line: i32,
+    col: usize,      // column of current, counted in characters
+    start_line: i32, // line and column where the current token starts
+    start_col: usize,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Scanner {
         Scanner {
             source,
+            trivia: false,
             tokens: Vec::new(),
+            interner: Interner::new(),
+            errors: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            col: 1,
+            start_line: 1,
+            start_col: 1,
+        }
+    }
+
+    // A scanner that also returns the whitespace and comments between tokens as
+    // Whitespace and Comment tokens, so the lexemes add up to the whole source.
+    // The shebang line is a Comment. Used by the formatter.
+    pub fn with_trivia(source: String) -> Scanner {
+        Scanner {
+            trivia: true,
+            ..Scanner::new(source)
         }
     }
 
     // Key public method
-    pub fn scan_tokens(&mut self) -> &Vec<Token> {
+    // Scanning keeps going after an error so all of them are returned together.
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Vec<Error>> {
+        let (tokens, errors) = self.scan_all();
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // The tokens scanned around the errors as well as the errors, for tools that work
+    // on broken source like the highlighter. An unterminated string makes no token.
+    pub fn scan_all(&mut self) -> (Vec<Token>, Vec<Error>) {
+        // A #! line at the very start lets scripts run as executables
+        if self.current == 0 && self.source.starts_with("#!") {
+            while self.peek() != '\n' && !self.is_at_end() {
+                self.advance();
+            }
+            self.add_trivia(TokenType::Comment);
+        }
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_col = self.col;
             self.scan_token();
         }
 
-        self.tokens.push(Token::new(TokenType::Eof, "", self.line));
-        &self.tokens
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_col = self.col;
+        self.tokens
+            .push(Token::new(TokenType::Eof, "", self.current_span()));
+        (mem::take(&mut self.tokens), mem::take(&mut self.errors))
     }
 
     // Helper methods
+    // The source from current on
+    fn rest(&self) -> &str {
+        &self.source[self.current..]
+    }
+
     // Peek current char without advancing
     fn peek(&self) -> char {
-        self.source.chars().nth(self.current).unwrap_or('\0')
+        self.rest().chars().next().unwrap_or('\0')
     }
 
     // peek 1 char further from current
     fn peek_next(&self) -> char {
-        self.source.chars().nth(self.current + 1).unwrap_or('\0')
+        self.rest().chars().nth(1).unwrap_or('\0')
     }
 
     fn is_at_end(&self) -> bool {
@@ -47,17 +106,72 @@ impl Scanner {
     }
 
     fn advance(&mut self) -> char {
-        self.current += 1;
-        let char_vec: Vec<char> = self.source.chars().collect();
-        char_vec[self.current - 1]
+        let c = self.peek();
+        self.current += c.len_utf8();
+        self.col += 1;
+        c
+    }
+
+    // Call after consuming a '\n'
+    fn newline(&mut self) {
+        self.line += 1;
+        self.col = 1;
+    }
+
+    // Span of the token being scanned, from its first character to current
+    fn current_span(&self) -> Span {
+        Span::new(self.start, self.current, self.start_line, self.start_col)
+    }
+
+    // Records an error at the token being scanned
+    fn error(&mut self, code: &'static str, message: &str) {
+        self.errors.push(Error::Scan {
+            span: self.current_span(),
+            code,
+            message: message.to_string(),
+        });
     }
 
     fn add_token(&mut self, t_type: TokenType) {
+        self.push_token(t_type, None)
+    }
+
+    fn add_literal_token(&mut self, t_type: TokenType, literal: Literal) {
+        self.push_token(t_type, Some(literal))
+    }
+
+    fn add_trivia(&mut self, t_type: TokenType) {
+        if self.trivia {
+            self.push_token(t_type, None)
+        }
+    }
+
+    // A run of whitespace, newlines included
+    fn whitespace(&mut self, first: char) {
+        if first == '\n' {
+            self.newline();
+        }
+        while matches!(self.peek(), ' ' | '\r' | '\t' | '\n') {
+            if self.advance() == '\n' {
+                self.newline();
+            }
+        }
+        self.add_trivia(TokenType::Whitespace);
+    }
+
+    fn push_token(&mut self, t_type: TokenType, literal: Option<Literal>) {
         let text = self
             .source
             .get(self.start..self.current)
             .expect("Source token is empty.");
-        self.tokens.push(Token::new(t_type, text, self.line))
+        let lexeme = self.interner.intern(text);
+        let span = self.current_span();
+        self.tokens.push(Token {
+            t_type,
+            lexeme,
+            literal,
+            span,
+        })
     }
 
     // Process identifiers
@@ -73,53 +187,130 @@ impl Scanner {
             .expect("Unexpected end.");
 
         // Save either the keyword or the identifier
-        let t_type: TokenType = KEYWORDS
-            .get(text)
-            .cloned()
-            .unwrap_or(TokenType::Identifier {
-                literal: text.to_string(),
-            });
+        let t_type: TokenType = KEYWORDS.get(text).copied().unwrap_or(TokenType::Identifier);
         self.add_token(t_type);
     }
 
-    // Process numbers
-    fn number(&mut self) {
-        while self.peek().is_ascii_digit() {
-            self.advance();
+    // Process numbers: 1_000, 2.5, 1e-3, 0xFF, 0b1010 and 0o755.
+    // Every number is an f64; prefixed literals are exact up to 2^53.
+    fn number(&mut self, first: char) {
+        if first == '0' {
+            let radix = match self.peek() {
+                'x' | 'X' => Some(16),
+                'b' | 'B' => Some(2),
+                'o' | 'O' => Some(8),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                self.advance();
+                return self.radix_number(radix);
+            }
         }
 
+        self.digits();
         // Look for a fractional part
         if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             // Consume the ".".
             self.advance();
-
-            while self.peek().is_ascii_digit() {
+            self.digits();
+        }
+        // And an exponent
+        if matches!(self.peek(), 'e' | 'E') {
+            let mut rest = self.rest().chars().skip(1);
+            let exponent = match rest.next() {
+                Some('+') | Some('-') => rest.next(),
+                c => c,
+            };
+            if exponent.is_some_and(|c| c.is_ascii_digit()) {
                 self.advance();
+                if matches!(self.peek(), '+' | '-') {
+                    self.advance();
+                }
+                self.digits();
             }
         }
 
-        let n: f64 = self
+        let text = self
             .source
             .get(self.start..self.current)
-            .expect("Unexpected end.")
+            .expect("Unexpected end.");
+        // '_' only goes between two digits
+        let misplaced = text
+            .split(|c: char| !c.is_ascii_digit() && c != '_')
+            .any(|group| group.starts_with('_') || group.ends_with('_') || group.contains("__"));
+        if misplaced {
+            self.error(
+                diagnostic::MALFORMED_NUMBER,
+                "'_' must separate digits in a number.",
+            );
+            return;
+        }
+        let n: f64 = text
+            .replace('_', "")
             .parse()
             .expect("Scanned number could not be parsed.");
         // add the number literal to tokens
-        self.add_token(TokenType::Number { literal: n })
+        self.add_literal_token(TokenType::Number, Literal::Number(n))
+    }
+
+    // Digits and '_' separators
+    fn digits(&mut self) {
+        while self.peek().is_ascii_digit() || self.peek() == '_' {
+            self.advance();
+        }
+    }
+
+    // The digits after a 0x, 0b or 0o prefix
+    fn radix_number(&mut self, radix: u32) {
+        // Take any letters too, so 0b102 or 0xFG is one bad literal, not a number and a name
+        while self.peek().is_alphanumeric() || self.peek() == '_' {
+            self.advance();
+        }
+
+        let text = self
+            .source
+            .get((self.start + 2)..self.current)
+            .expect("Unexpected end.");
+        let base = match radix {
+            16 => "hexadecimal",
+            8 => "octal",
+            _ => "binary",
+        };
+        if let Some(c) = text.chars().find(|c| *c != '_' && !c.is_digit(radix)) {
+            let message = format!("Invalid digit '{}' in {} number.", c, base);
+            self.error(diagnostic::MALFORMED_NUMBER, &message);
+            return;
+        }
+        if text.ends_with('_') || text.contains("__") {
+            self.error(
+                diagnostic::MALFORMED_NUMBER,
+                "'_' must separate digits in a number.",
+            );
+            return;
+        }
+        let digits = text.replace('_', "");
+        if digits.is_empty() {
+            let message = format!("Expect digits in {} number.", base);
+            self.error(diagnostic::MALFORMED_NUMBER, &message);
+            return;
+        }
+        match u64::from_str_radix(&digits, radix) {
+            Ok(n) => self.add_literal_token(TokenType::Number, Literal::Number(n as f64)),
+            Err(_) => self.error(diagnostic::MALFORMED_NUMBER, "Number is too large."),
+        }
     }
 
     // Process literal strings
     fn string(&mut self) {
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            if self.advance() == '\n' {
+                self.newline();
             }
-            self.advance();
         }
 
         // Unterminated string
         if self.is_at_end() {
-            error::error(self.line, "Unterminated string.");
+            self.error(diagnostic::UNTERMINATED_STRING, "Unterminated string.");
             return; // we should not advance for the closing ", nor load the token
         }
 
@@ -127,31 +318,76 @@ impl Scanner {
         self.advance();
 
         // Trim the surrounding quotes.
-        let literal = self
+        let text = self
             .source
             .get((self.start + 1)..(self.current - 1))
-            .expect("Unexpected end.")
-            .to_string();
+            .expect("Unexpected end.");
+        let literal = Literal::String(self.interner.intern(text));
         // add the string literal to tokens
-        self.add_token(TokenType::String { literal });
+        self.add_literal_token(TokenType::String, literal);
     }
 
-    // Compare characters
-    fn c_match(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
+    // After a '<': is this "<<~TAG" with nothing else up to the end of the line?
+    // Otherwise it is a shift followed by '~', as in  a <<~b;
+    fn is_heredoc_start(&self) -> bool {
+        let mut rest = self.rest().chars().peekable();
+        if rest.next() != Some('<') || rest.next() != Some('~') {
             return false;
         }
-        if self
-            .source
-            .chars()
-            .nth(self.current)
-            .expect("Unexpected end of source.")
-            != expected
-        {
+        let mut tag_len = 0;
+        while rest.next_if(|c| c.is_alphanumeric() || *c == '_').is_some() {
+            tag_len += 1;
+        }
+        tag_len > 0 && rest.take_while(|c| *c != '\n').all(char::is_whitespace)
+    }
+
+    // Process heredocs: <<~TAG, the text lines, then TAG alone on its own line.
+    // The common leading indentation of the text lines is trimmed.
+    fn heredoc(&mut self) {
+        // Consume the "<~" following the first '<'.
+        self.advance();
+        self.advance();
+
+        let mut tag = String::new();
+        while self.peek().is_alphanumeric() || self.peek() == '_' {
+            tag.push(self.advance());
+        }
+        // Only whitespace is left on the opening line, see is_heredoc_start.
+        while self.peek() != '\n' && !self.is_at_end() {
+            self.advance();
+        }
+
+        let mut lines: Vec<String> = Vec::new();
+        loop {
+            if self.is_at_end() {
+                self.error(diagnostic::UNTERMINATED_HEREDOC, "Unterminated heredoc.");
+                return;
+            }
+            // The newline ending the previous line.
+            self.advance();
+            self.newline();
+
+            let mut line = String::new();
+            while self.peek() != '\n' && !self.is_at_end() {
+                line.push(self.advance());
+            }
+            if line.trim() == tag {
+                break;
+            }
+            lines.push(line);
+        }
+
+        let literal = Literal::String(self.interner.intern(&trim_common_indent(&lines)));
+        self.add_literal_token(TokenType::String, literal);
+    }
+
+    // Compare characters
+    fn c_match(&mut self, expected: char) -> bool {
+        if self.is_at_end() || self.peek() != expected {
             return false;
         }
 
-        self.current += 1;
+        self.advance();
         true
     }
 
@@ -162,13 +398,50 @@ impl Scanner {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             '}' => self.add_token(TokenType::RightBrace),
             ',' => self.add_token(TokenType::Comma),
             '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
-            '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
-            '*' => self.add_token(TokenType::Star),
+            '-' => {
+                if self.c_match('=') {
+                    self.add_token(TokenType::MinusEqual)
+                } else if self.c_match('-') {
+                    self.add_token(TokenType::MinusMinus)
+                } else {
+                    self.add_token(TokenType::Minus)
+                }
+            }
+            '+' => {
+                if self.c_match('=') {
+                    self.add_token(TokenType::PlusEqual)
+                } else if self.c_match('+') {
+                    self.add_token(TokenType::PlusPlus)
+                } else {
+                    self.add_token(TokenType::Plus)
+                }
+            }
+            '*' => {
+                if self.c_match('=') {
+                    self.add_token(TokenType::StarEqual)
+                } else {
+                    self.add_token(TokenType::Star)
+                }
+            }
+            '%' => {
+                if self.c_match('=') {
+                    self.add_token(TokenType::PercentEqual)
+                } else {
+                    self.add_token(TokenType::Percent)
+                }
+            }
+            '&' => self.add_token(TokenType::Ampersand),
+            '|' => self.add_token(TokenType::Pipe),
+            '^' => self.add_token(TokenType::Caret),
+            '~' => self.add_token(TokenType::Tilde),
+            '?' => self.add_token(TokenType::Question),
+            ':' => self.add_token(TokenType::Colon),
             '!' => {
                 if self.c_match('=') {
                     self.add_token(TokenType::BangEqual)
@@ -179,19 +452,27 @@ impl Scanner {
             '=' => {
                 if self.c_match('=') {
                     self.add_token(TokenType::EqualEqual)
+                } else if self.c_match('>') {
+                    self.add_token(TokenType::EqualGreater)
                 } else {
                     self.add_token(TokenType::Equal)
                 }
             }
             '<' => {
-                if self.c_match('=') {
+                if self.is_heredoc_start() {
+                    self.heredoc()
+                } else if self.c_match('<') {
+                    self.add_token(TokenType::LessLess)
+                } else if self.c_match('=') {
                     self.add_token(TokenType::LessEqual)
                 } else {
                     self.add_token(TokenType::Less)
                 }
             }
             '>' => {
-                if self.c_match('=') {
+                if self.c_match('>') {
+                    self.add_token(TokenType::GreaterGreater)
+                } else if self.c_match('=') {
                     self.add_token(TokenType::GreaterEqual)
                 } else {
                     self.add_token(TokenType::Greater)
@@ -203,35 +484,56 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    self.add_trivia(TokenType::Comment);
+                } else if self.c_match('=') {
+                    self.add_token(TokenType::SlashEqual)
                 } else {
                     self.add_token(TokenType::Slash)
                 }
             }
-            ' ' | '\r' | '\t' => (), // Ignore whitespace
-            '\n' => self.line += 1,
+            ' ' | '\r' | '\t' | '\n' => self.whitespace(c),
             '"' => self.string(),
             c => {
                 if c.is_ascii_digit() {
-                    self.number()
+                    self.number(c)
                 } else if c.is_alphabetic() || c == '_' {
                     self.identifier()
                 } else {
-                    error::error(self.line, "Unexpected character.")
+                    self.error(diagnostic::UNEXPECTED_CHARACTER, "Unexpected character.")
                 }
             }
         }
     }
 }
 
+// Strips the indentation shared by all non-blank lines and joins them back,
+// each line keeping its trailing newline.
+fn trim_common_indent(lines: &[String]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.chars().take_while(|c| *c == ' ' || *c == '\t').count())
+        .min()
+        .unwrap_or(0);
+
+    let mut text = String::new();
+    for line in lines {
+        text.extend(line.chars().skip(indent));
+        text.push('\n');
+    }
+    text
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::frontend::token::PUNCTUATORS;
 
     #[test]
     fn single_char_token() {
         let plus = "+".to_string();
         let mut scanner = Scanner::new(plus);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         assert_eq!(tokens[0].t_type, TokenType::Plus);
     }
 
@@ -239,40 +541,44 @@ mod tests {
     fn longer_tokens() {
         let eqeq = "==".to_string();
         let mut scanner = Scanner::new(eqeq);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         assert_eq!(tokens[0].t_type, TokenType::EqualEqual);
     }
 
+    #[test]
+    fn punctuators_table_matches_scanner() {
+        for (lexeme, t_type) in PUNCTUATORS {
+            let mut scanner = Scanner::new(lexeme.to_string());
+            let tokens = scanner.scan_tokens().unwrap();
+            assert_eq!(&tokens[0].t_type, t_type, "lexeme {:?}", lexeme);
+            assert_eq!(tokens.len(), 2, "lexeme {:?}", lexeme);
+        }
+    }
+
     #[test]
     fn identifier_token() {
         let an_ident = "an_ident".to_string();
         let mut scanner = Scanner::new(an_ident);
-        let tokens = scanner.scan_tokens();
-        assert_eq!(
-            tokens[0].t_type,
-            TokenType::Identifier {
-                literal: "an_ident".to_string()
-            }
-        );
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[0].t_type, TokenType::Identifier);
+        assert_eq!(tokens[0].lexeme, "an_ident");
     }
 
     #[test]
     fn keyword_token() {
         let keyw = "class".to_string();
         let mut scanner = Scanner::new(keyw);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         assert_eq!(tokens[0].t_type, TokenType::Class);
     }
 
     #[test]
     fn literal_string_token() {
         let mut scanner = Scanner::new("\"quoted text\"".to_string());
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         assert_eq!(
-            tokens[0].t_type,
-            TokenType::String {
-                literal: r#"quoted text"#.to_string()
-            }
+            tokens[0].literal,
+            Some(Literal::String(r#"quoted text"#.into()))
         );
     }
 
@@ -280,80 +586,295 @@ mod tests {
     fn literal_number_token() {
         let number = "123".to_string();
         let mut scanner = Scanner::new(number);
-        let tokens = scanner.scan_tokens();
-        assert_eq!(tokens[0].t_type, TokenType::Number { literal: 123.0f64 });
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[0].literal, Some(Literal::Number(123.0)));
+    }
+
+    #[test]
+    fn number_literal_forms() {
+        let source = "1_000_000 2.5 1e3 2.5E-3 1e+2 0xFF 0Xff 0b1010 0o755 0x_dead_beef";
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let numbers: Vec<Option<Literal>> = tokens.into_iter().map(|t| t.literal).collect();
+        let expected = [
+            1_000_000.0,
+            2.5,
+            1000.0,
+            0.0025,
+            100.0,
+            255.0,
+            255.0,
+            10.0,
+            493.0,
+            3_735_928_559.0,
+        ];
+        let expected: Vec<Option<Literal>> =
+            expected.iter().map(|n| Some(Literal::Number(*n))).collect();
+        assert_eq!(numbers[..numbers.len() - 1], expected[..]);
+    }
+
+    #[test]
+    fn exponent_needs_digits() {
+        // `1e` and `2.x` are a number followed by a name or a property access
+        let tokens = Scanner::new("1else 2.x".to_string()).scan_tokens().unwrap();
+        assert_eq!(tokens[0].literal, Some(Literal::Number(1.0)));
+        assert_eq!(tokens[1].t_type, TokenType::Else);
+        assert_eq!(tokens[2].literal, Some(Literal::Number(2.0)));
+        assert_eq!(tokens[3].t_type, TokenType::Dot);
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        let source = "1__0; 1_; 1_.5; 0b102; 0xFG; 0x; 0o_; 0x1_0000_0000_0000_0000;";
+        let errors = Scanner::new(source.to_string()).scan_tokens().unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[line 1, col 1] Error: '_' must separate digits in a number.",
+                "[line 1, col 7] Error: '_' must separate digits in a number.",
+                "[line 1, col 11] Error: '_' must separate digits in a number.",
+                "[line 1, col 17] Error: Invalid digit '2' in binary number.",
+                "[line 1, col 24] Error: Invalid digit 'G' in hexadecimal number.",
+                "[line 1, col 30] Error: Expect digits in hexadecimal number.",
+                "[line 1, col 34] Error: '_' must separate digits in a number.",
+                "[line 1, col 39] Error: Number is too large.",
+            ]
+        );
     }
 
     #[test]
     fn expression() {
         let expr = "1+2".to_string();
         let mut scanner = Scanner::new(expr);
-        let tokens = scanner.scan_tokens();
-        assert_eq!(tokens[0].t_type, TokenType::Number { literal: 1.0f64 });
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[0].literal, Some(Literal::Number(1.0)));
         assert_eq!(tokens[1].t_type, TokenType::Plus);
-        assert_eq!(tokens[2].t_type, TokenType::Number { literal: 2.0f64 });
+        assert_eq!(tokens[2].literal, Some(Literal::Number(2.0)));
     }
 
     #[test]
     fn expression_with_whitespaces() {
         let expr = " 12 * 21 ".to_string();
         let mut scanner = Scanner::new(expr);
-        let tokens = scanner.scan_tokens();
-        assert_eq!(tokens[0].t_type, TokenType::Number { literal: 12.0f64 });
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[0].literal, Some(Literal::Number(12.0)));
         assert_eq!(tokens[1].t_type, TokenType::Star);
-        assert_eq!(tokens[2].t_type, TokenType::Number { literal: 21.0f64 });
+        assert_eq!(tokens[2].literal, Some(Literal::Number(21.0)));
     }
 
     #[test]
     fn assignement_with_comment() {
         let expr = "var a = 1.0; // A comment".to_string();
         let mut scanner = Scanner::new(expr);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         assert_eq!(tokens[0].t_type, TokenType::Var);
-        assert_eq!(
-            tokens[1].t_type,
-            TokenType::Identifier {
-                literal: "a".to_string()
-            }
-        );
+        assert_eq!(tokens[1].t_type, TokenType::Identifier);
+        assert_eq!(tokens[1].lexeme, "a");
         assert_eq!(tokens[2].t_type, TokenType::Equal);
-        assert_eq!(tokens[3].t_type, TokenType::Number { literal: 1.0f64 });
+        assert_eq!(tokens[3].literal, Some(Literal::Number(1.0)));
         assert_eq!(tokens[4].t_type, TokenType::Semicolon);
     }
 
+    #[test]
+    fn repeated_names_share_a_symbol() {
+        let mut scanner = Scanner::new("var n = n + n;".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let (a, b, c) = (&tokens[1].lexeme, &tokens[3].lexeme, &tokens[5].lexeme);
+        assert!(std::ptr::eq(a.as_str(), b.as_str()));
+        assert!(std::ptr::eq(a.as_str(), c.as_str()));
+    }
+
     #[test]
     fn multiline_statements() {
         let expr = r#"var a = 1.0;
             var b = "Hello";"#
             .to_string();
         let mut scanner = Scanner::new(expr);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         assert_eq!(tokens[0].t_type, TokenType::Var);
-        assert_eq!(
-            tokens[1].t_type,
-            TokenType::Identifier {
-                literal: "a".to_string()
-            }
-        );
+        assert_eq!(tokens[1].t_type, TokenType::Identifier);
+        assert_eq!(tokens[1].lexeme, "a");
         assert_eq!(tokens[2].t_type, TokenType::Equal);
-        assert_eq!(tokens[3].t_type, TokenType::Number { literal: 1.0f64 });
+        assert_eq!(tokens[3].literal, Some(Literal::Number(1.0)));
         assert_eq!(tokens[4].t_type, TokenType::Semicolon);
         assert_eq!(tokens[5].t_type, TokenType::Var);
+        assert_eq!(tokens[6].t_type, TokenType::Identifier);
+        assert_eq!(tokens[6].lexeme, "b");
+        assert_eq!(tokens[7].t_type, TokenType::Equal);
+        assert_eq!(tokens[8].literal, Some(Literal::String(r#"Hello"#.into())));
+        assert_eq!(tokens[9].t_type, TokenType::Semicolon);
+        assert_eq!(tokens[1].span.line, 1);
+        assert_eq!(tokens[9].span.line, 2);
+    }
+
+    #[test]
+    fn heredoc_trims_common_indent() {
+        let source =
+            "var usage = <<~END\n    lako [file]\n      runs a file\n    END\n;".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
         assert_eq!(
-            tokens[6].t_type,
-            TokenType::Identifier {
-                literal: "b".to_string()
-            }
+            tokens[3].literal,
+            Some(Literal::String("lako [file]\n  runs a file\n".into()))
         );
-        assert_eq!(tokens[7].t_type, TokenType::Equal);
+        assert_eq!(tokens[4].t_type, TokenType::Semicolon);
+    }
+
+    #[test]
+    fn heredoc_keeps_blank_lines_and_counts_lines() {
+        let source = "<<~SQL\n  select *\n\n  from t\n  SQL\nx".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
         assert_eq!(
-            tokens[8].t_type,
-            TokenType::String {
-                literal: r#"Hello"#.to_string()
-            }
+            tokens[0].literal,
+            Some(Literal::String("select *\n\nfrom t\n".into()))
         );
-        assert_eq!(tokens[9].t_type, TokenType::Semicolon);
-        assert_eq!(tokens[1].line, 1);
-        assert_eq!(tokens[9].line, 2);
+        assert_eq!(tokens[0].span.line, 1);
+        assert_eq!(tokens[1].span.line, 6);
+    }
+
+    #[test]
+    fn unterminated_heredoc() {
+        let mut scanner = Scanner::new("<<~END\n  text\n".to_string());
+        let errors = scanner.scan_tokens().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1, col 1] Error: Unterminated heredoc."
+        );
+    }
+
+    #[test]
+    fn collects_every_error() {
+        let mut scanner = Scanner::new("var a = @;\nvar b = #;\n\"open".to_string());
+        let errors = scanner.scan_tokens().unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[line 1, col 9] Error: Unexpected character.",
+                "[line 2, col 9] Error: Unexpected character.",
+                "[line 3, col 1] Error: Unterminated string.",
+            ]
+        );
+    }
+
+    #[test]
+    fn bitwise_and_shift_tokens() {
+        let mut scanner = Scanner::new("a << 2 >> b & c | ~d ^ e".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[1].t_type, TokenType::LessLess);
+        assert_eq!(tokens[3].t_type, TokenType::GreaterGreater);
+        assert_eq!(tokens[5].t_type, TokenType::Ampersand);
+        assert_eq!(tokens[7].t_type, TokenType::Pipe);
+        assert_eq!(tokens[8].t_type, TokenType::Tilde);
+        assert_eq!(tokens[10].t_type, TokenType::Caret);
+    }
+
+    #[test]
+    fn shift_of_complement_is_not_a_heredoc() {
+        let mut scanner = Scanner::new("a <<~b;\n".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[1].t_type, TokenType::LessLess);
+        assert_eq!(tokens[2].t_type, TokenType::Tilde);
+        assert_eq!(tokens[4].t_type, TokenType::Semicolon);
+    }
+
+    #[test]
+    fn token_spans() {
+        let mut scanner = Scanner::new("var  abc\n  >= \"a\nb\" 1;".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[0].span, Span::new(0, 3, 1, 1));
+        assert_eq!(tokens[1].span, Span::new(5, 8, 1, 6));
+        assert_eq!(tokens[2].span, Span::new(11, 13, 2, 3));
+        // multi-line tokens are located by their first character
+        assert_eq!(tokens[3].span, Span::new(14, 19, 2, 6));
+        assert_eq!(tokens[4].span, Span::new(20, 21, 3, 4));
+        assert_eq!(tokens[6].span, Span::new(22, 22, 3, 6));
+    }
+
+    #[test]
+    fn shebang_line_is_skipped() {
+        let source = "#!/usr/bin/env lako\nprint 1;".to_string();
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        assert_eq!(tokens[0].t_type, TokenType::Print);
+        assert_eq!(tokens[0].span, Span::new(20, 25, 2, 1));
+
+        // only on the first line
+        let errors = Scanner::new("print 1;\n#!x".to_string())
+            .scan_tokens()
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn multi_byte_characters() {
+        let source = "var école = \"ünï\"; // ✓\n  ¿ x".to_string();
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 2, col 3] Error: Unexpected character."
+        );
+
+        let mut scanner = Scanner::new("var école = \"ünï\"; // ✓\n x".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[1].lexeme, "école");
+        assert_eq!(tokens[1].span, Span::new(4, 10, 1, 5));
+        assert_eq!(tokens[3].literal, Some(Literal::String("ünï".into())));
+        assert_eq!(tokens[4].span.col, 18);
+        assert_eq!(tokens[5].span, Span::new(30, 31, 2, 2));
+    }
+
+    #[test]
+    fn trivia_tokens() {
+        let source = "#!/usr/bin/env lako\nprint 1; // one\n\n  x;";
+        let tokens = Scanner::with_trivia(source.to_string())
+            .scan_tokens()
+            .unwrap();
+        // the lexemes add up to the source
+        let text: String = tokens.iter().map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(text, source);
+
+        let trivia: Vec<(TokenType, &str)> = tokens
+            .iter()
+            .filter(|t| t.t_type.is_trivia())
+            .map(|t| (t.t_type, t.lexeme.as_str()))
+            .collect();
+        assert_eq!(
+            trivia,
+            vec![
+                (TokenType::Comment, "#!/usr/bin/env lako"),
+                (TokenType::Whitespace, "\n"),
+                (TokenType::Whitespace, " "),
+                (TokenType::Whitespace, " "),
+                (TokenType::Comment, "// one"),
+                (TokenType::Whitespace, "\n\n  "),
+            ]
+        );
+        let x = tokens.iter().find(|t| t.lexeme == "x").unwrap();
+        assert_eq!(x.span, Span::new(39, 40, 4, 3));
+
+        // without trivia nothing changes
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        assert!(tokens.iter().all(|t| !t.t_type.is_trivia()));
+    }
+
+    proptest::proptest! {
+        // Any text scans to tokens or errors, multi-byte characters included
+        #[test]
+        fn scans_anything(source in "\\PC*") {
+            let _ = Scanner::new(source.clone()).scan_tokens();
+            if let Ok(tokens) = Scanner::with_trivia(source.clone()).scan_tokens() {
+                let text: String = tokens.iter().map(|t| t.lexeme.as_str()).collect();
+                proptest::prop_assert_eq!(text, source);
+            }
+        }
+
+        #[test]
+        fn scans_any_bytes(bytes in proptest::collection::vec(proptest::prelude::any::<u8>(), 0..256)) {
+            let source = String::from_utf8_lossy(&bytes).into_owned();
+            let _ = Scanner::new(source).scan_tokens();
+        }
     }
 }
@@ -0,0 +1,38 @@
+// A thread with room for the deepest trees the parser accepts.
+//
+// The parser and every pass after it walk the tree recursively. Unoptimized builds
+// take about 64KB of stack per level of nesting in the parser and up to 5KB per level
+// of the tree in the later passes, so MAX_DEPTH levels of parentheses or a chain
+// MAX_HEIGHT operators long need far more than the 2MB a spawned thread gets, or the
+// 8MB of the main one. The lako commands, the language server and lib::parse run on
+// a thread of STACK_SIZE. Only the pages that get used are ever allocated.
+
+use std::panic;
+use std::thread;
+
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+// Runs `f` on a thread with a stack of STACK_SIZE and waits for its result. A panic
+// in `f` goes on in the calling thread.
+pub fn run<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .name("lako".to_string())
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("failed to spawn a thread for the parser")
+            .join()
+            .unwrap_or_else(|payload| panic::resume_unwind(payload))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "inner")]
+    fn panics_go_on_in_the_caller() {
+        run(|| panic!("inner"));
+    }
+}
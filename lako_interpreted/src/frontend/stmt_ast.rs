@@ -1,42 +1,153 @@
 use crate::frontend::error::Error;
 use crate::frontend::expr_ast::Expr;
-use crate::frontend::token::Token;
+use crate::frontend::token::{Literal, Span, Token};
 
-#[derive(Clone)]
+// A parameter and its optional type annotation, as in `a: Number`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Token,
+    pub ty: Option<Token>,
+}
+
+// An arm of a match, `patterns if guard => body`. A pattern is a literal, or a
+// name that matches anything and holds the subject in the guard and the body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arm {
+    pub patterns: Vec<Expr>,
+    pub guard: Option<Expr>,
+    pub body: Stmt,
+}
+
+// The path of an import, without the quotes
+pub fn module_path(path: &Token) -> &str {
+    match &path.literal {
+        Some(Literal::String(path)) => path,
+        _ => &path.lexeme,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Stmt {
+    // assert cond, message: the message is optional
+    Assert {
+        keywd: Token,
+        cond: Expr,
+        message: Option<Expr>,
+    },
+    // close is the closing brace, where comments after the last statement go
     Block {
         stmts: Vec<Stmt>,
+        close: Span,
+    },
+    // break, or break label to leave the enclosing loop with that label
+    Break {
+        keywd: Token,
+        label: Option<Token>,
     },
+    // fields are the Vars declared in the body, set on every new instance before
+    // init runs
     Class {
         name: Token,
         sclass: Option<Expr>,
+        fields: Vec<Stmt>,
         methods: Vec<Stmt>,
+        close: Span,
+    },
+    Continue {
+        keywd: Token,
+        label: Option<Token>,
+    },
+    // export decl: a top level class, function or variable that modules importing
+    // this one can see
+    Export {
+        keywd: Token,
+        decl: Box<Stmt>,
     },
     Expression {
         expr: Expr,
     },
+    For {
+        init: Box<Option<Stmt>>,
+        cond: Option<Expr>,
+        incr: Option<Expr>,
+        body: Box<Stmt>,
+    },
+    // for (name in iterable) body
+    ForIn {
+        name: Token,
+        iterable: Expr,
+        body: Box<Stmt>,
+    },
+    // ret is the return type annotation. Only methods can be static_, called on the
+    // class itself rather than an instance as in `static square(n) { ... }`, or have
+    // no params: a getter like `area { ... }` runs when the property is read.
     Function {
         name: Token,
-        params: Vec<Token>,
+        params: Option<Vec<Param>>,
+        ret: Option<Token>,
         body: Vec<Stmt>,
+        close: Span,
+        static_: bool,
     },
+    // if (var binding = cond): with a binding the then branch runs when cond isn't
+    // nil, and only it sees the variable. The same goes for the body of a while.
     If {
+        binding: Option<Token>,
         cond: Expr,
         then_: Box<Stmt>,
         else_: Box<Option<Stmt>>,
     },
+    // import "path" as alias; or from "path" import names, ...; where exactly one of
+    // alias and names is there. Imports only come at the top level.
+    Import {
+        keywd: Token,
+        path: Token,
+        alias: Option<Token>,
+        names: Vec<Token>,
+    },
+    // label: body, where the body is a while or for loop
+    Labeled {
+        label: Token,
+        body: Box<Stmt>,
+    },
+    // match (subject) { patterns if guard => body ... else => body }: the first arm
+    // with a pattern matching the subject and a true guard runs, there is no
+    // fallthrough
+    Match {
+        keywd: Token,
+        subject: Expr,
+        arms: Vec<Arm>,
+        else_: Box<Option<Stmt>>,
+        close: Span,
+    },
+    // print expr, rest...
     Print {
         expr: Expr,
+        rest: Vec<Expr>,
     },
     Return {
         keywd: Token,
         val: Option<Expr>,
     },
+    Throw {
+        keywd: Token,
+        val: Expr,
+    },
+    // try body catch (name) body finally body: the bodies are Blocks, and at least
+    // one of catch and finally is there
+    Try {
+        keywd: Token,
+        body: Box<Stmt>,
+        catch: Box<Option<(Token, Stmt)>>,
+        finally: Box<Option<Stmt>>,
+    },
     Var {
         name: Token,
+        ty: Option<Token>,
         init: Option<Expr>,
     },
     While {
+        binding: Option<Token>,
         cond: Expr,
         body: Box<Stmt>,
     },
@@ -46,49 +157,209 @@ pub enum Stmt {
 // Implement Visitor Pattern
 // A visitor encapsulates an algorithm that operates over a heterogeneous collection of objects.
 pub trait Visitor<T> {
-    fn visit_block_stmt(&mut self, stmts: &[Stmt]) -> Result<T, Error>;
+    fn visit_assert_stmt(
+        &mut self,
+        keywd: &Token,
+        cond: &Expr,
+        message: &Option<Expr>,
+    ) -> Result<T, Error>;
+    fn visit_block_stmt(&mut self, stmts: &[Stmt], close: Span) -> Result<T, Error>;
+    fn visit_break_stmt(&mut self, keywd: &Token, label: &Option<Token>) -> Result<T, Error>;
     fn visit_class_stmt(
         &mut self,
         name: &Token,
         sclass: &Option<Expr>,
+        fields: &[Stmt],
         methods: &[Stmt],
+        close: Span,
     ) -> Result<T, Error>;
+    fn visit_continue_stmt(&mut self, keywd: &Token, label: &Option<Token>) -> Result<T, Error>;
+    fn visit_export_stmt(&mut self, keywd: &Token, decl: &Stmt) -> Result<T, Error>;
     fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<T, Error>;
+    fn visit_for_stmt(
+        &mut self,
+        init: &Option<Stmt>,
+        cond: &Option<Expr>,
+        incr: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<T, Error>;
+    fn visit_for_in_stmt(&mut self, name: &Token, iterable: &Expr, body: &Stmt)
+        -> Result<T, Error>;
     fn visit_function_stmt(
         &mut self,
         name: &Token,
-        params: &[Token],
+        params: &Option<Vec<Param>>,
+        ret: &Option<Token>,
         body: &[Stmt],
+        close: Span,
+        static_: bool,
     ) -> Result<T, Error>;
     fn visit_if_stmt(
         &mut self,
+        binding: &Option<Token>,
         cond: &Expr,
         else_: &Option<Stmt>,
         then_: &Stmt,
     ) -> Result<T, Error>;
-    fn visit_print_stmt(&mut self, expr: &Expr) -> Result<T, Error>;
+    fn visit_import_stmt(
+        &mut self,
+        keywd: &Token,
+        path: &Token,
+        alias: &Option<Token>,
+        names: &[Token],
+    ) -> Result<T, Error>;
+    fn visit_labeled_stmt(&mut self, label: &Token, body: &Stmt) -> Result<T, Error>;
+    fn visit_match_stmt(
+        &mut self,
+        keywd: &Token,
+        subject: &Expr,
+        arms: &[Arm],
+        else_: &Option<Stmt>,
+        close: Span,
+    ) -> Result<T, Error>;
+    fn visit_print_stmt(&mut self, expr: &Expr, rest: &[Expr]) -> Result<T, Error>;
     fn visit_return_stmt(&mut self, keywd: &Token, val: &Option<Expr>) -> Result<T, Error>;
-    fn visit_var_stmt(&mut self, name: &Token, init: &Option<Expr>) -> Result<T, Error>;
-    fn visit_while_stmt(&mut self, cond: &Expr, body: &Stmt) -> Result<T, Error>;
+    fn visit_throw_stmt(&mut self, keywd: &Token, val: &Expr) -> Result<T, Error>;
+    fn visit_try_stmt(
+        &mut self,
+        keywd: &Token,
+        body: &Stmt,
+        catch: &Option<(Token, Stmt)>,
+        finally: &Option<Stmt>,
+    ) -> Result<T, Error>;
+    fn visit_var_stmt(
+        &mut self,
+        name: &Token,
+        ty: &Option<Token>,
+        init: &Option<Expr>,
+    ) -> Result<T, Error>;
+    fn visit_while_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        body: &Stmt,
+    ) -> Result<T, Error>;
 }
 
 impl Stmt {
     pub fn accept<T>(&self, v: &mut dyn Visitor<T>) -> Result<T, Error> {
         match self {
-            Stmt::Block { stmts } => v.visit_block_stmt(stmts),
+            Stmt::Assert {
+                keywd,
+                cond,
+                message,
+            } => v.visit_assert_stmt(keywd, cond, message),
+            Stmt::Block { stmts, close } => v.visit_block_stmt(stmts, *close),
+            Stmt::Break { keywd, label } => v.visit_break_stmt(keywd, label),
             Stmt::Class {
                 name,
                 sclass,
+                fields,
                 methods,
-            } => v.visit_class_stmt(name, sclass, methods),
+                close,
+            } => v.visit_class_stmt(name, sclass, fields, methods, *close),
+            Stmt::Continue { keywd, label } => v.visit_continue_stmt(keywd, label),
+            Stmt::Export { keywd, decl } => v.visit_export_stmt(keywd, decl),
             Stmt::Expression { expr } => v.visit_expression_stmt(expr),
-            Stmt::Function { name, params, body } => v.visit_function_stmt(name, params, body),
-            Stmt::If { cond, else_, then_ } => v.visit_if_stmt(cond, else_, then_),
-            Stmt::Print { expr } => v.visit_print_stmt(expr),
+            Stmt::For {
+                init,
+                cond,
+                incr,
+                body,
+            } => v.visit_for_stmt(init, cond, incr, body),
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => v.visit_for_in_stmt(name, iterable, body),
+            Stmt::Function {
+                name,
+                params,
+                ret,
+                body,
+                close,
+                static_,
+            } => v.visit_function_stmt(name, params, ret, body, *close, *static_),
+            Stmt::If {
+                binding,
+                cond,
+                else_,
+                then_,
+            } => v.visit_if_stmt(binding, cond, else_, then_),
+            Stmt::Import {
+                keywd,
+                path,
+                alias,
+                names,
+            } => v.visit_import_stmt(keywd, path, alias, names),
+            Stmt::Labeled { label, body } => v.visit_labeled_stmt(label, body),
+            Stmt::Match {
+                keywd,
+                subject,
+                arms,
+                else_,
+                close,
+            } => v.visit_match_stmt(keywd, subject, arms, else_, *close),
+            Stmt::Print { expr, rest } => v.visit_print_stmt(expr, rest),
             Stmt::Return { keywd, val } => v.visit_return_stmt(keywd, val),
-            Stmt::Var { name, init } => v.visit_var_stmt(name, init),
-            Stmt::While { cond, body } => v.visit_while_stmt(cond, body),
+            Stmt::Throw { keywd, val } => v.visit_throw_stmt(keywd, val),
+            Stmt::Try {
+                keywd,
+                body,
+                catch,
+                finally,
+            } => v.visit_try_stmt(keywd, body, catch, finally),
+            Stmt::Var { name, ty, init } => v.visit_var_stmt(name, ty, init),
+            Stmt::While {
+                binding,
+                cond,
+                body,
+            } => v.visit_while_stmt(binding, cond, body),
             //Stmt::Nil => unimplemented!(),
         }
     }
+
+    // The declaration an export marks, or the statement itself
+    pub fn declaration(&self) -> &Stmt {
+        match self {
+            Stmt::Export { decl, .. } => decl,
+            stmt => stmt,
+        }
+    }
+
+    // Where a statement starts, as far as the tree knows: keywords such as print
+    // or while are not kept, so it is the first token after them.
+    pub fn start(&self) -> Span {
+        match self {
+            Stmt::Block { stmts, close } => stmts.first().map_or(*close, Stmt::start),
+            Stmt::Class { name, .. }
+            | Stmt::ForIn { name, .. }
+            | Stmt::Function { name, .. }
+            | Stmt::Var { name, .. }
+            | Stmt::Labeled { label: name, .. } => name.span,
+            Stmt::Expression { expr } | Stmt::Print { expr, .. } => expr.start(),
+            Stmt::For {
+                init,
+                cond,
+                incr,
+                body,
+            } => match (&**init, cond, incr) {
+                (Some(init), _, _) => init.start(),
+                (None, Some(expr), _) | (None, None, Some(expr)) => expr.start(),
+                (None, None, None) => body.start(),
+            },
+            Stmt::If { binding, cond, .. } | Stmt::While { binding, cond, .. } => {
+                binding.as_ref().map_or(cond.start(), |name| name.span)
+            }
+            Stmt::Assert { keywd, .. }
+            | Stmt::Break { keywd, .. }
+            | Stmt::Continue { keywd, .. }
+            | Stmt::Export { keywd, .. }
+            | Stmt::Import { keywd, .. }
+            | Stmt::Match { keywd, .. }
+            | Stmt::Return { keywd, .. }
+            | Stmt::Throw { keywd, .. }
+            | Stmt::Try { keywd, .. } => keywd.span,
+        }
+    }
 }
@@ -1,16 +1,19 @@
+use crate::frontend::interner::Symbol;
 use std::collections::HashMap;
 use std::fmt;
 // Lazily evaluated constant HashMap
 // The HashMap will be evaluated once and stored behind a global static reference
 use lazy_static::lazy_static;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TokenType {
     // Single-character tokens
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -18,92 +21,220 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Question,
+    Colon,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
 
     // One or two character tokens
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    EqualGreater,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    MinusEqual,
+    MinusMinus,
+    PlusEqual,
+    PlusPlus,
+    SlashEqual,
+    StarEqual,
+    PercentEqual,
 
-    // Literals - Encoded in the enum
-    Identifier { literal: String },
-    String { literal: String },
-    Number { literal: f64 },
+    // Literals - the value is in Token::literal, an identifier's name is its lexeme
+    Identifier,
+    String,
+    Number,
 
     // Keywords
     And,
+    As,
+    Assert,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
+    Export,
     False,
+    Finally,
     Fn,
     For,
+    From,
     If,
+    Import,
+    In,
+    Is,
+    Match,
     Nil,
     Or,
     Print,
     Return,
+    Static,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
 
+    // Trivia, only produced by Scanner::with_trivia
+    Whitespace,
+    Comment,
+
     Eof,
 }
 
+impl TokenType {
+    // Whitespace and comments, which the parser skips
+    pub fn is_trivia(self) -> bool {
+        matches!(self, TokenType::Whitespace | TokenType::Comment)
+    }
+}
+
+// Operator and punctuation lexemes recognized by the Scanner.
+// Kept in sync with `Scanner::scan_token` by the scanner tests.
+pub const PUNCTUATORS: &[(&str, TokenType)] = &[
+    ("(", TokenType::LeftParen),
+    (")", TokenType::RightParen),
+    ("{", TokenType::LeftBrace),
+    ("}", TokenType::RightBrace),
+    ("[", TokenType::LeftBracket),
+    ("]", TokenType::RightBracket),
+    (",", TokenType::Comma),
+    (".", TokenType::Dot),
+    ("-", TokenType::Minus),
+    ("+", TokenType::Plus),
+    (";", TokenType::Semicolon),
+    ("/", TokenType::Slash),
+    ("*", TokenType::Star),
+    ("%", TokenType::Percent),
+    ("?", TokenType::Question),
+    (":", TokenType::Colon),
+    ("&", TokenType::Ampersand),
+    ("|", TokenType::Pipe),
+    ("^", TokenType::Caret),
+    ("~", TokenType::Tilde),
+    ("!", TokenType::Bang),
+    ("!=", TokenType::BangEqual),
+    ("=", TokenType::Equal),
+    ("==", TokenType::EqualEqual),
+    ("=>", TokenType::EqualGreater),
+    (">", TokenType::Greater),
+    (">=", TokenType::GreaterEqual),
+    ("<", TokenType::Less),
+    ("<=", TokenType::LessEqual),
+    ("<<", TokenType::LessLess),
+    (">>", TokenType::GreaterGreater),
+    ("-=", TokenType::MinusEqual),
+    ("--", TokenType::MinusMinus),
+    ("+=", TokenType::PlusEqual),
+    ("++", TokenType::PlusPlus),
+    ("/=", TokenType::SlashEqual),
+    ("*=", TokenType::StarEqual),
+    ("%=", TokenType::PercentEqual),
+];
+
 lazy_static! {
     pub static ref KEYWORDS: HashMap<&'static str, TokenType> = {
         let mut map = HashMap::new();
         map.insert("and", TokenType::And);
+        map.insert("as", TokenType::As);
+        map.insert("assert", TokenType::Assert);
+        map.insert("break", TokenType::Break);
+        map.insert("catch", TokenType::Catch);
         map.insert("class", TokenType::Class);
+        map.insert("continue", TokenType::Continue);
         map.insert("else", TokenType::Else);
+        map.insert("export", TokenType::Export);
         map.insert("false", TokenType::False);
+        map.insert("finally", TokenType::Finally);
         map.insert("for", TokenType::For);
         map.insert("fn", TokenType::Fn);
+        map.insert("from", TokenType::From);
         map.insert("if", TokenType::If);
+        map.insert("import", TokenType::Import);
+        map.insert("in", TokenType::In);
+        map.insert("is", TokenType::Is);
+        map.insert("match", TokenType::Match);
         map.insert("nil", TokenType::Nil);
         map.insert("or", TokenType::Or);
         map.insert("print", TokenType::Print);
         map.insert("return", TokenType::Return);
+        map.insert("static", TokenType::Static);
         map.insert("super", TokenType::Super);
         map.insert("this", TokenType::This);
+        map.insert("throw", TokenType::Throw);
         map.insert("true", TokenType::True);
+        map.insert("try", TokenType::Try);
         map.insert("var", TokenType::Var);
         map.insert("while", TokenType::While);
         map
     };
 }
 
+// Where a token sits in the source: start..end are byte offsets, line and col
+// (1-based, col counted in characters) locate its first character.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: i32,
+    pub col: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: i32, col: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            col,
+        }
+    }
+}
+
+// The value of a String or Number token
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(Symbol),
+    Number(f64),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub t_type: TokenType,
-    pub lexeme: String,
-    pub line: i32,
+    pub lexeme: Symbol,
+    pub literal: Option<Literal>,
+    pub span: Span,
 }
 
 impl Token {
-    pub fn new(t_type: TokenType, lexeme: &str, line: i32) -> Token {
+    pub fn new(t_type: TokenType, lexeme: &str, span: Span) -> Token {
         Token {
             t_type,
-            lexeme: lexeme.to_string(),
-            line,
+            lexeme: Symbol::from(lexeme),
+            literal: None,
+            span,
         }
     }
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self.t_type {
-            TokenType::String { literal } => write!(f, "String {:?} {:?}", self.lexeme, literal),
-            TokenType::Number { literal } => write!(f, "Number {:?} {:?}", self.lexeme, literal),
-            TokenType::Identifier { literal } => {
-                write!(f, "Identifier {:?} {:?}", self.lexeme, literal)
-            }
-            _ => write!(f, "{:?} {:?}", self.t_type, self.lexeme),
+        match &self.literal {
+            Some(Literal::String(s)) => write!(f, "{:?} {:?} {:?}", self.t_type, self.lexeme, s),
+            Some(Literal::Number(n)) => write!(f, "{:?} {:?} {:?}", self.t_type, self.lexeme, n),
+            None => write!(f, "{:?} {:?}", self.t_type, self.lexeme),
         }
     }
 }
@@ -0,0 +1,1099 @@
+// Static type checker, run by `lako check` and `lako run --check` before the program
+// runs. Typing is gradual: a name has the type of its annotation and unannotated
+// names are Any, which fits everywhere, so unannotated code always passes. It reports
+// - initializers, assigned values, arguments and returned values that don't fit the
+//   annotated type
+// - operands that can't be numbers, or numbers or strings for '+'
+// - calls of values that are neither functions nor classes
+// - annotations naming a type that doesn't exist
+//
+// Classes and functions are known to the whole statement list they are declared in,
+// so they can be used before their declaration. An instance of a class fits where its
+// superclasses are wanted, and nil fits any class, like an object not made yet. A
+// class that ends up among its own superclasses is reported too.
+
+use crate::frontend::diagnostic::{self, Diagnostic};
+use crate::frontend::error::Error;
+use crate::frontend::expr_ast::{self, Expr, LiteralValue};
+use crate::frontend::stmt_ast::{self, Arm, Param, Stmt};
+use crate::frontend::token::{Span, Token, TokenType};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Any,
+    Nil,
+    Bool,
+    Number,
+    String,
+    List,
+    Map,
+    Fn,
+    // the class itself, which is called to make an Instance
+    Class(String),
+    Instance(String),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "Any"),
+            Type::Nil => write!(f, "Nil"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Number => write!(f, "Number"),
+            Type::String => write!(f, "String"),
+            Type::List => write!(f, "List"),
+            Type::Map => write!(f, "Map"),
+            Type::Fn => write!(f, "Fn"),
+            Type::Class(name) => write!(f, "class {}", name),
+            Type::Instance(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// The type of each variable and parameter, by the span of its name
+pub type Types = Vec<(Span, Type)>;
+
+// What a declared function takes and gives back
+#[derive(Debug, Clone)]
+struct Signature {
+    params: Vec<Type>,
+    ret: Type,
+}
+
+#[derive(Debug, Clone)]
+struct Symbol {
+    ty: Type,
+    // only for names declared with fn
+    sig: Option<Signature>,
+    // the superclass name, only for classes that have one
+    sclass: Option<String>,
+}
+
+pub struct TypeChecker {
+    // globals first, innermost last
+    scopes: Vec<HashMap<String, Symbol>>,
+    // return types of the functions being checked, innermost last
+    returns: Vec<Type>,
+    errors: Vec<Diagnostic>,
+    types: Types,
+}
+
+impl Default for TypeChecker {
+    fn default() -> TypeChecker {
+        TypeChecker {
+            scopes: vec![HashMap::new()],
+            returns: Vec::new(),
+            errors: Vec::new(),
+            types: Vec::new(),
+        }
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> TypeChecker {
+        TypeChecker::default()
+    }
+
+    // The type errors in the program, in source order
+    pub fn check(self, stmts: &[Stmt]) -> Result<Vec<Diagnostic>, Error> {
+        self.check_types(stmts).map(|(errors, _)| errors)
+    }
+
+    // The type errors, and the type of every variable and parameter by the span of its
+    // name: the annotated type, or the type of the initializer for an unannotated
+    // variable. Editors show them on hover.
+    pub fn check_types(mut self, stmts: &[Stmt]) -> Result<(Vec<Diagnostic>, Types), Error> {
+        self.stmts(stmts)?;
+        self.errors.sort_by_key(|e| e.span.start);
+        self.types.sort_by_key(|(span, _)| span.start);
+        Ok((self.errors, self.types))
+    }
+
+    // Declares the classes and then the functions of the list before checking it
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<(), Error> {
+        for stmt in stmts {
+            if let Stmt::Class { name, sclass, .. } = stmt.declaration() {
+                self.declare_class(name, sclass);
+            }
+        }
+        for stmt in stmts {
+            if let Stmt::Function {
+                name,
+                params: Some(params),
+                ret,
+                ..
+            } = stmt.declaration()
+            {
+                self.declare_function(name, params, ret, false);
+            }
+        }
+        stmts.iter().try_for_each(|s| s.accept(self))
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token, ty: Type, sig: Option<Signature>) {
+        self.declare_symbol(
+            name,
+            Symbol {
+                ty,
+                sig,
+                sclass: None,
+            },
+        );
+    }
+
+    fn declare_symbol(&mut self, name: &Token, symbol: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.to_string(), symbol);
+        }
+    }
+
+    // The superclass lives with the class in its scope, so a class declared in a
+    // function doesn't change what a same-named one outside it inherits from
+    fn declare_class(&mut self, name: &Token, sclass: &Option<Expr>) {
+        let sclass = match sclass {
+            Some(Expr::Variable { name: sclass }) => Some(sclass.lexeme.to_string()),
+            _ => None,
+        };
+        let symbol = Symbol {
+            ty: Type::Class(name.lexeme.to_string()),
+            sig: None,
+            sclass,
+        };
+        self.declare_symbol(name, symbol);
+    }
+
+    // The superclass of the class visible by that name
+    fn superclass(&self, class: &str) -> Option<&str> {
+        let symbol = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(class))?;
+        match symbol.ty {
+            Type::Class(_) => symbol.sclass.as_deref(),
+            _ => None,
+        }
+    }
+
+    // The superclasses of a class, nearest first. A cycle is only followed once
+    // around, it has been reported by then.
+    fn ancestors<'a>(&'a self, class: &'a str) -> Vec<&'a str> {
+        let mut ancestors: Vec<&str> = Vec::new();
+        let mut class = class;
+        while let Some(sclass) = self.superclass(class) {
+            if ancestors.contains(&sclass) {
+                break;
+            }
+            ancestors.push(sclass);
+            class = sclass;
+        }
+        ancestors
+    }
+
+    // Checks the initializer of a variable or field against its annotation and
+    // returns the type it is declared with
+    fn variable(
+        &mut self,
+        name: &Token,
+        ty: &Option<Token>,
+        init: &Option<Expr>,
+    ) -> Result<Type, Error> {
+        let declared = self.annotated(ty, true);
+        let mut inferred = Type::Nil;
+        if let Some(init) = init {
+            let init_ty = init.accept(self)?;
+            let what = format!("Initializer of '{}'", name.lexeme);
+            self.expect(&init_ty, &declared, &what, init.start());
+            inferred = init_ty;
+        }
+        let shown = if ty.is_some() {
+            declared.clone()
+        } else {
+            inferred
+        };
+        self.types.push((name.span, shown));
+        Ok(declared)
+    }
+
+    // Reports a class that inherits from itself, directly or through other classes
+    fn check_inheritance(&mut self, name: &Token, sclass: &Option<Expr>) {
+        let sclass = match sclass {
+            Some(Expr::Variable { name: sclass }) => sclass,
+            _ => return,
+        };
+        let class = name.lexeme.as_str();
+        let message = if sclass.lexeme.as_str() == class {
+            "A class can't inherit from itself.".to_string()
+        } else if self.ancestors(sclass.lexeme.as_str()).contains(&class) {
+            format!(
+                "Class '{}' inherits from itself through '{}'.",
+                class, sclass.lexeme
+            )
+        } else {
+            return;
+        };
+        self.error(diagnostic::INHERITANCE_CYCLE, &message, sclass.span);
+    }
+
+    // Unknown types in the signature are only reported once, when checking
+    fn declare_function(
+        &mut self,
+        name: &Token,
+        params: &[Param],
+        ret: &Option<Token>,
+        report: bool,
+    ) {
+        let sig = Signature {
+            params: params
+                .iter()
+                .map(|p| self.annotated(&p.ty, report))
+                .collect(),
+            ret: self.annotated(ret, report),
+        };
+        self.declare(name, Type::Fn, Some(sig));
+    }
+
+    fn lookup(&self, name: &Token) -> Option<&Symbol> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name.lexeme.as_str()))
+    }
+
+    // The type an annotation names, Any without one
+    fn annotated(&mut self, ty: &Option<Token>, report: bool) -> Type {
+        let ty = match ty {
+            Some(ty) => ty,
+            None => return Type::Any,
+        };
+        match ty.lexeme.as_str() {
+            "Any" => Type::Any,
+            "Nil" => Type::Nil,
+            "Bool" => Type::Bool,
+            "Number" => Type::Number,
+            "String" => Type::String,
+            "List" => Type::List,
+            "Map" => Type::Map,
+            "Fn" => Type::Fn,
+            name => match self.lookup(ty).map(|s| &s.ty) {
+                Some(Type::Class(_)) => Type::Instance(name.to_string()),
+                _ => {
+                    if report {
+                        let message = format!("Unknown type '{}'.", name);
+                        self.error(diagnostic::UNKNOWN_TYPE, &message, ty.span);
+                    }
+                    Type::Any
+                }
+            },
+        }
+    }
+
+    fn error(&mut self, code: &'static str, message: &str, span: Span) {
+        self.errors.push(Diagnostic::error(code, message, span));
+    }
+
+    // Whether a value of type `actual` may go where `expected` is wanted
+    fn fits(&self, actual: &Type, expected: &Type) -> bool {
+        match (actual, expected) {
+            (Type::Nil, Type::Instance(_)) => true,
+            (Type::Instance(class), Type::Instance(wanted)) => {
+                class == wanted || self.ancestors(class).contains(&wanted.as_str())
+            }
+            _ => actual == expected || *actual == Type::Any || *expected == Type::Any,
+        }
+    }
+
+    // Reports `what` if a value of type `actual` doesn't fit `expected`
+    fn expect(&mut self, actual: &Type, expected: &Type, what: &str, span: Span) {
+        if !self.fits(actual, expected) {
+            let message = format!("{} must be {}, got {}.", what, expected, actual);
+            self.error(diagnostic::TYPE_MISMATCH, &message, span);
+        }
+    }
+
+    fn number_operand(&mut self, op: &Token, ty: &Type) {
+        if !self.fits(ty, &Type::Number) {
+            let message = format!("Operand of '{}' must be a number, got {}.", op.lexeme, ty);
+            self.error(diagnostic::BAD_OPERAND, &message, op.span);
+        }
+    }
+
+    // The type of `lhs op rhs`
+    fn binary(&mut self, op: &Token, lhs: &Type, rhs: &Type) -> Type {
+        match op.t_type {
+            TokenType::Plus => match (lhs, rhs) {
+                (Type::Number, Type::Number) => Type::Number,
+                (Type::String, Type::String) => Type::String,
+                (Type::Any, _) | (_, Type::Any) => Type::Any,
+                _ => {
+                    let message = format!(
+                        "Operands of '+' must be two numbers or two strings, got {} and {}.",
+                        lhs, rhs
+                    );
+                    self.error(diagnostic::BAD_OPERAND, &message, op.span);
+                    Type::Any
+                }
+            },
+            TokenType::Minus
+            | TokenType::Star
+            | TokenType::Slash
+            | TokenType::Percent
+            | TokenType::Ampersand
+            | TokenType::Pipe
+            | TokenType::Caret
+            | TokenType::LessLess
+            | TokenType::GreaterGreater => {
+                self.number_operand(op, lhs);
+                self.number_operand(op, rhs);
+                Type::Number
+            }
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => {
+                self.number_operand(op, lhs);
+                self.number_operand(op, rhs);
+                Type::Bool
+            }
+            _ => Type::Bool,
+        }
+    }
+
+    // The body of an if or while, where the variable its condition declares, if
+    // any, has the type of the condition
+    fn bound(&mut self, binding: &Option<Token>, ty: Type, body: &Stmt) -> Result<(), Error> {
+        self.begin_scope();
+        if let Some(name) = binding {
+            self.declare(name, ty, None);
+        }
+        let r = body.accept(self);
+        self.end_scope();
+        r
+    }
+
+    // Checks a function body against its parameter and return annotations
+    fn function(
+        &mut self,
+        params: &[Param],
+        ret: &Option<Token>,
+        body: &[Stmt],
+    ) -> Result<(), Error> {
+        let ret = self.annotated(ret, true);
+        self.begin_scope();
+        for param in params {
+            let ty = self.annotated(&param.ty, true);
+            self.types.push((param.name.span, ty.clone()));
+            self.declare(&param.name, ty, None);
+        }
+        self.returns.push(ret);
+        let r = self.stmts(body);
+        self.returns.pop();
+        self.end_scope();
+        r
+    }
+}
+
+impl expr_ast::Visitor<Type> for TypeChecker {
+    fn visit_assign_expr(&mut self, name: &Token, op: &Token, val: &Expr) -> Result<Type, Error> {
+        let declared = self.lookup(name).map_or(Type::Any, |s| s.ty.clone());
+        let mut ty = val.accept(self)?;
+        if let Some(op) = expr_ast::binary_operator(op) {
+            ty = self.binary(&op, &declared, &ty);
+        }
+        let what = format!("Value assigned to '{}'", name.lexeme);
+        self.expect(&ty, &declared, &what, val.start());
+        Ok(ty)
+    }
+
+    fn visit_binary_expr(&mut self, lhs: &Expr, op: &Token, rhs: &Expr) -> Result<Type, Error> {
+        let lhs = lhs.accept(self)?;
+        let rhs = rhs.accept(self)?;
+        Ok(self.binary(op, &lhs, &rhs))
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        _paren: &Token,
+        arg: &[Expr],
+    ) -> Result<Type, Error> {
+        let ty = callee.accept(self)?;
+        let args = arg
+            .iter()
+            .map(|a| a.accept(self))
+            .collect::<Result<Vec<Type>, Error>>()?;
+        let sig = match callee {
+            Expr::Variable { name } => self.lookup(name).and_then(|s| s.sig.clone()),
+            _ => None,
+        };
+        if let Some(sig) = sig {
+            for (i, ((arg, ty), param)) in arg.iter().zip(&args).zip(&sig.params).enumerate() {
+                self.expect(ty, param, &format!("Argument {}", i + 1), arg.start());
+            }
+            return Ok(sig.ret);
+        }
+        match ty {
+            Type::Any | Type::Fn => Ok(Type::Any),
+            Type::Class(name) => Ok(Type::Instance(name)),
+            ty => {
+                let message = format!("Can only call functions and classes, got {}.", ty);
+                self.error(diagnostic::NOT_CALLABLE, &message, callee.start());
+                Ok(Type::Any)
+            }
+        }
+    }
+
+    fn visit_conditional_expr(
+        &mut self,
+        cond: &Expr,
+        then_: &Expr,
+        else_: &Expr,
+    ) -> Result<Type, Error> {
+        cond.accept(self)?;
+        let then_ = then_.accept(self)?;
+        let else_ = else_.accept(self)?;
+        Ok(if then_ == else_ { then_ } else { Type::Any })
+    }
+
+    fn visit_get_expr(&mut self, obj: &Expr, _name: &Token) -> Result<Type, Error> {
+        obj.accept(self)?;
+        Ok(Type::Any)
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<Type, Error> {
+        expr.accept(self)
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+    ) -> Result<Type, Error> {
+        obj.accept(self)?;
+        index.accept(self)?;
+        Ok(Type::Any)
+    }
+
+    fn visit_index_set_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        _op: &Token,
+        val: &Expr,
+    ) -> Result<Type, Error> {
+        obj.accept(self)?;
+        index.accept(self)?;
+        val.accept(self)
+    }
+
+    fn visit_lambda_expr(
+        &mut self,
+        _keywd: &Token,
+        params: &[Param],
+        ret: &Option<Token>,
+        body: &[Stmt],
+        _close: Span,
+    ) -> Result<Type, Error> {
+        self.function(params, ret, body)?;
+        Ok(Type::Fn)
+    }
+
+    fn visit_list_expr(&mut self, _bracket: &Token, elems: &[Expr]) -> Result<Type, Error> {
+        for elem in elems {
+            elem.accept(self)?;
+        }
+        Ok(Type::List)
+    }
+
+    fn visit_literal_expr(&self, _token: &Token, val: &LiteralValue) -> Result<Type, Error> {
+        Ok(match val {
+            LiteralValue::Number(_) => Type::Number,
+            LiteralValue::String(_) => Type::String,
+            LiteralValue::Boolean(_) => Type::Bool,
+            LiteralValue::Nil => Type::Nil,
+        })
+    }
+
+    fn visit_logical_expr(&mut self, lhs: &Expr, _op: &Token, rhs: &Expr) -> Result<Type, Error> {
+        let lhs = lhs.accept(self)?;
+        let rhs = rhs.accept(self)?;
+        Ok(if lhs == rhs { lhs } else { Type::Any })
+    }
+
+    fn visit_map_expr(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> Result<Type, Error> {
+        for (key, val) in entries {
+            key.accept(self)?;
+            val.accept(self)?;
+        }
+        Ok(Type::Map)
+    }
+
+    fn visit_set_expr(
+        &mut self,
+        obj: &Expr,
+        _name: &Token,
+        _op: &Token,
+        val: &Expr,
+    ) -> Result<Type, Error> {
+        obj.accept(self)?;
+        val.accept(self)
+    }
+
+    fn visit_slice_expr(
+        &mut self,
+        obj: &Expr,
+        _bracket: &Token,
+        from: &Option<Expr>,
+        to: &Option<Expr>,
+    ) -> Result<Type, Error> {
+        let ty = obj.accept(self)?;
+        for bound in from.iter().chain(to.iter()) {
+            let bound_ty = bound.accept(self)?;
+            self.expect(&bound_ty, &Type::Number, "Slice bound", bound.start());
+        }
+        Ok(match ty {
+            Type::List | Type::String => ty,
+            _ => Type::Any,
+        })
+    }
+
+    fn visit_super_expr(&mut self, _keywd: &Token, _method: &Token) -> Result<Type, Error> {
+        Ok(Type::Any)
+    }
+
+    fn visit_this_expr(&mut self, _keywd: &Token) -> Result<Type, Error> {
+        Ok(Type::Any)
+    }
+
+    fn visit_unary_expr(&mut self, op: &Token, rhs: &Expr) -> Result<Type, Error> {
+        let ty = rhs.accept(self)?;
+        if op.t_type == TokenType::Bang {
+            return Ok(Type::Bool);
+        }
+        self.number_operand(op, &ty);
+        Ok(Type::Number)
+    }
+
+    fn visit_update_expr(
+        &mut self,
+        op: &Token,
+        target: &Expr,
+        _prefix: bool,
+    ) -> Result<Type, Error> {
+        let ty = target.accept(self)?;
+        self.number_operand(op, &ty);
+        Ok(Type::Number)
+    }
+
+    fn visit_variable_expr(&mut self, name: &Token) -> Result<Type, Error> {
+        Ok(self.lookup(name).map_or(Type::Any, |s| s.ty.clone()))
+    }
+}
+
+impl stmt_ast::Visitor<()> for TypeChecker {
+    fn visit_assert_stmt(
+        &mut self,
+        _keywd: &Token,
+        cond: &Expr,
+        message: &Option<Expr>,
+    ) -> Result<(), Error> {
+        cond.accept(self)?;
+        if let Some(message) = message {
+            message.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_block_stmt(&mut self, stmts: &[Stmt], _close: Span) -> Result<(), Error> {
+        self.begin_scope();
+        let r = self.stmts(stmts);
+        self.end_scope();
+        r
+    }
+
+    fn visit_break_stmt(&mut self, _keywd: &Token, _label: &Option<Token>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        sclass: &Option<Expr>,
+        fields: &[Stmt],
+        methods: &[Stmt],
+        _close: Span,
+    ) -> Result<(), Error> {
+        self.declare_class(name, sclass);
+        self.check_inheritance(name, sclass);
+        if let Some(sclass) = sclass {
+            sclass.accept(self)?;
+        }
+        for field in fields {
+            if let Stmt::Var { name, ty, init } = field {
+                self.variable(name, ty, init)?;
+            }
+        }
+        for method in methods {
+            if let Stmt::Function {
+                params, ret, body, ..
+            } = method
+            {
+                self.function(params.as_deref().unwrap_or_default(), ret, body)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_continue_stmt(&mut self, _keywd: &Token, _label: &Option<Token>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn visit_export_stmt(&mut self, _keywd: &Token, decl: &Stmt) -> Result<(), Error> {
+        decl.accept(self)
+    }
+
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<(), Error> {
+        expr.accept(self)?;
+        Ok(())
+    }
+
+    fn visit_for_stmt(
+        &mut self,
+        init: &Option<Stmt>,
+        cond: &Option<Expr>,
+        incr: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<(), Error> {
+        self.begin_scope();
+        if let Some(init) = init {
+            init.accept(self)?;
+        }
+        for expr in cond.iter().chain(incr.iter()) {
+            expr.accept(self)?;
+        }
+        body.accept(self)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        name: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+    ) -> Result<(), Error> {
+        iterable.accept(self)?;
+        self.begin_scope();
+        self.declare(name, Type::Any, None);
+        body.accept(self)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        name: &Token,
+        params: &Option<Vec<Param>>,
+        ret: &Option<Token>,
+        body: &[Stmt],
+        _close: Span,
+        _static: bool,
+    ) -> Result<(), Error> {
+        let params = params.as_deref().unwrap_or_default();
+        self.declare_function(name, params, ret, false);
+        self.function(params, ret, body)
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        else_: &Option<Stmt>,
+        then_: &Stmt,
+    ) -> Result<(), Error> {
+        let ty = cond.accept(self)?;
+        self.bound(binding, ty, then_)?;
+        if let Some(else_) = else_ {
+            else_.accept(self)?;
+        }
+        Ok(())
+    }
+
+    // what a module holds isn't known here, so the names it brings in are Any
+    fn visit_import_stmt(
+        &mut self,
+        _keywd: &Token,
+        _path: &Token,
+        alias: &Option<Token>,
+        names: &[Token],
+    ) -> Result<(), Error> {
+        for name in alias.iter().chain(names) {
+            self.declare(name, Type::Any, None);
+        }
+        Ok(())
+    }
+
+    fn visit_labeled_stmt(&mut self, _label: &Token, body: &Stmt) -> Result<(), Error> {
+        body.accept(self)
+    }
+
+    fn visit_match_stmt(
+        &mut self,
+        _keywd: &Token,
+        subject: &Expr,
+        arms: &[Arm],
+        else_: &Option<Stmt>,
+        _close: Span,
+    ) -> Result<(), Error> {
+        let subject = subject.accept(self)?;
+        for arm in arms {
+            self.begin_scope();
+            for pattern in &arm.patterns {
+                match pattern {
+                    Expr::Variable { name } => self.declare(name, subject.clone(), None),
+                    _ => {
+                        pattern.accept(self)?;
+                    }
+                }
+            }
+            if let Some(guard) = &arm.guard {
+                guard.accept(self)?;
+            }
+            arm.body.accept(self)?;
+            self.end_scope();
+        }
+        if let Some(else_) = else_ {
+            else_.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_print_stmt(&mut self, expr: &Expr, rest: &[Expr]) -> Result<(), Error> {
+        expr.accept(self)?;
+        for expr in rest {
+            expr.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_return_stmt(&mut self, keywd: &Token, val: &Option<Expr>) -> Result<(), Error> {
+        let (ty, span) = match val {
+            Some(val) => (val.accept(self)?, val.start()),
+            None => (Type::Nil, keywd.span),
+        };
+        if let Some(expected) = self.returns.last().cloned() {
+            self.expect(&ty, &expected, "Return value", span);
+        }
+        Ok(())
+    }
+
+    fn visit_throw_stmt(&mut self, _keywd: &Token, val: &Expr) -> Result<(), Error> {
+        val.accept(self)?;
+        Ok(())
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        _keywd: &Token,
+        body: &Stmt,
+        catch: &Option<(Token, Stmt)>,
+        finally: &Option<Stmt>,
+    ) -> Result<(), Error> {
+        body.accept(self)?;
+        if let Some((name, body)) = catch {
+            self.begin_scope();
+            self.declare(name, Type::Any, None);
+            body.accept(self)?;
+            self.end_scope();
+        }
+        if let Some(finally) = finally {
+            finally.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        name: &Token,
+        ty: &Option<Token>,
+        init: &Option<Expr>,
+    ) -> Result<(), Error> {
+        let declared = self.variable(name, ty, init)?;
+        // an unannotated variable may hold anything later on
+        self.declare(name, declared, None);
+        Ok(())
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        binding: &Option<Token>,
+        cond: &Expr,
+        body: &Stmt,
+    ) -> Result<(), Error> {
+        let ty = cond.accept(self)?;
+        self.bound(binding, ty, body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::parser::Parser;
+    use crate::frontend::scanner::Scanner;
+
+    fn errors(source: &str) -> Vec<(&'static str, String, usize)> {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        TypeChecker::new()
+            .check(&stmts)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.code, e.message, e.span.start))
+            .collect()
+    }
+
+    #[test]
+    fn unannotated_code_passes() {
+        let source = "var a = 1; a = \"s\"; fn f(x) { return x + 1; } print f(a) - a;";
+        assert_eq!(errors(source), vec![]);
+    }
+
+    #[test]
+    fn annotations_are_checked() {
+        let source = "var n: Number = \"one\";\n\
+                      n = true;\n\
+                      fn add(a: Number, b: Number): Number { return a + b; }\n\
+                      add(1, \"2\");\n\
+                      fn name(): String { return 1; }\n\
+                      var s: String = name();";
+        assert_eq!(
+            errors(source),
+            vec![
+                (
+                    MISMATCH,
+                    "Initializer of 'n' must be Number, got String.".to_string(),
+                    16
+                ),
+                (
+                    MISMATCH,
+                    "Value assigned to 'n' must be Number, got Bool.".to_string(),
+                    27
+                ),
+                (
+                    MISMATCH,
+                    "Argument 2 must be Number, got String.".to_string(),
+                    95
+                ),
+                (
+                    MISMATCH,
+                    "Return value must be String, got Number.".to_string(),
+                    128
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn operands_and_calls() {
+        let source = "var s: String = \"a\"; print -s; print s + 1; print 1 < s; \"f\"();";
+        assert_eq!(
+            errors(source),
+            vec![
+                (
+                    BAD_OPERAND,
+                    "Operand of '-' must be a number, got String.".to_string(),
+                    27
+                ),
+                (
+                    BAD_OPERAND,
+                    "Operands of '+' must be two numbers or two strings, got String and Number."
+                        .to_string(),
+                    39
+                ),
+                (
+                    BAD_OPERAND,
+                    "Operand of '<' must be a number, got String.".to_string(),
+                    52
+                ),
+                (
+                    diagnostic::NOT_CALLABLE,
+                    "Can only call functions and classes, got String.".to_string(),
+                    57
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn match_bindings_have_the_subject_type() {
+        // t is only bound in its own arm
+        let source = "var s: String = \"a\"; match (s) { t if -t => {} 1 => print -t; }";
+        assert_eq!(
+            errors(source),
+            vec![(
+                BAD_OPERAND,
+                "Operand of '-' must be a number, got String.".to_string(),
+                38
+            )]
+        );
+    }
+
+    #[test]
+    fn conditional_bindings_have_the_value_type() {
+        let source = "var s: String = \"a\"; if (var t = s) print -t; print -t;";
+        assert_eq!(
+            errors(source),
+            vec![(
+                BAD_OPERAND,
+                "Operand of '-' must be a number, got String.".to_string(),
+                42
+            )]
+        );
+    }
+
+    #[test]
+    fn classes_are_types() {
+        let source = "fn make(): Point { return Point(); }\n\
+                      class Point {}\n\
+                      var p: Point = make();\n\
+                      var q: Point = 1;\n\
+                      var r: Pointy;";
+        assert_eq!(
+            errors(source),
+            vec![
+                (
+                    MISMATCH,
+                    "Initializer of 'q' must be Point, got Number.".to_string(),
+                    90
+                ),
+                (
+                    diagnostic::UNKNOWN_TYPE,
+                    "Unknown type 'Pointy'.".to_string(),
+                    100
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn field_initializers() {
+        let source = "class P { var x: Number = \"a\"; var y = -x; }";
+        assert_eq!(
+            errors(source),
+            vec![(
+                MISMATCH,
+                "Initializer of 'x' must be Number, got String.".to_string(),
+                26
+            )]
+        );
+    }
+
+    #[test]
+    fn subclasses_and_nil_fit_classes() {
+        let source = "class Animal {} class Dog < Animal {} class Cat < Animal {}\n\
+                      var a: Animal = Dog();\n\
+                      var p: Animal = nil;\n\
+                      var d: Dog = Animal();\n\
+                      var c: Cat = Dog();\n\
+                      var n: Number = nil;";
+        assert_eq!(
+            errors(source),
+            vec![
+                (
+                    MISMATCH,
+                    "Initializer of 'd' must be Dog, got Animal.".to_string(),
+                    117
+                ),
+                (
+                    MISMATCH,
+                    "Initializer of 'c' must be Cat, got Dog.".to_string(),
+                    140
+                ),
+                (
+                    MISMATCH,
+                    "Initializer of 'n' must be Number, got Nil.".to_string(),
+                    163
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn inheritance_cycles() {
+        let source = "class A < A {}\nclass B < C {} class C < B {}";
+        let cycle = diagnostic::INHERITANCE_CYCLE;
+        assert_eq!(
+            errors(source),
+            vec![
+                (cycle, "A class can't inherit from itself.".to_string(), 10),
+                (
+                    cycle,
+                    "Class 'B' inherits from itself through 'C'.".to_string(),
+                    25
+                ),
+                (
+                    cycle,
+                    "Class 'C' inherits from itself through 'B'.".to_string(),
+                    40
+                ),
+            ]
+        );
+        // and checking against them still ends
+        assert_eq!(errors("class A < A {} var a: A = A();").len(), 1);
+    }
+
+    #[test]
+    fn superclasses_are_scoped() {
+        let source = "class Animal {}\n\
+                      fn f() { class Dog < Animal {} }\n\
+                      class Dog {}\n\
+                      var a: Animal = Dog();";
+        assert_eq!(
+            errors(source),
+            vec![(
+                MISMATCH,
+                "Initializer of 'a' must be Animal, got Dog.".to_string(),
+                78
+            )]
+        );
+    }
+
+    #[test]
+    fn declaration_types() {
+        let source = "var a = 1; var b: Any = \"s\"; var c; fn f(x: Bool, y) {}";
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let (_, types) = TypeChecker::new().check_types(&stmts).unwrap();
+        let types: Vec<(usize, String)> = types
+            .into_iter()
+            .map(|(span, ty)| (span.start, ty.to_string()))
+            .collect();
+        let expected = [
+            (4, "Number"),
+            (15, "Any"),
+            (33, "Nil"),
+            (41, "Bool"),
+            (50, "Any"),
+        ];
+        let expected: Vec<(usize, String)> = expected
+            .iter()
+            .map(|(at, ty)| (*at, ty.to_string()))
+            .collect();
+        assert_eq!(types, expected);
+    }
+
+    const MISMATCH: &str = diagnostic::TYPE_MISMATCH;
+    const BAD_OPERAND: &str = diagnostic::BAD_OPERAND;
+}
@@ -0,0 +1,201 @@
+// Syntax highlighting: the category of every token in a source. Keywords, literals,
+// comments and operators come from the scanner. Names are told apart by what the
+// resolver says they are declared as, so a parameter reads differently from a
+// variable and a class from a function. Source that doesn't scan or parse, like a
+// line half typed into the REPL, still gets colored - names then go by the tokens
+// around them. Used by lako-lsp for semantic tokens and by the REPL.
+
+use crate::frontend::diagnostic::{UNTERMINATED_HEREDOC, UNTERMINATED_STRING};
+use crate::frontend::dialect::Dialect;
+use crate::frontend::error::Error;
+use crate::frontend::parser::Parser;
+use crate::frontend::resolver::Resolver;
+use crate::frontend::scanner::Scanner;
+use crate::frontend::token::{Span, Token, TokenType, KEYWORDS};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenCategory {
+    Keyword,
+    String,
+    Number,
+    Comment,
+    Operator,
+    Variable,
+    Parameter,
+    Function,
+    Class,
+    // a name after a dot, and one called after a dot
+    Property,
+    Method,
+}
+
+// The category of each token worth coloring, in source order. Punctuation is left out.
+pub fn highlight(source: &str) -> Vec<(Span, TokenCategory)> {
+    let (tokens, errors) = Scanner::with_trivia(source.to_string()).scan_all();
+    let kinds = if errors.is_empty() {
+        declared_kinds(source, &tokens)
+    } else {
+        HashMap::new()
+    };
+
+    let code: Vec<&Token> = tokens.iter().filter(|t| !t.t_type.is_trivia()).collect();
+    let mut categories = Vec::new();
+    for (i, token) in code.iter().enumerate() {
+        let category = match token.t_type {
+            TokenType::Identifier => Some(name(
+                &kinds,
+                i.checked_sub(1).map(|i| code[i]),
+                token,
+                code.get(i + 1).copied(),
+            )),
+            t_type => category(t_type),
+        };
+        if let Some(category) = category {
+            categories.push((token.span, category));
+        }
+    }
+    let comments = tokens.iter().filter(|t| t.t_type == TokenType::Comment);
+    categories.extend(comments.map(|t| (t.span, TokenCategory::Comment)));
+    // a string still open runs to the end, so color what there is of it
+    for e in &errors {
+        if let Error::Scan { span, code, .. } = e {
+            if *code == UNTERMINATED_STRING || *code == UNTERMINATED_HEREDOC {
+                categories.push((*span, TokenCategory::String));
+            }
+        }
+    }
+    categories.sort_by_key(|(span, _)| span.start);
+    categories
+}
+
+fn category(t_type: TokenType) -> Option<TokenCategory> {
+    match t_type {
+        TokenType::String => Some(TokenCategory::String),
+        TokenType::Number => Some(TokenCategory::Number),
+        TokenType::LeftParen
+        | TokenType::RightParen
+        | TokenType::LeftBrace
+        | TokenType::RightBrace
+        | TokenType::LeftBracket
+        | TokenType::RightBracket
+        | TokenType::Comma
+        | TokenType::Dot
+        | TokenType::Semicolon
+        | TokenType::Whitespace
+        | TokenType::Comment
+        | TokenType::Eof => None,
+        t_type if KEYWORDS.values().any(|k| *k == t_type) => Some(TokenCategory::Keyword),
+        _ => Some(TokenCategory::Operator),
+    }
+}
+
+// A name, by what it resolved to or else by the tokens around it
+fn name(
+    kinds: &HashMap<usize, &str>,
+    prev: Option<&Token>,
+    token: &Token,
+    next: Option<&Token>,
+) -> TokenCategory {
+    let is = |t: Option<&Token>, t_type| t.is_some_and(|t| t.t_type == t_type);
+    if is(prev, TokenType::Dot) {
+        return if is(next, TokenType::LeftParen) {
+            TokenCategory::Method
+        } else {
+            TokenCategory::Property
+        };
+    }
+    match kinds.get(&token.span.start).copied() {
+        Some("parameter") => TokenCategory::Parameter,
+        Some("function") => TokenCategory::Function,
+        Some("class") => TokenCategory::Class,
+        Some(_) => TokenCategory::Variable,
+        None if is(prev, TokenType::Class) => TokenCategory::Class,
+        // method declarations don't resolve, so they land here too
+        None if is(prev, TokenType::Fn) || is(next, TokenType::LeftParen) => {
+            TokenCategory::Function
+        }
+        None => TokenCategory::Variable,
+    }
+}
+
+// What every resolved name and its declaration were declared as, by where they
+// start. Empty if the source doesn't parse.
+fn declared_kinds(source: &str, tokens: &[Token]) -> HashMap<usize, &'static str> {
+    let stmts = Dialect::from_source(source)
+        .and_then(|dialect| Parser::with_dialect(tokens.to_vec(), dialect).parse());
+    let references = match stmts.map(|stmts| Resolver::new().resolve_references(&stmts)) {
+        Ok(Ok((_, references))) => references,
+        _ => return HashMap::new(),
+    };
+    references
+        .iter()
+        .flat_map(|r| vec![(r.span.start, r.kind), (r.declaration.start, r.kind)])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TokenCategory::*;
+
+    fn highlighted(source: &str) -> Vec<(&str, TokenCategory)> {
+        highlight(source)
+            .into_iter()
+            .map(|(span, category)| (&source[span.start..span.end], category))
+            .collect()
+    }
+
+    #[test]
+    fn categories() {
+        let source = "class P { area() { return this.w * 2; } }\n\
+                      fn f(a) { return a + P().area(); } // sum\n\
+                      var s = \"x\"; print f(s) == nil;";
+        assert_eq!(
+            highlighted(source),
+            vec![
+                ("class", Keyword),
+                ("P", Class),
+                ("area", Function),
+                ("return", Keyword),
+                ("this", Keyword),
+                ("w", Property),
+                ("*", Operator),
+                ("2", Number),
+                ("fn", Keyword),
+                ("f", Function),
+                ("a", Parameter),
+                ("return", Keyword),
+                ("a", Parameter),
+                ("+", Operator),
+                ("P", Class),
+                ("area", Method),
+                ("// sum", Comment),
+                ("var", Keyword),
+                ("s", Variable),
+                ("=", Operator),
+                ("\"x\"", String),
+                ("print", Keyword),
+                ("f", Function),
+                ("s", Variable),
+                ("==", Operator),
+                ("nil", Keyword),
+            ]
+        );
+    }
+
+    #[test]
+    fn broken_source() {
+        // doesn't parse, so names go by the tokens around them
+        assert_eq!(
+            highlighted("fn g(x) { x(); \"open"),
+            vec![
+                ("fn", Keyword),
+                ("g", Function),
+                ("x", Variable),
+                ("x", Function),
+                ("\"open", String),
+            ]
+        );
+    }
+}
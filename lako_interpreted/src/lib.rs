@@ -1 +1,258 @@
+// Lako as a library. The items re-exported here are the supported API for embedding
+// Lako and follow semver. The modules are public for the lako binary, benches and
+// tests only - they change as the interpreter grows and are hidden from the docs.
+//
+// The evaluation API (an Engine running programs and the Values they produce) will
+// be added here once the interpreter exists.
+
+#[doc(hidden)]
+pub mod analysis;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
 pub mod frontend;
+#[doc(hidden)]
+pub mod highlight;
+#[doc(hidden)]
+pub mod repl;
+#[doc(hidden)]
+pub mod script_test;
+
+pub use frontend::diagnostic::{Diagnostic, Help, Severity};
+pub use frontend::reporter::{MemoryReporter, Reporter};
+pub use frontend::token::Span;
+pub use highlight::{highlight, TokenCategory};
+
+use config::Config;
+use frontend::cst;
+use frontend::dialect::Dialect;
+use frontend::error::Error;
+use frontend::expr_ast::AstPrinter;
+use frontend::parser::Parser;
+use frontend::resolver::Resolver;
+use frontend::scanner::Scanner;
+use frontend::stack;
+use frontend::stmt_ast::Stmt;
+use std::fmt;
+
+// A parsed source file. Copying, printing and formatting the tree recurse as deep
+// as it is, so they run on a large stack like parse; dropping it takes little.
+pub struct Program {
+    statements: Vec<Stmt>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl Program {
+    // Warnings from the resolver, such as unused variables. They don't stop the
+    // program from running.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    #[doc(hidden)]
+    pub fn statements(&self) -> &[Stmt] {
+        &self.statements
+    }
+}
+
+impl Clone for Program {
+    fn clone(&self) -> Program {
+        stack::run(|| Program {
+            statements: self.statements.clone(),
+            warnings: self.warnings.clone(),
+        })
+    }
+}
+
+// One statement per line in the s-expression form the REPL prints
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printed = stack::run(|| {
+            let mut printer = AstPrinter;
+            let mut printed = String::new();
+            for stmt in &self.statements {
+                printed += &printer.print_stmt(stmt).ok()?;
+                printed.push('\n');
+            }
+            Some(printed)
+        });
+        f.write_str(&printed.ok_or(fmt::Error)?)
+    }
+}
+
+impl fmt::Debug for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // prints what #[derive(Debug)] would
+        #[derive(Debug)]
+        struct Program<'a> {
+            #[allow(dead_code)]
+            statements: &'a [Stmt],
+            #[allow(dead_code)]
+            warnings: &'a [Diagnostic],
+        }
+        let program = Program {
+            statements: &self.statements,
+            warnings: &self.warnings,
+        };
+        let alternate = f.alternate();
+        let printed = stack::run(|| {
+            if alternate {
+                format!("{:#?}", program)
+            } else {
+                format!("{:?}", program)
+            }
+        });
+        f.write_str(&printed)
+    }
+}
+
+// Scans, parses and resolves a whole source file, honoring its pragmas. Every error
+// found is returned, each one ready to be rendered against `source`. It runs on a
+// thread of its own with a large stack, so it is safe to call from any thread.
+pub fn parse(source: &str) -> Result<Program, Vec<Diagnostic>> {
+    stack::run(|| parse_program(source))
+}
+
+fn parse_program(source: &str) -> Result<Program, Vec<Diagnostic>> {
+    let mut reporter = MemoryReporter::new();
+    // frontend errors all have a location, so reporter.messages stays empty
+    let program = parse_with(source, &Config::default(), &mut reporter).and_then(|statements| {
+        match Resolver::new().resolve(&statements) {
+            Ok(diagnostics) => {
+                reporter.report_all(diagnostics.clone());
+                Some(Program {
+                    statements,
+                    warnings: diagnostics,
+                })
+            }
+            Err(e) => {
+                reporter.report_errors(&[e]);
+                None
+            }
+        }
+    });
+    match program {
+        Some(program) if !reporter.had_error() => Ok(program),
+        _ => Err(reporter.diagnostics),
+    }
+}
+
+// Scans and parses a source file, honoring its pragmas and --strict from the config.
+// The errors go to the reporter, None if there were any. Everything that reads
+// source - the lako commands, the REPL and the editor analysis - parses through here.
+// Deep programs need the stack of frontend::stack::run.
+#[doc(hidden)]
+pub fn parse_with(source: &str, config: &Config, reporter: &mut dyn Reporter) -> Option<Vec<Stmt>> {
+    frontend(source, config, reporter, Scanner::new, Parser::parse)
+}
+
+// Like parse_with, but keeps the trivia and also returns the concrete syntax tree
+#[doc(hidden)]
+pub fn parse_cst_with(
+    source: &str,
+    config: &Config,
+    reporter: &mut dyn Reporter,
+) -> Option<(Vec<Stmt>, cst::Node)> {
+    frontend(
+        source,
+        config,
+        reporter,
+        Scanner::with_trivia,
+        Parser::parse_cst,
+    )
+}
+
+fn frontend<T>(
+    source: &str,
+    config: &Config,
+    reporter: &mut dyn Reporter,
+    scanner: fn(String) -> Scanner,
+    parse: fn(&mut Parser) -> Result<T, Vec<Error>>,
+) -> Option<T> {
+    let parsed = Dialect::from_source(source).and_then(|mut dialect| {
+        dialect.strict |= config.strict;
+        let tokens = scanner(source.to_string()).scan_tokens()?;
+        parse(&mut Parser::with_dialect(tokens, dialect))
+    });
+    parsed
+        .map_err(|errors| reporter.report_errors(&errors))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_a_program() {
+        let program = parse("var a = 1;\nprint a + 2;").unwrap();
+        assert_eq!(program.to_string(), "(var a 1)\n(print (+ a 2))\n");
+        assert!(program.warnings().is_empty());
+    }
+
+    #[test]
+    fn parse_returns_warnings() {
+        let program = parse("{ var a = 1; }").unwrap();
+        let warnings = program.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+        assert_eq!(warnings[0].message, "Unused variable 'a'.");
+    }
+
+    #[test]
+    fn parse_reports_diagnostics() {
+        let diagnostics = parse("//! strict\nvar a = 1;\nprint @;").unwrap_err();
+        let codes: Vec<&str> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["E0011"]);
+        assert_eq!(diagnostics[0].span, Span::new(28, 29, 3, 7));
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn parse_on_a_small_stack() {
+        // a spawned thread gets 2MB, far less than the passes take on the deepest trees
+        std::thread::spawn(|| {
+            let nested = format!("print {}1{};", "(".repeat(250), ")".repeat(250));
+            let program = parse(&nested).unwrap();
+            assert!(program.to_string().starts_with("(print (group (group"));
+
+            let chain = format!("print 1{};", "+1".repeat(9_999));
+            let program = parse(&chain).unwrap();
+            assert_eq!(program.clone().to_string(), program.to_string());
+            assert!(format!("{:?}", program).starts_with("Program { statements: [Print"));
+
+            let too_deep = format!("print {}1{};", "(".repeat(300), ")".repeat(300));
+            assert_eq!(
+                parse(&too_deep).unwrap_err()[0].message,
+                "Too deeply nested."
+            );
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn parse_with_a_reporter() {
+        let mut reporter = MemoryReporter::new();
+        let config = Config::default();
+        assert_eq!(
+            parse_with("{ var a = 1; }", &config, &mut reporter)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(!reporter.had_error());
+
+        assert!(parse_with("print ;", &config, &mut reporter).is_none());
+        assert!(reporter.had_error());
+        assert_eq!(reporter.diagnostics[0].message, "Expect expression.");
+
+        // --strict applies as if the file started with the pragma
+        let strict = Config {
+            strict: true,
+            ..Config::default()
+        };
+        assert!(parse_with("var a;", &config, &mut MemoryReporter::new()).is_some());
+        assert!(parse_with("var a;", &strict, &mut MemoryReporter::new()).is_none());
+    }
+}
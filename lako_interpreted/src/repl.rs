@@ -0,0 +1,300 @@
+// Helpers for the interactive prompt in bin/lako.rs.
+
+use crate::config::Config;
+use crate::frontend::diagnostic::{UNTERMINATED_HEREDOC, UNTERMINATED_STRING};
+use crate::frontend::error::Error;
+use crate::frontend::reporter::Reporter;
+use crate::frontend::scanner::Scanner;
+use crate::frontend::stmt_ast::Stmt;
+use crate::frontend::token::{TokenType, KEYWORDS};
+use crate::highlight::{highlight, TokenCategory};
+use crate::parse_with;
+use rustyline::completion::Completer;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::Context;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+// The commands the REPL takes besides code, with what they do for :help
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("help", "show this help"),
+    ("vars", "list the globals declared so far"),
+    ("clear", "forget every input so far"),
+    ("load <file>", "run a script as if it was typed in"),
+    ("save <file>", "write the inputs that ran to a script"),
+];
+
+// Whether the input so far is unfinished and the REPL should read another line:
+// a '(', '[' or '{' is still open, or a string or heredoc is still running.
+// Other errors are left for the parser to report.
+pub fn needs_more_input(source: &str) -> bool {
+    let tokens = match Scanner::new(source.to_string()).scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(errors) => {
+            return errors.iter().any(|e| {
+                matches!(e, Error::Scan { code, .. }
+                    if *code == UNTERMINATED_STRING || *code == UNTERMINATED_HEREDOC)
+            })
+        }
+    };
+
+    let mut depth = 0;
+    for token in &tokens {
+        match token.t_type {
+            TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => depth += 1,
+            TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => depth -= 1,
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+// The names declared at the top level of the statements, sorted
+pub fn globals(stmts: &[Stmt]) -> Vec<String> {
+    let mut names: Vec<String> = stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Var { name, .. } | Stmt::Function { name, .. } | Stmt::Class { name, .. } => {
+                Some(name.lexeme.to_string())
+            }
+            _ => None,
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+// Where the word before pos starts and the words it could be completed to: a
+// command after ':', otherwise a keyword or one of the globals
+pub fn complete(line: &str, pos: usize, globals: &[String]) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let prefix = &line[start..pos];
+    let words: Vec<&str> = match line[..start].trim_start() {
+        ":" => COMMANDS
+            .iter()
+            .filter_map(|(command, _)| command.split(' ').next())
+            .collect(),
+        // arguments of commands, like file names, aren't completed
+        before if before.starts_with(':') => Vec::new(),
+        _ => KEYWORDS
+            .keys()
+            .copied()
+            .chain(globals.iter().map(String::as_str))
+            .collect(),
+    };
+    let mut candidates: Vec<String> = words
+        .into_iter()
+        .filter(|w| w.starts_with(prefix))
+        .map(str::to_string)
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+// The statements of the files :load parsed, by a hash of their source and of --strict,
+// the one flag that changes how a source parses. Loading an unchanged file again
+// skips scanning and parsing it; its statements are still resolved against the
+// session every time, as that changes between loads.
+#[derive(Debug, Default)]
+pub struct ParseCache {
+    parsed: HashMap<u64, (String, Vec<Stmt>)>,
+}
+
+impl ParseCache {
+    pub fn new() -> ParseCache {
+        ParseCache::default()
+    }
+
+    // Like parse_with, from the cache when the source was parsed before
+    pub fn parse(
+        &mut self,
+        source: &str,
+        config: &Config,
+        reporter: &mut dyn Reporter,
+    ) -> Option<Vec<Stmt>> {
+        let mut hasher = DefaultHasher::new();
+        (source, config.strict).hash(&mut hasher);
+        let key = hasher.finish();
+        // the source is compared too, a hash collision only costs a parse
+        if let Some((cached, statements)) = self.parsed.get(&key) {
+            if cached == source {
+                return Some(statements.clone());
+            }
+        }
+        let statements = parse_with(source, config, reporter)?;
+        self.parsed
+            .insert(key, (source.to_string(), statements.clone()));
+        Some(statements)
+    }
+}
+
+// The line editor helper: completes names on tab and, with color on, colors the
+// input as it is typed
+#[derive(Debug, Default)]
+pub struct Helper {
+    pub color: bool,
+    // the globals declared by earlier inputs
+    pub globals: Vec<String>,
+}
+
+impl Highlighter for Helper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if self.color {
+            Cow::Owned(colorize(line))
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+
+    // every key can change how the line colors
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        self.color
+    }
+}
+
+impl Completer for Helper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(line, pos, &self.globals))
+    }
+}
+
+impl Hinter for Helper {
+    type Hint = String;
+}
+
+impl Validator for Helper {}
+
+impl rustyline::Helper for Helper {}
+
+// The source with ANSI colors around its tokens
+pub fn colorize(source: &str) -> String {
+    let mut colored = String::with_capacity(source.len());
+    let mut end = 0;
+    for (span, category) in highlight(source) {
+        colored.push_str(&source[end..span.start]);
+        match color(category) {
+            Some(color) => {
+                colored.push_str(color);
+                colored.push_str(&source[span.start..span.end]);
+                colored.push_str(RESET);
+            }
+            None => colored.push_str(&source[span.start..span.end]),
+        }
+        end = span.end;
+    }
+    colored.push_str(&source[end..]);
+    colored
+}
+
+const RESET: &str = "\x1b[0m";
+
+fn color(category: TokenCategory) -> Option<&'static str> {
+    match category {
+        TokenCategory::Keyword => Some("\x1b[35m"), // magenta
+        TokenCategory::String => Some("\x1b[32m"),  // green
+        TokenCategory::Number => Some("\x1b[36m"),  // cyan
+        TokenCategory::Comment => Some("\x1b[90m"), // gray
+        TokenCategory::Function | TokenCategory::Method => Some("\x1b[34m"), // blue
+        TokenCategory::Class => Some("\x1b[33m"),   // yellow
+        TokenCategory::Parameter => Some("\x1b[3m"), // italic
+        TokenCategory::Operator | TokenCategory::Variable | TokenCategory::Property => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_brackets_need_more_input() {
+        assert!(needs_more_input("fn add(a, b) {\n"));
+        assert!(needs_more_input("fn add(a, b) {\n  return (a +\n"));
+        assert!(needs_more_input("var l = [\n  1,\n"));
+        assert!(!needs_more_input(
+            "fn add(a, b) {\n  return (a +\n b);\n}\n"
+        ));
+        // too many closing brackets is a syntax error, not unfinished input
+        assert!(!needs_more_input("print a);\n"));
+    }
+
+    #[test]
+    fn open_strings_need_more_input() {
+        assert!(needs_more_input("print \"first line\n"));
+        assert!(needs_more_input("var s = <<~END\n  text\n"));
+        assert!(!needs_more_input("print \"{\";\n"));
+        assert!(!needs_more_input("print @;\n"));
+    }
+
+    #[test]
+    fn colors_tokens() {
+        assert_eq!(
+            colorize("print \"hi\" + 1; // x"),
+            "\x1b[35mprint\x1b[0m \x1b[32m\"hi\"\x1b[0m + \x1b[36m1\x1b[0m; \x1b[90m// x\x1b[0m"
+        );
+        assert_eq!(colorize("var a"), "\x1b[35mvar\x1b[0m a");
+    }
+
+    #[test]
+    fn completes_keywords_globals_and_commands() {
+        let globals = vec!["print_all".to_string(), "total".to_string()];
+        assert_eq!(
+            complete("pr", 2, &globals),
+            (0, vec!["print".to_string(), "print_all".to_string()])
+        );
+        assert_eq!(
+            complete("var x = to + 1;", 10, &globals),
+            (8, vec!["total".to_string()])
+        );
+        assert_eq!(complete(":lo", 3, &globals), (1, vec!["load".to_string()]));
+        assert!(complete(":load to", 8, &globals).1.is_empty());
+        assert!(complete("zz", 2, &globals).1.is_empty());
+    }
+
+    #[test]
+    fn parse_cache() {
+        let mut cache = ParseCache::new();
+        let mut reporter = crate::frontend::reporter::MemoryReporter::new();
+        let config = Config::default();
+        let first = cache.parse("var a;", &config, &mut reporter).unwrap();
+        let again = cache.parse("var a;", &config, &mut reporter).unwrap();
+        assert_eq!(first, again);
+        assert_eq!(cache.parsed.len(), 1);
+
+        // --strict parses the same source anew, and errors aren't kept
+        let strict = Config {
+            strict: true,
+            ..Config::default()
+        };
+        assert!(cache.parse("var a;", &strict, &mut reporter).is_none());
+        assert!(reporter.had_error());
+        assert_eq!(cache.parsed.len(), 1);
+    }
+
+    #[test]
+    fn globals_of_earlier_inputs() {
+        let source = "var b = 1; fn a() { var local; } class C {} print b; var b;";
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let stmts = crate::frontend::parser::Parser::new(tokens)
+            .parse()
+            .unwrap();
+        assert_eq!(globals(&stmts), vec!["C", "a", "b"]);
+    }
+}
@@ -0,0 +1,131 @@
+// Golden-file tests for `lako test`: a script carries the output it should produce
+// in comments, in the style of the Lox test suite.
+//
+// print 1 + 2;          // expect: (print (+ 1 2))
+// { var unused = 1; }   // warning[W0201]: Unused variable 'unused'.
+// print ;               // error[E0100]: Expect expression.
+//
+// `// expect:` gives the next line of stdout. A comment starting with `error[` or
+// `warning[` gives the next diagnostic on stderr, as its first line reads without
+// color. The script must exit with 65 if it expects an error and 0 otherwise.
+
+// The exit code for scan, parse and type errors, see the lako binary
+const EX_DATAERR: i32 = 65;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Expected {
+    pub stdout: Vec<String>,
+    pub diagnostics: Vec<String>,
+    pub exit_code: i32,
+}
+
+impl Expected {
+    // Reads the expectations from the comments of a script
+    pub fn parse(source: &str) -> Expected {
+        let mut expected = Expected::default();
+        for line in source.lines() {
+            let comment = match line.find("//") {
+                Some(i) => line[i + 2..].trim(),
+                None => continue,
+            };
+            if let Some(out) = comment.strip_prefix("expect:") {
+                expected.stdout.push(out.trim().to_string());
+            } else if comment.starts_with("error[") || comment.starts_with("warning[") {
+                expected.diagnostics.push(comment.to_string());
+            }
+        }
+        if expected.diagnostics.iter().any(|d| d.starts_with("error[")) {
+            expected.exit_code = EX_DATAERR;
+        }
+        expected
+    }
+
+    // What differs between the expectations and a run, one line per mismatch.
+    // Empty if the run passed.
+    pub fn check(&self, stdout: &str, stderr: &str, exit_code: i32) -> Vec<String> {
+        let mut failures = Vec::new();
+        let stdout: Vec<&str> = stdout.lines().collect();
+        compare("output", &self.stdout, &stdout, &mut failures);
+        compare(
+            "diagnostic",
+            &self.diagnostics,
+            &headlines(stderr),
+            &mut failures,
+        );
+        if exit_code != self.exit_code {
+            failures.push(format!(
+                "Expected exit code {}, got {}.",
+                self.exit_code, exit_code
+            ));
+        }
+        failures
+    }
+}
+
+// The first line of every diagnostic on stderr
+fn headlines(stderr: &str) -> Vec<&str> {
+    stderr
+        .lines()
+        .filter(|line| line.starts_with("error[") || line.starts_with("warning["))
+        .collect()
+}
+
+fn compare(what: &str, expected: &[String], actual: &[&str], failures: &mut Vec<String>) {
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => {}
+            (Some(e), Some(a)) => failures.push(format!("Expected {} '{}', got '{}'.", what, e, a)),
+            (Some(e), None) => failures.push(format!("Missing expected {} '{}'.", what, e)),
+            (None, Some(a)) => failures.push(format!("Unexpected {} '{}'.", what, a)),
+            (None, None) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_expectations() {
+        let source = "print 1; // expect: (print 1)\n\
+                      // a plain comment\n\
+                      { var a; } // warning[W0201]: Unused variable 'a'.\n\
+                      print ; // error[E0100]: Expect expression.\n";
+        assert_eq!(
+            Expected::parse(source),
+            Expected {
+                stdout: vec!["(print 1)".to_string()],
+                diagnostics: vec![
+                    "warning[W0201]: Unused variable 'a'.".to_string(),
+                    "error[E0100]: Expect expression.".to_string(),
+                ],
+                exit_code: 65,
+            }
+        );
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        let expected =
+            Expected::parse("print 1; // expect: (print 1)\nprint 2; // expect: (print 2)");
+        assert!(expected.check("(print 1)\n(print 2)\n", "", 0).is_empty());
+
+        let stderr = "warning[W0201]: Unused variable 'a'.\n --> line 1, col 7\n";
+        assert_eq!(
+            expected.check("(print 1)\n(print 3)\n", stderr, 0),
+            vec![
+                "Expected output '(print 2)', got '(print 3)'.",
+                "Unexpected diagnostic 'warning[W0201]: Unused variable 'a'.'.",
+            ]
+        );
+        assert_eq!(
+            expected.check("", "", 65),
+            vec![
+                "Missing expected output '(print 1)'.",
+                "Missing expected output '(print 2)'.",
+                "Expected exit code 0, got 65.",
+            ]
+        );
+    }
+}
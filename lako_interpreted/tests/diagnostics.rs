@@ -0,0 +1,162 @@
+// Snapshots of what `lako run` and `lako fmt` report for broken programs: the exit
+// code and the rendered diagnostics, so wording, spans and exit codes only change on
+// purpose.
+// After an intended change, review and accept the new snapshots with
+// `cargo insta review`, or rerun with INSTA_UPDATE=always.
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Runs lako on the source given on stdin and returns its exit code and stderr
+fn lako(args: &[&str], source: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lako"))
+        .args([
+            "--color=never",
+            "--error-format=human",
+            "--no-strict",
+            "--no-opt",
+        ])
+        .args(args)
+        .env_remove("LAKO_FLAGS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("lako should run");
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(source.as_bytes())
+        .expect("lako should read the source");
+    let output = child.wait_with_output().expect("lako should finish");
+    format!(
+        "exit code: {}\n{}",
+        output.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+fn run(source: &str) -> String {
+    lako(&["run", "-"], source)
+}
+
+#[test]
+fn scan_errors() {
+    insta::assert_snapshot!("unexpected_character", run("var a = 1;\nprint a @ 2;"));
+    insta::assert_snapshot!("unterminated_string", run("print \"abc;\n"));
+    insta::assert_snapshot!(
+        "malformed_numbers",
+        run("print 0xFG;\nprint 1__000;\nprint 0b;")
+    );
+}
+
+#[test]
+fn parse_errors() {
+    insta::assert_snapshot!(
+        "every_syntax_error",
+        run("var = 1;\nprint ;\nvar ok = 2;\nfn 1() {}\nprint ok")
+    );
+    insta::assert_snapshot!("missing_semicolon", run("var a = 1\nprint a;"));
+    insta::assert_snapshot!("invalid_assignment_target", run("1 + 2 = 3;"));
+    insta::assert_snapshot!("strict_mode", run("//! strict\nvar a;"));
+    insta::assert_snapshot!(
+        "too_deeply_nested",
+        run(&format!("print {}1{};", "(".repeat(300), ")".repeat(300)))
+    );
+}
+
+#[test]
+fn warnings() {
+    insta::assert_snapshot!("unused_variable", run("{ var a = 1; }"));
+    insta::assert_snapshot!(
+        "unreachable_code",
+        run("fn f() {\n  return 1;\n  print 2;\n}\nprint f();")
+    );
+    insta::assert_snapshot!("nil_dereference", run("var a;\nprint a.b;"));
+    insta::assert_snapshot!("calls", run("fn f(a, b) {}\nf(1);\ng();"));
+}
+
+#[test]
+fn lint_levels() {
+    let source = "fn f(a, b) {}\n{ var u; }\nf(1);";
+    insta::assert_snapshot!("deny_unused", lako(&["-D", "unused", "run", "-"], source));
+    insta::assert_snapshot!(
+        "allow_all_but_one",
+        lako(
+            &["--allow", "all", "--warn", "wrong-arity", "run", "-"],
+            source
+        )
+    );
+}
+
+#[test]
+fn type_errors() {
+    insta::assert_snapshot!(
+        "type_errors",
+        lako(
+            &["run", "--check", "-"],
+            "var n: Number = \"one\";\nprint -\"a\";\n\"f\"();\nvar p: Point;"
+        )
+    );
+}
+
+#[test]
+fn class_types() {
+    // a subclass instance and nil fit a class, a superclass instance doesn't
+    insta::assert_snapshot!(
+        "class_types",
+        lako(
+            &["run", "--check", "-"],
+            "class Animal {}\nclass Dog < Animal {}\nvar a: Animal = Dog();\n\
+             var p: Animal = nil;\nvar d: Dog = Animal();"
+        )
+    );
+}
+
+#[test]
+fn fmt_keeps_going() {
+    // the broken file is reported, the one after it still formatted
+    let dir = std::env::temp_dir().join(format!("lako-fmt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (broken, messy) = (dir.join("broken.lako"), dir.join("messy.lako"));
+    std::fs::write(&broken, "print ;\n").unwrap();
+    std::fs::write(&messy, "print   1+2;\n").unwrap();
+    let paths = [broken.to_str().unwrap(), messy.to_str().unwrap()];
+    insta::assert_snapshot!("fmt_keeps_going", lako(&["fmt", paths[0], paths[1]], ""));
+    assert_eq!(std::fs::read_to_string(&messy).unwrap(), "print 1 + 2;\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn imports_need_exports() {
+    // modules are found next to the file importing them
+    let dir = std::env::temp_dir().join(format!("lako-modules-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (utils, main) = (dir.join("utils.lako"), dir.join("main.lako"));
+    std::fs::write(&utils, "export fn clamp(x) { return x; }\nfn helper() {}\n").unwrap();
+    std::fs::write(
+        &main,
+        "import \"utils.lako\" as u;\nfrom \"utils.lako\" import helper;\nprint u.clamp(1) + u.helper();\n",
+    )
+    .unwrap();
+    insta::assert_snapshot!("not_exported", lako(&["check", main.to_str().unwrap()], ""));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn json() {
+    let json = |source| lako(&["--error-format=json", "run", "-"], source);
+    insta::assert_snapshot!("json_error", json("print \"a\tb\" @;"));
+    insta::assert_snapshot!("json_warnings", json("fn f(a, b) {}\nf(1);\n{ var u; }"));
+}
+
+#[test]
+fn unreadable_file() {
+    insta::assert_snapshot!("unreadable_file", lako(&["run", "missing.lako"], ""));
+    insta::assert_snapshot!(
+        "unreadable_file_json",
+        lako(&["--error-format=json", "run", "missing.lako"], "")
+    );
+}
+
+// Runtime errors get their snapshots once programs run
@@ -0,0 +1,17 @@
+// Runs the golden-file scripts in tests/scripts with `lako test`
+use std::process::Command;
+
+#[test]
+fn scripts() {
+    let output = Command::new(env!("CARGO_BIN_EXE_lako"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["test", "tests/scripts"])
+        .output()
+        .expect("lako should run");
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}